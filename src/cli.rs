@@ -1,12 +1,17 @@
 use clap::{Parser, Subcommand, ValueEnum};
 use std::{
-    fs::OpenOptions, io::Read, path::{PathBuf, Path}, fmt,
-    io::Write,
+    fs::OpenOptions, path::{PathBuf, Path}, fmt,
+    io::Read, str::FromStr, time::{SystemTime, UNIX_EPOCH},
 };
-use indexmap::IndexMap;
 use crate::{
-    repository::Repository,
-    object::Object
+    error::{Error, Result},
+    fsck::FsckReport,
+    pathspec::Pathspec,
+    repository::{branch_ref, AddOptions, CheckoutOptions, CommitOptions, LsFilesOptions, Repository, RmOptions},
+    revision::{approxidate, ReflogSelector, Revision},
+    status::UntrackedFiles,
+    object::{self, Object, delta::DeltaOptions},
+    hash::{HashAlgorithm, ObjectId}
 };
 
 /// Minit. A bare bones version control system
@@ -19,7 +24,54 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
-    Add {},
+    /// Add file contents to the index
+    Add {
+        /// Files or directories to add, relative to the current directory
+        #[arg()]
+        pathspec: Vec<String>,
+        /// Also stage removals, and the whole worktree when no pathspec is given
+        #[arg(short='A', long)]
+        all: bool,
+        /// Only update files that are already in the index
+        #[arg(short, long)]
+        update: bool,
+        /// Show what would be added without adding it
+        #[arg(short='n', long)]
+        dry_run: bool,
+        /// Record that new paths will be added later
+        #[arg(short='N', long)]
+        intent_to_add: bool,
+        /// Allow adding otherwise ignored files
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// List, create, delete or rename branches
+    Branch {
+        /// The branch to create, delete or rename, followed by its start point or new name
+        #[arg()]
+        names: Vec<String>,
+        /// Show the commit each branch points at
+        #[arg(short, long)]
+        verbose: bool,
+        /// Delete branches that are merged
+        #[arg(short, long, conflicts_with_all=["rename", "force_rename", "set_upstream_to"])]
+        delete: bool,
+        /// Delete branches even if they are not merged
+        #[arg(short='D', conflicts_with_all=["rename", "force_rename", "set_upstream_to"])]
+        force_delete: bool,
+        /// Rename a branch, or the current one if only the new name is given
+        #[arg(short='m', long="move", conflicts_with="set_upstream_to")]
+        rename: bool,
+        /// Rename a branch even if the new name exists
+        #[arg(short='M', conflicts_with="set_upstream_to")]
+        force_rename: bool,
+        /// Move an existing branch when creating it, or delete or rename regardless
+        #[arg(short, long)]
+        force: bool,
+        /// Make a branch, or the current one, track this upstream branch
+        #[arg(short='u', long, value_name="UPSTREAM")]
+        set_upstream_to: Option<String>,
+    },
     /// Provide contents of repository objects
     CatFile {
         /// The object to display
@@ -29,17 +81,65 @@ pub enum Commands {
         #[arg(default_value_t=Format::Blob)]
         r#type: Format,
     },
-    CheckIgnore {},
-    /// Checkout a commit inside a directory
+    /// Debug ignore files by showing which paths they ignore
+    CheckIgnore {
+        /// The paths to check, relative to the current directory
+        #[arg(required=true)]
+        pathname: Vec<String>,
+        /// Show the file, line and pattern that matched each path
+        #[arg(short, long)]
+        verbose: bool,
+        /// With --verbose, also list paths that no rule matched
+        #[arg(short, long, requires="verbose")]
+        non_matching: bool,
+        /// Check tracked files too, as if the index were empty
+        #[arg(long)]
+        no_index: bool,
+    },
+    /// Switch the worktree to a branch or commit, or check a commit out into a directory
     Checkout {
-        /// The commit/tree to checkout on
-        #[arg()]
-        commit: String, 
-        /// The **empty** directory to checkout on
-        #[arg()]
-        directory: String, 
+        /// The branch or commit to switch to, or to start the new branch at
+        #[arg(required_unless_present="new_branch")]
+        target: Option<String>,
+        /// An **empty** directory to check the commit out into instead
+        #[arg(conflicts_with_all=["new_branch", "force", "detach"])]
+        directory: Option<String>,
+        /// Create a branch at the target and switch to it
+        #[arg(short='b')]
+        new_branch: Option<String>,
+        /// Throw away local changes instead of refusing to switch
+        #[arg(short, long)]
+        force: bool,
+        /// Detach HEAD at the target even if it is a branch
+        #[arg(long)]
+        detach: bool,
+    },
+    /// Record the staged changes as a new commit
+    Commit {
+        /// The commit message; several are joined as separate paragraphs
+        #[arg(short, long)]
+        message: Vec<String>,
+        /// Read the commit message from a file, or standard input for `-`
+        #[arg(short='F', long, conflicts_with="message")]
+        file: Option<String>,
+        /// Replace the tip of the current branch
+        #[arg(long)]
+        amend: bool,
+        /// Allow a commit that changes nothing
+        #[arg(long)]
+        allow_empty: bool,
+        /// Stage changes and deletions of tracked files first
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Verify the connectivity and validity of the objects in the database
+    Fsck {},
+    /// Pack the repository and optionally prune unreachable objects
+    Gc {
+        /// Delete loose objects that are not reachable from any ref
+        #[arg(long)]
+        prune: bool,
     },
-    Commit {},
     /// Compute the object ID and optionally create a blob from a file
     HashObject {
         /// Flag to write the object to the database
@@ -56,7 +156,10 @@ pub enum Commands {
     Init {
         /// Where to create the repository
         #[arg(default_value_t=String::from("."))]
-        path: String
+        path: String,
+        /// The hash algorithm used to name objects
+        #[arg(long, value_enum)]
+        object_format: Option<HashAlgorithm>,
     },
     /// Commit history 
     Log {
@@ -64,7 +167,36 @@ pub enum Commands {
         #[arg(default_value_t=String::from("HEAD"))]
         commit: String
     },
-    LsFile {},
+    /// Show information about files in the index and the worktree
+    LsFiles {
+        /// Only list paths matching these, relative to the current directory
+        #[arg()]
+        pathspec: Vec<String>,
+        /// Show staged files (the default)
+        #[arg(short, long)]
+        cached: bool,
+        /// Show the mode, object name and stage of staged entries
+        #[arg(short, long)]
+        stage: bool,
+        /// Show staged files that are missing from the worktree
+        #[arg(short, long)]
+        deleted: bool,
+        /// Show staged files that differ in the worktree
+        #[arg(short, long)]
+        modified: bool,
+        /// Show untracked files
+        #[arg(short, long)]
+        others: bool,
+        /// Leave out files ignored by the standard ignore files
+        #[arg(long)]
+        exclude_standard: bool,
+        /// Show unmerged entries only, in the --stage format
+        #[arg(short, long)]
+        unmerged: bool,
+        /// End each line with NUL instead of a newline
+        #[arg(short)]
+        z: bool,
+    },
     /// Print a tree object
     LsTree {
         /// Recurse into subtrees
@@ -74,19 +206,97 @@ pub enum Commands {
         #[arg()]
         tree: String, 
     },
+    /// Move or rename a file or directory in the worktree and the index
+    Mv {
+        /// The tracked file or directory to move
+        #[arg()]
+        source: String,
+        /// The new name, or an existing directory to move into
+        #[arg()]
+        destination: String,
+    },
+    /// Move loose refs into packed-refs
+    PackRefs {
+        /// Pack every ref, not only tags and refs that are already packed
+        #[arg(long)]
+        all: bool,
+        /// Remove the loose refs once they are packed (the default)
+        #[arg(long, overrides_with = "no_prune")]
+        prune: bool,
+        /// Keep the loose refs
+        #[arg(long)]
+        no_prune: bool,
+    },
+    /// Show or prune the log of updates to a ref
+    Reflog {
+        #[command(subcommand)]
+        action: Option<ReflogAction>,
+    },
+    /// Pack reachable objects and delete their loose copies
+    Repack {
+        /// How many neighbouring objects to try as delta bases (0 disables deltas)
+        #[arg(long, default_value_t=10)]
+        window: usize,
+        /// The longest chain of deltas to build
+        #[arg(long, default_value_t=50)]
+        depth: usize,
+    },
     /// Retrieve the 
     RevParse {
         /// The expected type
         #[arg(short)]
         r#type: Option<Format>,
-        /// The name to parse
+        /// The revision to resolve, in gitrevisions(7) syntax
         #[arg(short)]
         name: String,
     },
-    Rm {},
+    /// Remove files from the worktree and the index
+    Rm {
+        /// Files to remove, relative to the current directory
+        #[arg(required=true)]
+        pathspec: Vec<String>,
+        /// Only remove from the index, keeping the worktree files
+        #[arg(long)]
+        cached: bool,
+        /// Allow recursive removal when a directory is given
+        #[arg(short)]
+        recursive: bool,
+        /// Remove even if the files have staged or local changes
+        #[arg(short, long)]
+        force: bool,
+    },
     /// List references
     ShowRef {},
-    Status {},
+    /// Show the working tree status
+    Status {
+        /// Give the output in the short format
+        #[arg(short, long)]
+        short: bool,
+        /// Give the output in a stable format for scripts
+        #[arg(long, value_enum, num_args=0..=1, default_missing_value="v1", require_equals=true)]
+        porcelain: Option<PorcelainVersion>,
+        /// Show the branch in the short and porcelain formats
+        #[arg(short, long)]
+        branch: bool,
+        /// Which untracked files to show
+        #[arg(short='u', long, value_enum, default_value_t, num_args=0..=1, default_missing_value="all")]
+        untracked_files: UntrackedFiles,
+    },
+    /// Switch the worktree to a branch
+    Switch {
+        /// The branch to switch to, or the commit to start at with --create or --detach
+        #[arg(required_unless_present="create")]
+        branch: Option<String>,
+        /// Create a branch and switch to it
+        #[arg(short, long)]
+        create: Option<String>,
+        /// Switch to a commit without being on a branch
+        #[arg(short, long)]
+        detach: bool,
+        /// Throw away local changes instead of refusing to switch
+        #[arg(short, long, alias="discard-changes")]
+        force: bool,
+    },
     /// Create a tag
     Tag {
         /// Whether to create a tag object
@@ -103,8 +313,35 @@ pub enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum ReflogAction {
+    /// List the entries of a reflog, newest first
+    Show {
+        /// The ref whose log to show
+        #[arg(default_value_t=String::from("HEAD"))]
+        name: String,
+    },
+    /// Drop entries older than a date
+    Expire {
+        /// The cutoff, such as `90.days.ago`, `2024-01-01`, `now` or `all`; `never` keeps everything
+        #[arg(long, default_value_t=String::from("90.days.ago"), require_equals=true)]
+        expire: String,
+        /// Expire the log of every ref
+        #[arg(long)]
+        all: bool,
+        /// The refs whose logs to expire
+        #[arg(required_unless_present="all")]
+        names: Vec<String>,
+    },
+    /// Delete single entries, given as `<ref>@{<n>}`
+    Delete {
+        #[arg(required=true)]
+        entries: Vec<String>,
+    },
+}
+
 #[derive(Debug, Clone, ValueEnum)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq)]
 pub enum Format {
     Blob,
     Tree,
@@ -112,6 +349,14 @@ pub enum Format {
     Commit
 }
 
+/// The versions of `status --porcelain`
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[derive(PartialEq, Eq)]
+pub enum PorcelainVersion {
+    V1,
+    V2,
+}
+
 impl fmt::Display for Format {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
@@ -123,98 +368,300 @@ impl fmt::Display for Format {
     }
 }
 
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "blob" => Ok(Format::Blob),
+            "tree" => Ok(Format::Tree),
+            "tag" => Ok(Format::Tag),
+            "commit" => Ok(Format::Commit),
+            other => Err(Error::MalformedObject(format!("unknown object type {}", other))),
+        }
+    }
+}
+
 
-pub fn init(path: &Path) {
-    match Repository::create(path) {
+pub fn init(path: &Path, object_format: Option<HashAlgorithm>) {
+    match Repository::create(path, object_format) {
         Ok(..) => {},
-        Err(err) => print!("Error: {:#?}\n", err)
+        Err(err) => println!("Error: {:#?}", err)
     }
 }
 
-// - If we have a tag and fmt is anything else, we follow the tag.
-// - If we have a commit and fmt is tree, we return this commit’s tree object
-// - In all other situations, we bail out: nothing else makes sense.
-pub fn checkout(commit: &str, directory: &str, path: Option<&str>) {
+/// Switch the worktree to `target` and say where HEAD ended up, as git does
+pub fn checkout(target: &str, options: &CheckoutOptions, path: Option<&str>) -> Result<String> {
     let path = path.as_ref().map_or(".", |p| p);
-    let repo = Repository::find(&Path::new(path), true).unwrap().unwrap();
-    let mut object = repo.read_object(&repo.find_object(commit, Some(Format::Commit), false).unwrap()).unwrap();
-    match object {
-        Object::Blob(_) => panic!("commit is not a tree or commit: {}", commit),
-        Object::Commit(map) => {
-            let tree = map.get("tree").unwrap()[0].as_str();
-            object = repo.read_object(tree).unwrap();
-        },
-        _ => {}
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let previous = repo.head_ref()?;
+    Ok(match repo.checkout(target, options)? {
+        Some(branch) if options.new_branch.is_some() => format!("Switched to a new branch '{}'\n", branch),
+        Some(branch) if previous == Some(format!("refs/heads/{}", branch)) => format!("Already on '{}'\n", branch),
+        Some(branch) => format!("Switched to branch '{}'\n", branch),
+        None => format!("HEAD is now at {}\n", summary(&repo, &repo.resolve_ref("HEAD")?)?),
+    })
+}
+
+/// A commit as `<short id> <subject>`
+fn summary(repo: &Repository, sha: &ObjectId) -> Result<String> {
+    let Object::Commit(commit) = repo.read_object(sha)? else {
+        return Err(Error::WrongObjectType(*sha, Format::Commit, repo.read_raw(sha)?.0));
+    };
+    let subject = commit.message.as_deref().unwrap_or_default().split(|&c| c == b'\n').next().unwrap_or_default();
+    Ok(format!("{} {}", &sha.to_hex()[0..7], String::from_utf8_lossy(subject)))
+}
+
+/// List the branches, marking the current one with `*`, and with `verbose` the commit
+/// each one points at
+pub fn list_branches(verbose: bool, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let head = repo.head_ref()?;
+    let mut lines: Vec<(String, bool, ObjectId)> = Vec::new();
+    if head.is_none() {
+        let sha = repo.resolve_ref("HEAD")?;
+        lines.push((format!("(HEAD detached at {})", &sha.to_hex()[0..7]), true, sha));
     }
-    _ = object;
-    let target = Path::new(directory);
-    if target.exists() {
-        if !target.is_dir() {
-            panic!("directory {} must be a directory", directory);
-        } 
-        if target.read_dir().unwrap().count() > 0 {
-            panic!("path {} is non-empty", directory);
+    for (name, sha) in repo.branches()? {
+        let current = head.as_deref() == Some(format!("refs/heads/{}", name).as_str());
+        lines.push((name, current, sha));
+    }
+    let width = lines.iter().map(|(name, _, _)| name.chars().count()).max().unwrap_or(0);
+    let mut output = String::new();
+    for (name, current, sha) in lines {
+        let marker = if current { '*' } else { ' ' };
+        match verbose {
+            true => output.push_str(&format!("{} {:<width$} {}\n", marker, name, summary(&repo, &sha)?, width = width)),
+            false => output.push_str(&format!("{} {}\n", marker, name)),
         }
     }
+    Ok(output)
+}
+
+/// Create the branch `names[0]` at `names[1]`, or at HEAD
+pub fn create_branch(names: &[String], force: bool, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let (name, start_point) = match names {
+        [name] => (name, "HEAD"),
+        [name, start_point] => (name, start_point.as_str()),
+        _ => return Err(Error::Refused(String::from("too many arguments to create a branch"))),
+    };
+    repo.create_branch(name, start_point, force)?;
+    Ok(String::new())
+}
+
+/// Delete each of `names`, saying where each one pointed
+pub fn delete_branches(names: &[String], force: bool, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    if names.is_empty() {
+        return Err(Error::Refused(String::from("branch name required")));
+    }
+    let mut output = String::new();
+    for name in names {
+        let sha = repo.delete_branch(name, force)?;
+        output.push_str(&format!("Deleted branch {} (was {}).\n", name, &sha.to_hex()[0..7]));
+    }
+    Ok(output)
+}
+
+/// Rename `names[0]` to `names[1]`, or the current branch to `names[0]`
+pub fn rename_branch(names: &[String], force: bool, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let current = repo.head_ref()?.map(|head| head.trim_start_matches("refs/heads/").to_string());
+    let (old, new) = match (names, current) {
+        ([new], Some(current)) => (current, new),
+        ([new], None) => return Err(Error::Refused(format!("cannot rename the current branch to '{}' while not on any", new))),
+        ([old, new], _) => (old.clone(), new),
+        _ => return Err(Error::Refused(String::from("too many arguments for a rename operation"))),
+    };
+    repo.rename_branch(&old, new, force)?;
+    Ok(String::new())
+}
+
+/// Make `branch`, or the current branch, track `upstream`
+pub fn set_upstream(branch: Option<&str>, upstream: &str, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => repo.head_ref()?
+            .map(|head| head.trim_start_matches("refs/heads/").to_string())
+            .ok_or_else(|| Error::Refused(String::from("could not set upstream of HEAD when it does not point to any branch")))?,
+    };
+    repo.set_upstream(&branch, upstream)?;
+    Ok(format!("branch '{}' set up to track '{}'.\n", branch, upstream))
+}
+
+/// The full name of the ref whose reflog `name` means, as `HEAD`, `refs/...` or a name
+/// under `refs/heads`, `refs/tags` or `refs/remotes`
+fn reflog_ref(repo: &Repository, name: &str) -> Result<String> {
+    match name {
+        "HEAD" => Ok(String::from("HEAD")),
+        name if name.starts_with("refs/") => Ok(name.to_string()),
+        name => repo.dwim_ref(name).ok_or_else(|| Error::Refused(format!("no such ref '{}'", name))),
+    }
+}
+
+/// List the reflog of `name` as `<sha> <name>@{<n>}: <message>`, newest first
+pub fn reflog_show(name: &str, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let entries = repo.reflog(&reflog_ref(&repo, name)?)?;
+    Ok(entries.iter()
+        .rev()
+        .enumerate()
+        .map(|(i, entry)| format!("{} {}@{{{}}}: {}\n", &entry.new.to_hex()[0..7], name, i, entry.message))
+        .collect())
+}
+
+/// Drop the entries older than `expire` from the reflogs of `names`, or of every ref
+pub fn reflog_expire(expire: &str, all: bool, names: &[String], path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    let before = match expire {
+        "all" => i64::MAX,
+        "never" | "false" => i64::MIN,
+        expire => approxidate(expire, now).ok_or_else(|| Error::Refused(format!("invalid timestamp '{}' given to '--expire'", expire)))?,
+    };
+    let names = match all {
+        true => repo.reflogs()?,
+        false => names.iter().map(|name| reflog_ref(&repo, name)).collect::<Result<Vec<String>>>()?,
+    };
+    for name in names {
+        repo.expire_reflog(&name, before)?;
+    }
+    Ok(String::new())
+}
+
+/// Delete the reflog entries `entries`, each given as `<ref>@{<n>}`
+pub fn reflog_delete(entries: &[String], path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    // Deleted from the highest index down so that each one still means the same entry
+    let mut targets = Vec::new();
+    for entry in entries {
+        let Revision::Reflog(name, ReflogSelector::Nth(n)) = Revision::parse(entry)? else {
+            return Err(Error::Refused(format!("not a reflog entry: '{}'", entry)));
+        };
+        let name = match name.as_str() {
+            "" => repo.head_ref()?.unwrap_or_else(|| String::from("HEAD")),
+            name => reflog_ref(&repo, name)?,
+        };
+        targets.push((name, n));
+    }
+    targets.sort_by(|a, b| b.cmp(a));
+    targets.dedup();
+    for (name, n) in targets {
+        repo.delete_reflog_entry(&name, n)?;
+    }
+    Ok(String::new())
+}
 
-    checkout_tree(&repo, object, Path::new(directory).to_path_buf());
-}
-
-pub fn checkout_tree(repo: &Repository, object: Object, directory: PathBuf) {
-    // if blob, make file
-    // if tree, mkdir and recurse
-    if let Object::Tree(leaves) = object {
-        leaves.iter().for_each(|leaf| {
-            let object = repo.read_object(&leaf.sha).unwrap();
-            let dest = directory.join(Path::new(&leaf.path));
-            match object {
-                Object::Tree(_) => {
-                    std::fs::create_dir(&dest).unwrap();
-                    checkout_tree(repo, object, dest);
-                },
-                Object::Blob(data) => {
-                    // NOTE: handle symlinks
-                    let mut file = OpenOptions::new().write(true).open(dest).unwrap();
-                    _ = file.write(&data[..]);
-                },
-                _ => panic!("The object in the tree is neither a blob nor a tree.")
-            } 
-
-        }); 
-    } else {
-        panic!("Object is not a tree");
+/// Like `checkout`, but without `--detach` only a branch can be switched to
+pub fn switch(branch: Option<&str>, create: Option<&str>, detach: bool, force: bool, path: Option<&str>) -> Result<String> {
+    let target = branch.unwrap_or("HEAD");
+    if create.is_none() && !detach {
+        let repo = match Repository::find(Path::new(path.unwrap_or(".")), true) {
+            Err(err) => panic!("{:#?}", err),
+            Ok(Some(repo)) => repo,
+            Ok(None) => unreachable!(),
+        };
+        if !branch_ref(target).is_ok_and(|name| repo.ref_exists(&name)) {
+            return Err(Error::Refused(format!("a branch is expected, got '{}'; use --detach to switch to a commit", target)));
+        }
     }
+    let options = CheckoutOptions { force, new_branch: create.map(String::from), detach };
+    checkout(target, &options, path)
+}
+
+/// Check out the tree of `treeish`, a commit or a tree, into the empty or missing `directory`
+pub fn checkout_into(treeish: &str, directory: &str, path: Option<&str>) -> Result<()> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let tree = repo.find_object(treeish, Some(Format::Tree), true)?;
+    repo.checkout_tree(&tree, Path::new(directory))
 }
 
 pub fn cat_file(fmt: Format, object: &str, path: Option<&str>) -> String {
     let path = path.as_ref().map_or(".", |p| p);
-    let repo = match Repository::find(&Path::new(path), true) {
+    let repo = match Repository::find(Path::new(path), true) {
         Err(err) => panic!("{:#?}", err),
         Ok(Some(repo)) => repo,
         Ok(None) => unreachable!(),
     };
     // TODO: refactor below two errors
-    let object = repo.read_object(&repo.find_object(&object, Some(fmt), true).unwrap()).unwrap();
+    let object = repo.read_object(&repo.find_object(object, Some(fmt), true).unwrap()).unwrap();
     String::from_utf8(object.serialize().unwrap().clone()).unwrap()
 }
 
-pub fn hash_object(fmt: Format, write: bool, path: &str) -> String {
+pub fn hash_object(fmt: Format, write: bool, path: &str) -> Result<ObjectId> {
     let path = Path::new(&path);
-    let mut file = OpenOptions::new().read(true).open(path).unwrap();
-    let mut buf: Vec<u8> = Vec::new();
-    file.read_to_end(&mut buf).unwrap();
-    let object = match fmt {
-        Format::Blob => Object::new(Format::Blob, buf),
-        Format::Tag | Format::Tree | Format::Commit => unimplemented!(),
-    }.unwrap();
-    if write {
-        // TODO: handle unwraps
-        let repo = Repository::find(path, true).unwrap().unwrap();
-        let sha = repo.write_object(object).unwrap();
-        sha
-    } else {
-        let (sha, _) = object.write().unwrap();
-        sha
+    let mut file = OpenOptions::new().read(true).open(path)?;
+    let size = file.metadata()?.len();
+    let worktree = path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let repo = Repository::find(worktree, write)?;
+    let algorithm = repo.as_ref().map_or(HashAlgorithm::default(), |repo| repo.algorithm());
+    if fmt == Format::Blob {
+        return match repo {
+            Some(repo) if write => repo.write_stream(fmt, size, file),
+            _ => object::hash_stream(&fmt, size, file, algorithm),
+        };
+    }
+    // Trees, commits and tags are parsed first so a malformed one is never stored,
+    // then hashed as the bytes given
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Object::new(fmt.clone(), data.clone(), algorithm)?;
+    match repo {
+        Some(repo) if write => repo.write_stream(fmt, data.len() as u64, &data[..]),
+        _ => object::hash_stream(&fmt, data.len() as u64, &data[..], algorithm),
     }
 }
 
@@ -224,46 +671,45 @@ pub fn hash_object(fmt: Format, write: bool, path: &str) -> String {
 // If there is no parent, return
 pub fn log(commit: &str, path: Option<&str>) -> String {
     let path = path.as_ref().map_or(".", |p| p);
-    let repo = match Repository::find(&Path::new(path), true) {
+    let repo = match Repository::find(Path::new(path), true) {
         Err(err) => panic!("{:#?}", err),
         Ok(Some(repo)) => repo,
         Ok(None) => unreachable!(),
     };
     // TODO: throw error when commit is *not* a commit
-    let object = repo.read_object(&repo.find_object(&commit, Some(Format::Commit), true).unwrap()).unwrap();
+    let object = repo.read_object(&repo.find_object(commit, Some(Format::Commit), true).unwrap()).unwrap();
     String::from_utf8(object.serialize().unwrap().clone()).unwrap()
     
 }
 
-pub fn ls_tree(recursive: bool, tree: &str, path: Option<String>, prefix: PathBuf) {
+pub fn ls_tree(recursive: bool, tree: &str, path: Option<String>, prefix: PathBuf) -> Result<()> {
     let path = path.as_ref().map_or(".", |p| p);
-    let repo = match Repository::find(&Path::new(path), true) {
+    let repo = match Repository::find(Path::new(path), true) {
         Err(err) => panic!("{:#?}", err),
         Ok(Some(repo)) => repo,
         Ok(None) => unreachable!(),
     };
-    let sha = repo.find_object(&tree, Some(Format::Tree), false).unwrap();
-    let object = repo.read_object(&sha).unwrap();
-    match object {
+    let sha = repo.find_object(tree, Some(Format::Tree), false)?;
+    match repo.read_object(&sha)? {
         Object::Tree(items) => {
-            items.into_iter().for_each(|leaf| {
-                let object_type = leaf.get_type();
+            for leaf in items {
+                let object_type = leaf.get_type()?;
+                let name = String::from_utf8_lossy(&leaf.path).into_owned();
                 if !recursive && object_type == tree {
-                    println!("{} {} {}\t{}", leaf.mode, object_type, leaf.sha, prefix.join(&leaf.path).to_str().unwrap());
+                    println!("{} {} {}\t{}", leaf.mode, object_type, leaf.sha, prefix.join(&name).display());
                 } else {
-                    ls_tree(recursive, tree, Some(path.to_string()), prefix.join(&leaf.path))
+                    ls_tree(recursive, tree, Some(path.to_string()), prefix.join(&name))?;
                 }
-            });
+            }
+            Ok(())
         },
-        _ => {
-            panic!("[tree] arg {} is not a tree", &tree);
-        }
+        object => Err(Error::WrongObjectType(sha, Format::Tree, object.format())),
     }
 }
 
 pub fn show_ref(path: Option<String>) {
     let path = path.as_ref().map_or(".", |p| p);
-    let repo = match Repository::find(&Path::new(path), true) {
+    let repo = match Repository::find(Path::new(path), true) {
         Err(err) => panic!("{:#?}", err),
         Ok(Some(repo)) => repo,
         Ok(None) => unreachable!(),
@@ -272,30 +718,248 @@ pub fn show_ref(path: Option<String>) {
     map.iter().for_each(|(k, v)| println!("{} {}", k, v));
 }
 
-pub fn rev_parse(fmt: Option<Format>, name: String, path: Option<String>) -> String {
+pub fn rev_parse(fmt: Option<Format>, name: String, path: Option<String>) -> Result<ObjectId> {
     let path = path.as_ref().map_or(".", |p| p);
-    let repo = match Repository::find(&Path::new(path), true) {
+    let repo = match Repository::find(Path::new(path), true) {
         Err(err) => panic!("{:#?}", err),
         Ok(Some(repo)) => repo,
         Ok(None) => unreachable!(),
     };
-    repo.find_object(&name, fmt, true).unwrap()
+    repo.find_object(&name, fmt, true)
 }
 
-pub fn tag(add: bool, name: Option<String>, object: String, path: Option<String>) {
+pub fn tag(add: bool, name: Option<String>, object: String, path: Option<String>) -> Result<String> {
     let path = path.as_ref().map_or(".", |p| p);
-    let repo = match Repository::find(&Path::new(path), true) {
+    let repo = match Repository::find(Path::new(path), true) {
         Err(err) => panic!("{:#?}", err),
         Ok(Some(repo)) => repo,
         Ok(None) => unreachable!(),
     };
     if let Some(name) = name {
-        let _ = repo.create_tag(&name, &object, add);
-    } else {
-        repo.ls_ref(None)
-            .unwrap()
-            .into_iter()
-            .filter(|(k, v)| k.starts_with("tags"))
-            .for_each(|(k, v)| println!("{} {}", k, v));
+        repo.create_tag(&name, &object, add)?;
+        return Ok(String::new());
+    }
+    Ok(repo.ls_ref(None)?
+        .into_iter()
+        .filter(|(k, _)| k.starts_with("refs/tags/"))
+        .map(|(k, v)| format!("{} {}\n", k, v))
+        .collect())
+}
+
+pub fn pack_refs(all: bool, prune: bool, path: Option<String>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    repo.pack_refs(all, prune)?;
+    Ok(String::new())
+}
+
+pub fn add(pathspec: &[String], options: &AddOptions, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    if pathspec.is_empty() && !options.all && !options.update {
+        return Ok(String::from("Nothing specified, nothing added.\n"));
+    }
+    let pathspec = Pathspec::new(repo.worktree(), &std::env::current_dir()?, pathspec)?;
+    let changes = repo.add(&pathspec, options)?;
+    if !options.dry_run {
+        return Ok(String::new());
+    }
+    Ok(changes.iter().map(|change| format!("{}\n", change)).collect())
+}
+
+pub fn rm(pathspec: &[String], options: &RmOptions, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let pathspec = Pathspec::new(repo.worktree(), &std::env::current_dir()?, pathspec)?;
+    let removed = repo.rm(&pathspec, options)?;
+    Ok(removed.iter().map(|path| format!("rm '{}'\n", path)).collect())
+}
+
+pub fn mv(source: &str, destination: &str, path: Option<&str>) -> Result<()> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let paths = Pathspec::new(repo.worktree(), &std::env::current_dir()?, &[source.to_string(), destination.to_string()])?;
+    repo.mv(&paths.patterns()[0], &paths.patterns()[1])?;
+    Ok(())
+}
+
+/// Commit with the message from `-m`, `-F` or an editor, and describe the new commit
+/// as `[master 1a2b3c4] Subject`
+pub fn commit(messages: &[String], file: Option<&str>, options: &CommitOptions, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let message = match file {
+        Some("-") => {
+            let mut message = Vec::new();
+            std::io::stdin().read_to_end(&mut message)?;
+            Some(object::commit::cleanup_message(&message, false))
+        },
+        Some(file) => Some(object::commit::cleanup_message(&std::fs::read(file)?, false)),
+        None if !messages.is_empty() => Some(object::commit::cleanup_message(messages.join("\n\n").as_bytes(), false)),
+        None => Some(edit_message(&repo, options.amend)?),
+    };
+    let root = repo.resolve_ref("HEAD").is_err();
+    let sha = repo.commit(message, options)?;
+    let Object::Commit(commit) = repo.read_object(&sha)? else { unreachable!() };
+    let branch = match repo.head_ref()? {
+        Some(target) => target.trim_start_matches("refs/heads/").to_string(),
+        None => String::from("detached HEAD"),
+    };
+    let subject = commit.message.as_deref().unwrap_or_default().split(|&c| c == b'\n').next().unwrap_or_default();
+    Ok(format!(
+        "[{}{} {}] {}\n",
+        branch,
+        if root { " (root-commit)" } else { "" },
+        &sha.to_hex()[0..7],
+        String::from_utf8_lossy(subject),
+    ))
+}
+
+/// Open `$MINIT_EDITOR`, `$VISUAL` or `$EDITOR` on `.minit/COMMIT_EDITMSG`, starting from
+/// the message being amended, and return what was written without the comments
+fn edit_message(repo: &Repository, amend: bool) -> Result<Vec<u8>> {
+    let mut template = Vec::new();
+    if amend && let Ok(head) = repo.resolve_ref("HEAD") && let Object::Commit(commit) = repo.read_object(&head)? {
+        template.extend(commit.message.unwrap_or_default());
+    }
+    template.extend_from_slice(b"\n# Please enter the commit message for your changes. Lines starting\n# with '#' will be ignored, and an empty message aborts the commit.\n");
+    let path = repo.repo_path(vec!["COMMIT_EDITMSG"]);
+    std::fs::write(&path, template)?;
+    let editor = ["MINIT_EDITOR", "VISUAL", "EDITOR"].iter()
+        .find_map(|name| std::env::var(name).ok().filter(|editor| !editor.is_empty()))
+        .unwrap_or_else(|| String::from("vi"));
+    // Through the shell, so that editors given with arguments work
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(&path)
+        .status()?;
+    if !status.success() {
+        return Err(Error::Refused(format!("there was a problem with the editor '{}'", editor)));
+    }
+    Ok(object::commit::cleanup_message(&std::fs::read(&path)?, true))
+}
+
+pub fn status(short: bool, porcelain: Option<PorcelainVersion>, branch: bool, untracked: UntrackedFiles, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let status = repo.status(untracked)?;
+    Ok(match porcelain {
+        Some(PorcelainVersion::V2) => status.porcelain_v2(branch, repo.algorithm()),
+        Some(PorcelainVersion::V1) => status.short_format(branch),
+        None if short => status.short_format(branch),
+        None => status.long_format(),
+    })
+}
+
+/// List the ignored paths among `pathnames`, or with `verbose` every path with the rule
+/// that decided it as `<source>:<line>:<pattern>\t<path>`. Returns the output and whether
+/// anything was ignored.
+pub fn check_ignore(pathnames: &[String], verbose: bool, non_matching: bool, no_index: bool, path: Option<&str>) -> Result<(String, bool)> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let cwd = std::env::current_dir()?;
+    let resolved = Pathspec::new(repo.worktree(), &cwd, pathnames)?;
+    let index = repo.read_index()?;
+    let mut ignore = repo.ignore()?;
+    let mut output = String::new();
+    let mut any = false;
+    for (pathname, resolved) in pathnames.iter().zip(resolved.patterns()) {
+        let is_dir = pathname.ends_with('/') || repo.worktree().join(resolved).is_dir();
+        let tracked = !no_index && index.entries.iter().any(|entry| entry.path == *resolved);
+        let rule = match tracked {
+            true => None,
+            false => ignore.matching(resolved, is_dir)?,
+        };
+        any |= rule.is_some_and(|rule| !rule.negated);
+        match rule {
+            Some(rule) if verbose => {
+                let source = rule.source.strip_prefix(repo.worktree()).unwrap_or(&rule.source);
+                output.push_str(&format!("{}:{}:{}\t{}\n", source.display(), rule.line, rule.text, pathname));
+            },
+            Some(rule) if !rule.negated => output.push_str(&format!("{}\n", pathname)),
+            None if non_matching => output.push_str(&format!("::\t{}\n", pathname)),
+            _ => {},
+        }
     }
+    Ok((output, any))
+}
+
+pub fn ls_files(pathspec: &[String], options: &LsFilesOptions, nul: bool, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let pathspec = Pathspec::new(repo.worktree(), &std::env::current_dir()?, pathspec)?;
+    let terminator = if nul { '\0' } else { '\n' };
+    Ok(repo.ls_files(&pathspec, options)?.into_iter().map(|line| format!("{}{}", line, terminator)).collect())
+}
+
+pub fn fsck(path: Option<&str>) -> Result<FsckReport> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    repo.fsck()
+}
+
+pub fn repack(options: &DeltaOptions, path: Option<&str>) -> Result<String> {
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    Ok(match repo.repack(options)? {
+        Some(idx_path) => format!("Wrote {}", idx_path.display()),
+        None => String::from("Nothing to pack"),
+    })
+}
+
+pub fn gc(prune: bool, path: Option<&str>) -> Result<String> {
+    let summary = repack(&DeltaOptions::default(), path)?;
+    if !prune {
+        return Ok(summary);
+    }
+    let path = path.as_ref().map_or(".", |p| p);
+    let repo = match Repository::find(Path::new(path), true) {
+        Err(err) => panic!("{:#?}", err),
+        Ok(Some(repo)) => repo,
+        Ok(None) => unreachable!(),
+    };
+    let pruned = repo.prune_loose()?;
+    Ok(format!("{}\nPruned {} unreachable objects", summary, pruned.len()))
 }
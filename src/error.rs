@@ -1,3 +1,5 @@
+use crate::cli::Format;
+use crate::hash::ObjectId;
 use std::{
     convert::From, fmt, io, num, path::PathBuf, str::Utf8Error, string::FromUtf8Error
 };
@@ -18,14 +20,32 @@ pub enum Error {
     Utf8Error(Utf8Error),
     StringNotFound(String, String),
     NameNotDefined,
-    AmbiguousReference(Vec<String>),
+    AmbiguousReference(Vec<ObjectId>),
     ObjectNotFound,
+    MalformedObject(String),
+    UnsupportedObjectFormat(String),
+    MalformedPack(String),
+    MalformedDelta(String),
+    WrongObjectType(ObjectId, Format, Format),
+    PathNotFound(ObjectId, String),
+    InvalidObjectId(String),
+    MalformedIndex(String),
+    MalformedPackedRefs(String),
+    PathspecNoMatch(String),
+    Refused(String),
+    BadRevision(String, usize, String),
+    UnknownRevision(String, String),
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        // TODO: implement this!
-        unimplemented!()
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Parse(err) => Some(err),
+            Error::FromUtf8Error(err) => Some(err),
+            Error::Utf8Error(err) => Some(err),
+            _ => None,
+        }
     }
 }
 
@@ -46,6 +66,19 @@ impl fmt::Display for Error {
             Error::NameNotDefined => write!(f, "NameNotDefined"),
             Error::ObjectNotFound => write!(f, "ObjectNotFound"),
             Error::AmbiguousReference(v) => write!(f, "AmbiguousReference: {:#?}", v),
+            Error::MalformedObject(reason) => write!(f, "MalformedObject: {}", reason),
+            Error::UnsupportedObjectFormat(format) => write!(f, "UnsupportedObjectFormat: {:#?}", format),
+            Error::MalformedPack(reason) => write!(f, "MalformedPack: {}", reason),
+            Error::MalformedDelta(reason) => write!(f, "MalformedDelta: {}", reason),
+            Error::WrongObjectType(sha, expected, found) => write!(f, "WrongObjectType: {} is a {}, expected a {}", sha, found, expected),
+            Error::PathNotFound(tree, path) => write!(f, "PathNotFound: '{}' does not exist in tree {}", path, tree),
+            Error::InvalidObjectId(id) => write!(f, "InvalidObjectId: {:#?}", id),
+            Error::MalformedIndex(reason) => write!(f, "MalformedIndex: {}", reason),
+            Error::MalformedPackedRefs(reason) => write!(f, "MalformedPackedRefs: {}", reason),
+            Error::PathspecNoMatch(pathspec) => write!(f, "PathspecNoMatch: pathspec {:#?} did not match any files", pathspec),
+            Error::Refused(reason) => write!(f, "Refused: {}", reason),
+            Error::BadRevision(revision, position, reason) => write!(f, "BadRevision: {} at position {} of {:?}", reason, position, revision),
+            Error::UnknownRevision(revision, reason) => write!(f, "UnknownRevision: {} in {:?}", reason, revision),
         }
     }
 }
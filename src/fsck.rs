@@ -0,0 +1,142 @@
+use std::{fmt, path::PathBuf};
+use crate::cli::Format;
+use crate::hash::{HashAlgorithm, ObjectId};
+use crate::object::{Leaf, Object};
+
+/// Tree entry modes git writes; anything else is reported
+const VALID_MODES: [&str; 5] = ["100644", "100755", "120000", "40000", "160000"];
+
+/// A problem found by `Repository::fsck`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckIssue {
+    /// The object couldn't be read back at all
+    Corrupt(ObjectId, String),
+    /// The object's content hashes to the second id rather than its name
+    HashMismatch(Format, ObjectId, ObjectId),
+    /// The object was read but its content is invalid
+    Malformed(Format, ObjectId, String),
+    /// Something points at an object the repository doesn't have, of the type it expected
+    /// if it said one
+    Missing(Option<Format>, ObjectId),
+    /// Something points at an object of the first type, but it is of the second
+    WrongType(ObjectId, Format, Format),
+    /// Not reachable from any ref, and no other unreachable object points at it
+    Dangling(Format, ObjectId),
+    /// Not reachable from any ref, but pointed at by another unreachable object
+    Unreachable(Format, ObjectId),
+    /// A pack index that couldn't be opened, so none of its objects were checked
+    BadPack(PathBuf, String),
+}
+
+impl FsckIssue {
+    /// Whether the issue means the repository is damaged. Unreachable objects are only
+    /// garbage waiting to be pruned.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, FsckIssue::Dangling(..) | FsckIssue::Unreachable(..))
+    }
+}
+
+/// One issue per line as `<kind> <type> <id>`, followed by a detail where there is one.
+/// The kind and type are single words so the output can be split on spaces.
+impl fmt::Display for FsckIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsckIssue::Corrupt(sha, reason) => write!(f, "corrupt object {} {}", sha, reason),
+            FsckIssue::HashMismatch(format, sha, actual) => write!(f, "hash-mismatch {} {} {}", format, sha, actual),
+            FsckIssue::Malformed(format, sha, reason) => write!(f, "malformed {} {} {}", format, sha, reason),
+            FsckIssue::Missing(Some(format), sha) => write!(f, "missing {} {}", format, sha),
+            FsckIssue::Missing(None, sha) => write!(f, "missing object {}", sha),
+            FsckIssue::WrongType(sha, expected, found) => write!(f, "wrong-type {} {} expected {}", found, sha, expected),
+            FsckIssue::Dangling(format, sha) => write!(f, "dangling {} {}", format, sha),
+            FsckIssue::Unreachable(format, sha) => write!(f, "unreachable {} {}", format, sha),
+            FsckIssue::BadPack(path, reason) => write!(f, "bad pack {} {}", path.display(), reason),
+        }
+    }
+}
+
+/// The outcome of `Repository::fsck`
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// How many stored objects were read, counting each loose and packed copy
+    pub checked: usize,
+    pub issues: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    /// True when nothing worse than unreachable objects was found
+    pub fn is_ok(&self) -> bool {
+        !self.issues.iter().any(FsckIssue::is_error)
+    }
+}
+
+impl fmt::Display for FsckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Re-hash one stored copy of `sha` and parse it, recording what is wrong with it.
+/// Returns the parsed object when it is usable for the connectivity walk.
+pub fn check_object(sha: &ObjectId, format: Format, data: Vec<u8>, algorithm: HashAlgorithm, issues: &mut Vec<FsckIssue>) -> Option<Object> {
+    let mut raw = format!("{} {}\x00", format, data.len()).into_bytes();
+    raw.extend_from_slice(&data);
+    let actual = algorithm.digest(&raw);
+    if actual != *sha {
+        issues.push(FsckIssue::HashMismatch(format.clone(), *sha, actual));
+    }
+
+    let object = match Object::new(format.clone(), data, algorithm) {
+        Ok(object) => object,
+        Err(err) => {
+            issues.push(FsckIssue::Malformed(format, *sha, err.to_string()));
+            return None;
+        },
+    };
+    if let Object::Tree(leaves) = &object {
+        issues.extend(check_tree(leaves).into_iter().map(|reason| FsckIssue::Malformed(Format::Tree, *sha, reason)));
+    }
+    Some(object)
+}
+
+fn check_tree(leaves: &[Leaf]) -> Vec<String> {
+    let mut reasons = Vec::new();
+    for leaf in leaves {
+        if !VALID_MODES.contains(&leaf.mode.as_str()) {
+            reasons.push(format!("entry {:?} has invalid mode {}", String::from_utf8_lossy(&leaf.path), leaf.mode));
+        }
+        if matches!(leaf.path.as_slice(), b"" | b"." | b".." | b".minit" | b".git") || leaf.path.contains(&b'/') {
+            reasons.push(format!("entry {:?} has an invalid name", String::from_utf8_lossy(&leaf.path)));
+        }
+    }
+    for pair in leaves.windows(2) {
+        let name = String::from_utf8_lossy(&pair[1].path);
+        if pair[0].path == pair[1].path {
+            reasons.push(format!("entry {:?} appears twice", name));
+        } else if pair[0].cmp_entries(&pair[1]).is_gt() {
+            reasons.push(format!("entry {:?} is out of order", name));
+        }
+    }
+    reasons
+}
+
+/// The objects `object` points at and the type each should have. Submodule commits live
+/// in another repository and are left out.
+pub fn links(object: &Object) -> Vec<(ObjectId, Format)> {
+    match object {
+        Object::Blob(_) => Vec::new(),
+        Object::Commit(commit) => std::iter::once((commit.tree, Format::Tree))
+            .chain(commit.parents.iter().map(|parent| (*parent, Format::Commit)))
+            .collect(),
+        Object::Tag(tag) => vec![(tag.object, tag.kind.clone())],
+        Object::Tree(leaves) => leaves.iter()
+            .filter_map(|leaf| match leaf.mode.as_str() {
+                "160000" => None,
+                _ if leaf.is_tree() => Some((leaf.sha, Format::Tree)),
+                _ => Some((leaf.sha, Format::Blob)),
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,223 @@
+use clap::ValueEnum;
+use sha1::Sha1;
+use sha2::{Sha256, Digest};
+use std::{fmt, str::FromStr};
+use crate::error::{Error, Result};
+
+/// The hash function a repository names its objects with.
+///
+/// Repositories without an `extensions.objectformat` setting predate the option and use SHA-256.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, ValueEnum)]
+pub enum HashAlgorithm {
+    Sha1,
+    #[default]
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Length of a raw digest in bytes
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => <Sha1 as Digest>::output_size(),
+            HashAlgorithm::Sha256 => <Sha256 as Digest>::output_size(),
+        }
+    }
+
+    /// Length of a digest in hex characters
+    pub fn hex_len(&self) -> usize {
+        self.digest_len() * 2
+    }
+
+    /// Hash `data` into an object id
+    pub fn digest(&self, data: &[u8]) -> ObjectId {
+        let digest = match self {
+            HashAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+        };
+        ObjectId::from_bytes(&digest, *self).unwrap()
+    }
+}
+
+/// Longest digest any supported algorithm produces
+const MAX_DIGEST_LEN: usize = 32;
+
+/// The name of an object: a digest tagged with the algorithm that produced it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId {
+    algorithm: HashAlgorithm,
+    bytes: [u8; MAX_DIGEST_LEN],
+}
+
+impl ObjectId {
+    /// Build an id from a raw digest, which must be exactly as long as `algorithm` produces
+    pub fn from_bytes(digest: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        if digest.len() != algorithm.digest_len() {
+            return Err(Error::InvalidObjectId(to_hex(digest)));
+        }
+        let mut bytes = [0u8; MAX_DIGEST_LEN];
+        bytes[..digest.len()].copy_from_slice(digest);
+        Ok(ObjectId { algorithm, bytes })
+    }
+
+    /// Parse a full length hex id for `algorithm`
+    pub fn from_hex(hex: &str, algorithm: HashAlgorithm) -> Result<Self> {
+        if hex.len() != algorithm.hex_len() {
+            return Err(Error::InvalidObjectId(hex.to_string()));
+        }
+        let digest = from_hex(hex).map_err(|_| Error::InvalidObjectId(hex.to_string()))?;
+        ObjectId::from_bytes(&digest, algorithm)
+    }
+
+    /// The all-zero id, which git uses to mean "no object"
+    pub fn null(algorithm: HashAlgorithm) -> Self {
+        ObjectId { algorithm, bytes: [0u8; MAX_DIGEST_LEN] }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.bytes.iter().all(|&b| b == 0)
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// The raw digest
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.algorithm.digest_len()]
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(self.as_bytes())
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self.to_hex())
+    }
+}
+
+/// Infers the algorithm from the length of the hex string
+impl FromStr for ObjectId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let algorithm = [HashAlgorithm::Sha1, HashAlgorithm::Sha256]
+            .into_iter()
+            .find(|algorithm| algorithm.hex_len() == s.len())
+            .ok_or(Error::InvalidObjectId(s.to_string()))?;
+        ObjectId::from_hex(s, algorithm)
+    }
+}
+
+/// An abbreviated object id: at least four hex digits, at most a full id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectIdPrefix {
+    hex: String,
+}
+
+impl ObjectIdPrefix {
+    pub const MIN_LEN: usize = 4;
+
+    pub fn parse(prefix: &str, algorithm: HashAlgorithm) -> Result<Self> {
+        if prefix.len() < Self::MIN_LEN
+            || prefix.len() > algorithm.hex_len()
+            || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(Error::InvalidObjectId(prefix.to_string()));
+        }
+        Ok(ObjectIdPrefix { hex: prefix.to_lowercase() })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.hex
+    }
+
+    /// The first byte every matching id starts with
+    pub fn first_byte(&self) -> u8 {
+        u8::from_str_radix(&self.hex[0..2], 16).unwrap()
+    }
+
+    pub fn matches(&self, id: &ObjectId) -> bool {
+        id.to_hex().starts_with(&self.hex)
+    }
+}
+
+impl fmt::Display for ObjectIdPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.hex)
+    }
+}
+
+/// Incremental hashing for data that is produced piece by piece
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The raw digest of everything hashed so far
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::Sha1 => write!(f, "sha1"),
+            HashAlgorithm::Sha256 => write!(f, "sha256"),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            other => Err(Error::UnsupportedObjectFormat(other.to_string())),
+        }
+    }
+}
+
+/// Lowercase hex encoding of raw digest bytes
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex digest into raw bytes
+pub fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(Error::MalformedObject(format!("invalid hex digest {}", hex)));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i+2], 16).map_err(Error::from))
+        .collect()
+}
@@ -0,0 +1,193 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+use crate::error::Result;
+use crate::pathspec::wildmatch;
+
+/// The per-directory files ignore rules are read from, lowest precedence first
+pub const IGNORE_FILES: [&str; 2] = [".gitignore", ".minitignore"];
+
+/// One line of an ignore file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreRule {
+    /// The glob, without the `!`, the leading `/` or the trailing `/`
+    pub pattern: String,
+    /// A `!` rule re-includes what an earlier rule excluded
+    pub negated: bool,
+    /// A trailing `/` only matches directories
+    pub dir_only: bool,
+    /// A `/` anywhere but the end ties the pattern to the directory of its file;
+    /// otherwise it matches a name at any depth
+    pub anchored: bool,
+    /// The directory the rule applies under, relative to the worktree root
+    pub base: String,
+    /// The file the rule was read from and its line number
+    pub source: PathBuf,
+    pub line: usize,
+    /// The line as written, less trailing spaces
+    pub text: String,
+}
+
+impl IgnoreRule {
+    /// Parse one line of an ignore file, or `None` for blank lines and comments
+    pub fn parse(line: &str, base: &str, source: &Path, number: usize) -> Option<Self> {
+        if line.starts_with('#') {
+            return None;
+        }
+        // Trailing spaces are dropped unless escaped
+        let mut line = line.trim_end_matches(['\r', '\n']);
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        let text = line.to_string();
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // `\#` and `\!` escape a leading `#` or `!`, which the glob then matches literally
+        let anchored = line.contains('/');
+        let pattern = line.strip_prefix('/').unwrap_or(line);
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(IgnoreRule {
+            pattern: pattern.to_string(),
+            negated,
+            dir_only,
+            anchored,
+            base: base.to_string(),
+            source: source.to_path_buf(),
+            line: number,
+            text,
+        })
+    }
+
+    /// Whether the rule matches `path`, which is relative to the worktree root
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = match self.base.as_str() {
+            "" => path,
+            base => match path.strip_prefix(base).and_then(|rest| rest.strip_prefix('/')) {
+                Some(relative) => relative,
+                None => return false,
+            },
+        };
+        if self.anchored {
+            wildmatch(self.pattern.as_bytes(), relative.as_bytes(), true)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(self.pattern.as_bytes(), name.as_bytes(), true)
+        }
+    }
+}
+
+/// The ignore rules of a worktree: the ignore files of each directory, which take
+/// precedence the deeper they are, then `.minit/info/exclude`, then the file named by
+/// `core.excludesFile`. Within a file the last matching rule wins.
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    worktree: PathBuf,
+    /// Rules by the directory whose ignore files they came from, loaded on demand
+    directories: HashMap<String, Vec<IgnoreRule>>,
+    /// Rules that apply everywhere, lowest precedence first
+    global: Vec<IgnoreRule>,
+}
+
+impl Ignore {
+    /// The rules for the worktree at `worktree` with its repository in `minit_dir`, and
+    /// the user's own excludes file if there is one
+    pub fn new(worktree: &Path, minit_dir: &Path, excludes_file: Option<&Path>) -> Result<Self> {
+        let mut ignore = Ignore { worktree: worktree.to_path_buf(), ..Ignore::default() };
+        if let Some(path) = excludes_file {
+            ignore.global = read_rules(path, "")?;
+        }
+        ignore.global.extend(read_rules(&minit_dir.join("info").join("exclude"), "")?);
+        Ok(ignore)
+    }
+
+    /// The rule that decides whether `path` is ignored, if any. A path inside an ignored
+    /// directory is ignored by that directory's rule, since git never looks inside.
+    pub fn matching(&mut self, path: &str, is_dir: bool) -> Result<Option<&IgnoreRule>> {
+        let mut found = None;
+        for (end, _) in path.match_indices('/').chain(std::iter::once((path.len(), ""))) {
+            let prefix = &path[..end];
+            self.load(prefix.rfind('/').map_or("", |i| &prefix[..i]))?;
+            let inside = end < path.len();
+            found = self.find(prefix, inside || is_dir);
+            if inside && found.as_ref().is_some_and(|found| !self.rule(found).negated) {
+                break;
+            }
+        }
+        Ok(found.map(|found| self.rule(&found)))
+    }
+
+    /// Whether `path` is ignored
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> Result<bool> {
+        Ok(self.matching(path, is_dir)?.is_some_and(|rule| !rule.negated))
+    }
+
+    /// The last rule matching `path` in the most specific file that has one, assuming
+    /// its directories are loaded
+    fn find(&self, path: &str, is_dir: bool) -> Option<Found> {
+        let mut dir = path.rfind('/').map(|i| &path[..i]);
+        loop {
+            let base = dir.unwrap_or("");
+            if let Some(rules) = self.directories.get(base)
+                && let Some(index) = rules.iter().rposition(|rule| rule.matches(path, is_dir))
+            {
+                return Some(Found::Directory(base.to_string(), index));
+            }
+            match dir {
+                Some(current) => dir = current.rfind('/').map(|i| &current[..i]),
+                None => break,
+            }
+        }
+        self.global.iter().rposition(|rule| rule.matches(path, is_dir)).map(Found::Global)
+    }
+
+    fn rule(&self, found: &Found) -> &IgnoreRule {
+        match found {
+            Found::Directory(base, index) => &self.directories[base][*index],
+            Found::Global(index) => &self.global[*index],
+        }
+    }
+
+    /// Read the ignore files of `dir` and its parents, unless they already have been
+    fn load(&mut self, dir: &str) -> Result<()> {
+        if self.directories.contains_key(dir) {
+            return Ok(());
+        }
+        if let Some(i) = dir.rfind('/') {
+            self.load(&dir[..i])?;
+        } else if !dir.is_empty() {
+            self.load("")?;
+        }
+        let mut rules = Vec::new();
+        for name in IGNORE_FILES {
+            rules.extend(read_rules(&self.worktree.join(dir).join(name), dir)?);
+        }
+        self.directories.insert(dir.to_string(), rules);
+        Ok(())
+    }
+}
+
+/// Where `Ignore::find` found a rule
+enum Found {
+    Directory(String, usize),
+    Global(usize),
+}
+
+fn read_rules(path: &Path, base: &str) -> Result<Vec<IgnoreRule>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    Ok(String::from_utf8_lossy(&fs::read(path)?)
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| IgnoreRule::parse(line, base, path, i + 1))
+        .collect())
+}
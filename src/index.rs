@@ -0,0 +1,379 @@
+use std::{fmt, fs::Metadata};
+use crate::error::{Error, Result};
+use crate::hash::{HashAlgorithm, Hasher, ObjectId};
+use crate::pack;
+
+const INDEX_MAGIC: &[u8; 4] = b"DIRC";
+/// Bytes of stat data, mode and size before the object id of an entry
+const STAT_LEN: usize = 40;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+/// Longer paths store this in the flags and are read up to their NUL instead
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_INTENT_TO_ADD: u16 = 0x2000;
+
+pub const MODE_FILE: u32 = 0o100644;
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+pub const MODE_SYMLINK: u32 = 0o120000;
+pub const MODE_GITLINK: u32 = 0o160000;
+
+/// One staged path. The stat fields are whatever the filesystem reported when the path was
+/// last staged, truncated to 32 bits as git does, and only serve to spot changed files
+/// without re-hashing them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    /// The file mode as a number, e.g. `0o100644`
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub sha: ObjectId,
+    /// 0 for a normal entry, 1 to 3 for the base, ours and theirs sides of a conflict
+    pub stage: u8,
+    pub assume_valid: bool,
+    pub skip_worktree: bool,
+    pub intent_to_add: bool,
+    /// Slash separated and relative to the worktree
+    pub path: String,
+}
+
+impl IndexEntry {
+    /// An entry with no stat data, which is always considered modified until restaged
+    pub fn new(path: String, mode: u32, sha: ObjectId) -> Self {
+        IndexEntry {
+            ctime: 0, ctime_nsec: 0, mtime: 0, mtime_nsec: 0,
+            dev: 0, ino: 0, mode, uid: 0, gid: 0, size: 0,
+            sha,
+            stage: 0,
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+            path,
+        }
+    }
+
+    /// An entry for a worktree file or symlink, with its mode and stat data taken from
+    /// `metadata` as returned by `symlink_metadata`
+    pub fn from_metadata(path: String, sha: ObjectId, metadata: &Metadata) -> Self {
+        let mut entry = IndexEntry::new(path, mode_of(metadata), sha);
+        entry.size = metadata.len() as u32;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            entry.ctime = metadata.ctime() as u32;
+            entry.ctime_nsec = metadata.ctime_nsec() as u32;
+            entry.mtime = metadata.mtime() as u32;
+            entry.mtime_nsec = metadata.mtime_nsec() as u32;
+            entry.dev = metadata.dev() as u32;
+            entry.ino = metadata.ino() as u32;
+            entry.uid = metadata.uid();
+            entry.gid = metadata.gid();
+        }
+        #[cfg(not(unix))]
+        if let Ok(mtime) = metadata.modified().map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default()) {
+            entry.mtime = mtime.as_secs() as u32;
+            entry.mtime_nsec = mtime.subsec_nanos();
+        }
+        entry
+    }
+
+    /// Whether the file behind `metadata` looks untouched since this entry was staged, so
+    /// it need not be hashed again
+    pub fn stat_matches(&self, metadata: &Metadata) -> bool {
+        let fresh = IndexEntry::from_metadata(self.path.clone(), self.sha, metadata);
+        self.mode == fresh.mode
+            && self.size == fresh.size
+            && self.mtime == fresh.mtime
+            && self.mtime_nsec == fresh.mtime_nsec
+            && self.ctime == fresh.ctime
+            && self.ctime_nsec == fresh.ctime_nsec
+            && self.ino == fresh.ino
+            && self.dev == fresh.dev
+    }
+
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+}
+
+/// The index mode git records for a worktree file: a symlink, an executable or a plain file
+pub fn mode_of(metadata: &Metadata) -> u32 {
+    if metadata.file_type().is_symlink() {
+        return MODE_SYMLINK;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o100 != 0 {
+            return MODE_EXECUTABLE;
+        }
+    }
+    MODE_FILE
+}
+
+/// A path added to or removed from the index, printed the way `git add -n` does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexChange {
+    Add(String),
+    Remove(String),
+}
+
+impl fmt::Display for IndexChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexChange::Add(path) => write!(f, "add '{}'", path),
+            IndexChange::Remove(path) => write!(f, "remove '{}'", path),
+        }
+    }
+}
+
+/// The staging area stored in `.minit/index`, in git's dircache format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    /// 2, 3 or 4. Version 3 is required for skip-worktree and intent-to-add entries, and
+    /// version 4 prefix-compresses paths.
+    pub version: u32,
+    /// Sorted by path bytes, then stage
+    pub entries: Vec<IndexEntry>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Index { version: 2, entries: Vec::new() }
+    }
+}
+
+impl Index {
+    /// Parse an index file, checking its trailing checksum. Optional extensions, such as
+    /// git's cached trees, are skipped since they go stale as soon as minit changes the
+    /// entries; required ones are an error.
+    pub fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        let digest_len = algorithm.digest_len();
+        if data.len() < 12 + digest_len || &data[0..4] != INDEX_MAGIC {
+            return Err(Error::MalformedIndex(String::from("missing DIRC header")));
+        }
+        let (body, checksum) = data.split_at(data.len() - digest_len);
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(body);
+        if hasher.finalize() != checksum {
+            return Err(Error::MalformedIndex(String::from("checksum mismatch")));
+        }
+
+        let version = read_u32(body, 4)?;
+        if !(2..=4).contains(&version) {
+            return Err(Error::MalformedIndex(format!("unsupported version {}", version)));
+        }
+        let count = read_u32(body, 8)? as usize;
+        let mut entries: Vec<IndexEntry> = Vec::with_capacity(count);
+        let mut idx = 12;
+        for _ in 0..count {
+            let previous = entries.last().map_or("", |entry: &IndexEntry| entry.path.as_str());
+            let (entry, len) = parse_entry(&body[idx..], version, previous, algorithm)?;
+            entries.push(entry);
+            idx += len;
+        }
+
+        while idx < body.len() {
+            let signature = body.get(idx..idx + 4).ok_or(truncated())?;
+            let size = read_u32(body, idx + 4)? as usize;
+            if !signature[0].is_ascii_uppercase() {
+                return Err(Error::MalformedIndex(format!(
+                    "unsupported required extension {}", String::from_utf8_lossy(signature)
+                )));
+            }
+            idx += 8 + size;
+        }
+        if idx != body.len() {
+            return Err(truncated());
+        }
+        Ok(Index { version, entries })
+    }
+
+    pub fn serialize(&self, algorithm: HashAlgorithm) -> Vec<u8> {
+        // Extended flags don't exist before version 3
+        let version = match self.entries.iter().any(IndexEntry::is_extended) {
+            true => self.version.max(3),
+            false => self.version,
+        };
+        let mut data = INDEX_MAGIC.to_vec();
+        data.extend(version.to_be_bytes());
+        data.extend((self.entries.len() as u32).to_be_bytes());
+        let mut previous = "";
+        for entry in &self.entries {
+            serialize_entry(&mut data, entry, version, previous);
+            previous = &entry.path;
+        }
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(&data);
+        data.extend(hasher.finalize());
+        data
+    }
+
+    /// The stage 0 entry for `path`
+    pub fn get(&self, path: &str) -> Option<&IndexEntry> {
+        self.find(path, 0).ok().map(|i| &self.entries[i])
+    }
+
+    /// Insert `entry`, replacing any entry for the same path and stage
+    pub fn add(&mut self, entry: IndexEntry) {
+        match self.find(&entry.path, entry.stage) {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    /// Remove every stage of `path`, returning whether anything was staged there
+    pub fn remove(&mut self, path: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.len() != before
+    }
+
+    fn find(&self, path: &str, stage: u8) -> std::result::Result<usize, usize> {
+        self.entries.binary_search_by(|entry| {
+            entry.path.as_bytes().cmp(path.as_bytes()).then(entry.stage.cmp(&stage))
+        })
+    }
+}
+
+fn parse_entry(data: &[u8], version: u32, previous: &str, algorithm: HashAlgorithm) -> Result<(IndexEntry, usize)> {
+    let digest_len = algorithm.digest_len();
+    let mut stat = [0u32; 10];
+    for (i, field) in stat.iter_mut().enumerate() {
+        *field = read_u32(data, i * 4)?;
+    }
+    let sha = ObjectId::from_bytes(data.get(STAT_LEN..STAT_LEN + digest_len).ok_or(truncated())?, algorithm)?;
+    let mut idx = STAT_LEN + digest_len;
+    let flags = read_u16(data, idx)?;
+    idx += 2;
+    let extended = match flags & FLAG_EXTENDED != 0 {
+        true if version < 3 => return Err(Error::MalformedIndex(String::from("extended flags before version 3"))),
+        true => {
+            idx += 2;
+            read_u16(data, idx - 2)?
+        },
+        false => 0,
+    };
+
+    let path = if version == 4 {
+        let strip = read_offset_varint(data, &mut idx)?;
+        let keep = previous.len().checked_sub(strip)
+            .ok_or(Error::MalformedIndex(String::from("path prefix is longer than the previous path")))?;
+        let end = idx + data[idx..].iter().position(|&c| c == 0).ok_or(truncated())?;
+        let mut path = previous.as_bytes()[..keep].to_vec();
+        path.extend_from_slice(&data[idx..end]);
+        idx = end + 1;
+        String::from_utf8(path)?
+    } else {
+        let end = idx + data[idx..].iter().position(|&c| c == 0).ok_or(truncated())?;
+        let path = String::from_utf8(data[idx..end].to_vec())?;
+        // NUL padding to a multiple of eight bytes, at least one
+        idx = (end + 8) & !7;
+        if idx > data.len() {
+            return Err(truncated());
+        }
+        path
+    };
+
+    let entry = IndexEntry {
+        ctime: stat[0],
+        ctime_nsec: stat[1],
+        mtime: stat[2],
+        mtime_nsec: stat[3],
+        dev: stat[4],
+        ino: stat[5],
+        mode: stat[6],
+        uid: stat[7],
+        gid: stat[8],
+        size: stat[9],
+        sha,
+        stage: ((flags & FLAG_STAGE_MASK) >> FLAG_STAGE_SHIFT) as u8,
+        assume_valid: flags & FLAG_ASSUME_VALID != 0,
+        skip_worktree: extended & EXTENDED_SKIP_WORKTREE != 0,
+        intent_to_add: extended & EXTENDED_INTENT_TO_ADD != 0,
+        path,
+    };
+    Ok((entry, idx))
+}
+
+fn serialize_entry(data: &mut Vec<u8>, entry: &IndexEntry, version: u32, previous: &str) {
+    let start = data.len();
+    for field in [
+        entry.ctime, entry.ctime_nsec, entry.mtime, entry.mtime_nsec, entry.dev,
+        entry.ino, entry.mode, entry.uid, entry.gid, entry.size,
+    ] {
+        data.extend(field.to_be_bytes());
+    }
+    data.extend_from_slice(entry.sha.as_bytes());
+
+    let mut flags = (entry.path.len().min(FLAG_NAME_MASK as usize)) as u16;
+    flags |= ((entry.stage as u16) << FLAG_STAGE_SHIFT) & FLAG_STAGE_MASK;
+    if entry.assume_valid {
+        flags |= FLAG_ASSUME_VALID;
+    }
+    if entry.is_extended() {
+        flags |= FLAG_EXTENDED;
+    }
+    data.extend(flags.to_be_bytes());
+    if entry.is_extended() {
+        let mut extended = 0u16;
+        if entry.skip_worktree {
+            extended |= EXTENDED_SKIP_WORKTREE;
+        }
+        if entry.intent_to_add {
+            extended |= EXTENDED_INTENT_TO_ADD;
+        }
+        data.extend(extended.to_be_bytes());
+    }
+
+    if version == 4 {
+        let common = previous.bytes()
+            .zip(entry.path.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        data.extend(pack::encode_ofs_distance((previous.len() - common) as u64));
+        data.extend_from_slice(&entry.path.as_bytes()[common..]);
+        data.push(0);
+    } else {
+        data.extend_from_slice(entry.path.as_bytes());
+        let len = data.len() - start;
+        data.resize(start + ((len + 8) & !7), 0);
+    }
+}
+
+/// Decode the varint git uses for both OFS_DELTA distances and v4 path prefixes
+fn read_offset_varint(data: &[u8], idx: &mut usize) -> Result<usize> {
+    let mut byte = *data.get(*idx).ok_or(truncated())?;
+    *idx += 1;
+    let mut value = (byte & 0x7f) as usize;
+    while byte & 0x80 != 0 {
+        byte = *data.get(*idx).ok_or(truncated())?;
+        *idx += 1;
+        value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+    }
+    Ok(value)
+}
+
+fn read_u32(data: &[u8], idx: usize) -> Result<u32> {
+    let bytes = data.get(idx..idx + 4).ok_or(truncated())?;
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u16(data: &[u8], idx: usize) -> Result<u16> {
+    let bytes = data.get(idx..idx + 2).ok_or(truncated())?;
+    Ok(u16::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn truncated() -> Error {
+    Error::MalformedIndex(String::from("index is truncated"))
+}
@@ -1,4 +1,14 @@
 pub mod repository;
 pub mod object;
 pub mod error;
+pub mod hash;
+pub mod pack;
 pub mod cli;
+pub mod fsck;
+pub mod index;
+pub mod pathspec;
+pub mod ignore;
+pub mod status;
+pub mod revision;
+pub mod reflog;
+pub mod refs;
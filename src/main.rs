@@ -1,22 +1,219 @@
 use std::{
-    path::{Path, PathBuf}
+    path::Path, process,
 };
 use clap::{Parser};
 use minit::{
-    cli::{Cli, Commands},
-    cli
+    cli::{Cli, Commands, ReflogAction},
+    cli,
+    object::delta::DeltaOptions,
+    repository::{AddOptions, CheckoutOptions, CommitOptions, LsFilesOptions, RmOptions},
 };
 
 fn main() {
     let args = Cli::parse();
     match args.command {
-        Commands::Init { path } => cli::init(Path::new(&path)),
+        Commands::Add { pathspec, all, update, dry_run, intent_to_add, force } => {
+            let options = AddOptions { all, update, dry_run, intent_to_add, force };
+            match cli::add(&pathspec, &options, None) {
+                Ok(output) => print!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::Branch { names, verbose, delete, force_delete, rename, force_rename, force, set_upstream_to } => {
+            let result = if let Some(upstream) = set_upstream_to {
+                cli::set_upstream(names.first().map(String::as_str), &upstream, None)
+            } else if delete || force_delete {
+                cli::delete_branches(&names, force || force_delete, None)
+            } else if rename || force_rename {
+                cli::rename_branch(&names, force || force_rename, None)
+            } else if names.is_empty() {
+                cli::list_branches(verbose, None)
+            } else {
+                cli::create_branch(&names, force, None)
+            };
+            match result {
+                Ok(output) => print!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::Init { path, object_format } => cli::init(Path::new(&path), object_format),
         Commands::CatFile { r#type, object } => println!("{:#?}", cli::cat_file(r#type, &object, None)),
-        Commands::HashObject { r#type, write, path } => println!("{:#?}", cli::hash_object(r#type, write, &path)),
+        Commands::CheckIgnore { pathname, verbose, non_matching, no_index } => {
+            match cli::check_ignore(&pathname, verbose, non_matching, no_index, None) {
+                Ok((output, ignored)) => {
+                    print!("{}", output);
+                    if !ignored {
+                        process::exit(1);
+                    }
+                },
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::Checkout { target, directory, new_branch, force, detach } => {
+            let target = target.unwrap_or_else(|| String::from("HEAD"));
+            if let Some(directory) = directory {
+                if let Err(err) = cli::checkout_into(&target, &directory, None) {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                }
+                return;
+            }
+            match cli::checkout(&target, &CheckoutOptions { force, new_branch, detach }, None) {
+                Ok(output) => eprint!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(1);
+                },
+            }
+        },
+        Commands::Commit { message, file, amend, allow_empty, all } => {
+            let options = CommitOptions { amend, allow_empty, all };
+            match cli::commit(&message, file.as_deref(), &options, None) {
+                Ok(output) => print!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(1);
+                },
+            }
+        },
+        Commands::Fsck {} => {
+            match cli::fsck(None) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.is_ok() {
+                        process::exit(1);
+                    }
+                },
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::Gc { prune } => {
+            match cli::gc(prune, None) {
+                Ok(output) => println!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::HashObject { r#type, write, path } => {
+            match cli::hash_object(r#type, write, &path) {
+                Ok(sha) => println!("{}", sha),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
         Commands::Log { commit } => println!("{:#?}", cli::log(&commit, None)),
-        Commands::LsTree { recursive, tree } => println!("{:#?}", cli::ls_tree(recursive, &tree, None, Path::new("").to_path_buf())),
-        Commands::Tag { add, name, object } => {},
-        _ => {},
+        Commands::LsFiles { pathspec, cached, stage, deleted, modified, others, exclude_standard, unmerged, z } => {
+            let options = LsFilesOptions { cached, stage, deleted, modified, others, exclude_standard, unmerged };
+            match cli::ls_files(&pathspec, &options, z, None) {
+                Ok(output) => print!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::LsTree { recursive, tree } => {
+            if let Err(err) = cli::ls_tree(recursive, &tree, None, Path::new("").to_path_buf()) {
+                eprintln!("fatal: {}", err);
+                process::exit(128);
+            }
+        },
+        Commands::Mv { source, destination } => {
+            if let Err(err) = cli::mv(&source, &destination, None) {
+                eprintln!("fatal: {}", err);
+                process::exit(128);
+            }
+        },
+        Commands::PackRefs { all, prune: _, no_prune } => {
+            if let Err(err) = cli::pack_refs(all, !no_prune, None) {
+                eprintln!("fatal: {}", err);
+                process::exit(128);
+            }
+        },
+        Commands::Reflog { action } => {
+            let result = match action.unwrap_or(ReflogAction::Show { name: String::from("HEAD") }) {
+                ReflogAction::Show { name } => cli::reflog_show(&name, None),
+                ReflogAction::Expire { expire, all, names } => cli::reflog_expire(&expire, all, &names, None),
+                ReflogAction::Delete { entries } => cli::reflog_delete(&entries, None),
+            };
+            match result {
+                Ok(output) => print!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::Repack { window, depth } => {
+            match cli::repack(&DeltaOptions { window, max_depth: depth }, None) {
+                Ok(output) => println!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::RevParse { r#type, name } => {
+            match cli::rev_parse(r#type, name, None) {
+                Ok(sha) => println!("{}", sha),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::Rm { pathspec, cached, recursive, force } => {
+            match cli::rm(&pathspec, &RmOptions { cached, recursive, force }, None) {
+                Ok(output) => print!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::ShowRef {} => cli::show_ref(None),
+        Commands::Status { short, porcelain, branch, untracked_files } => {
+            match cli::status(short, porcelain, branch, untracked_files, None) {
+                Ok(output) => print!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
+        Commands::Switch { branch, create, detach, force } => {
+            match cli::switch(branch.as_deref(), create.as_deref(), detach, force, None) {
+                Ok(output) => eprint!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(1);
+                },
+            }
+        },
+        Commands::Tag { add, name, object } => {
+            match cli::tag(add, name, object, None) {
+                Ok(output) => print!("{}", output),
+                Err(err) => {
+                    eprintln!("fatal: {}", err);
+                    process::exit(128);
+                },
+            }
+        },
     }
 }
-
@@ -1,110 +1,142 @@
-use sha2::{Sha256, Digest};
 use crate::error::{Error,Result};
 use crate::cli::Format;
-use indexmap::IndexMap;
-use itertools::Itertools;
+use crate::hash::{HashAlgorithm, Hasher, ObjectId};
+use std::{cmp::Ordering, io::Read};
+
+pub mod commit;
+pub mod delta;
+pub mod tag;
+
+use commit::Commit;
+use tag::Tag;
 
 pub enum Object {
     Blob(Vec<u8>),
-    Commit(IndexMap<String, Vec<String>>),
-    Tag(IndexMap<String, Vec<String>>),
+    Commit(Commit),
+    Tag(Tag),
     Tree(Vec<Leaf>),
 }
 
 #[derive(Clone)]
 pub struct Leaf {
-    pub mode: String, 
-    pub path: String,
-    pub sha: String
+    pub mode: String,
+    /// The entry name as stored in the tree; git places no encoding on it
+    pub path: Vec<u8>,
+    pub sha: ObjectId
 }
 
 impl Leaf {
-    pub fn new(mode: String, path: String, sha: String) -> Self {
-        Leaf{ mode, path, sha }
+    pub fn new(mode: String, path: impl Into<Vec<u8>>, sha: ObjectId) -> Self {
+        Leaf{ mode, path: path.into(), sha }
     }
 
-    pub fn parse_line(data: &str) -> Result<Self> {
-        let row = data;
-        let space_idx = row.find(" ")
-            .ok_or(Error::StringNotFound(data.to_string(), " ".to_string()))?;
-        let null_idx = row.find("\x00")
-            .ok_or(Error::StringNotFound(data.to_string(), "\x00".to_string()))?;
+    /// Parse a single `mode SP path NUL raw-digest` entry from the start of `data`,
+    /// returning the leaf and the number of bytes it occupied.
+    pub fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<(Self, usize)> {
+        let space_idx = data.iter()
+            .position(|&b| b == b' ')
+            .ok_or(Error::MalformedObject(String::from("tree entry is missing its mode")))?;
+        let null_idx = space_idx + 1 + data[space_idx+1..].iter()
+            .position(|&b| b == b'\x00')
+            .ok_or(Error::MalformedObject(String::from("tree entry is missing its path")))?;
+        let end = null_idx + 1 + algorithm.digest_len();
+        if data.len() < end {
+            return Err(Error::MalformedObject(String::from("tree entry has a truncated digest")));
+        }
 
-        let mode = row[0..space_idx].to_string();
-        let path = row[space_idx+1..null_idx].to_string();
-        let sha = row[null_idx+1..].to_string();
-        Ok(Leaf::new(mode, path, sha))
+        let mode = String::from_utf8(data[0..space_idx].to_vec())?;
+        let path = data[space_idx+1..null_idx].to_vec();
+        let sha = ObjectId::from_bytes(&data[null_idx+1..end], algorithm)?;
+        Ok((Leaf::new(mode, path, sha), end))
     }
 
-    pub fn remap_dirs(&self) -> String {
-        if self.mode.starts_with("10") {
-            self.path.clone()
-        } else {
-            self.path.clone() + "/"
+    /// Serialize the leaf into git's binary tree entry layout.
+    pub fn serialize(&self) -> Vec<u8> {
+        let digest = self.sha.as_bytes();
+        let mut entry = Vec::with_capacity(self.mode.len() + self.path.len() + 2 + digest.len());
+        entry.extend_from_slice(self.mode.as_bytes());
+        entry.push(b' ');
+        entry.extend_from_slice(&self.path);
+        entry.push(b'\x00');
+        entry.extend_from_slice(digest);
+        entry
+    }
+
+    /// The entry name as text, for worktree and index paths which must be UTF-8
+    pub fn path_str(&self) -> Result<&str> {
+        Ok(str::from_utf8(&self.path)?)
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.mode == "40000" || self.mode == "040000"
+    }
+
+    /// Git sorts tree entries as if directories carried a trailing `/`.
+    pub fn remap_dirs(&self) -> Vec<u8> {
+        let mut path = self.path.clone();
+        if self.is_tree() {
+            path.push(b'/');
         }
+        path
+    }
+
+    pub fn cmp_entries(&self, other: &Leaf) -> Ordering {
+        self.remap_dirs().cmp(&other.remap_dirs())
     }
 
-    pub fn get_type(&self) -> &str {
-        match &self.mode[0..2] {
-            "10" | "12" => "blob",
-            "04" => "tree",
-            "16" => "commit",
-            other => panic!("Invalid leaf mode {}", other)
+    pub fn get_type(&self) -> Result<&'static str> {
+        if self.is_tree() {
+            return Ok("tree");
+        }
+        match self.mode.get(0..2).unwrap_or_default() {
+            "10" | "12" => Ok("blob"),
+            "16" => Ok("commit"),
+            _ => Err(Error::MalformedObject(format!("invalid mode {} for tree entry {:?}", self.mode, String::from_utf8_lossy(&self.path)))),
         }
     }
 }
 
 impl Object {
-    pub fn new(format: Format, data: Vec<u8>) -> Result<Self> {
+    pub fn new(format: Format, data: Vec<u8>, algorithm: HashAlgorithm) -> Result<Self> {
         match format {
             Format::Blob => Ok(Object::Blob(data)),
             Format::Commit => {
-                Ok(Object::Commit(key_value_parse(str::from_utf8(&data)?)))
+                Ok(Object::Commit(Commit::parse(&data, algorithm)?))
             },
             Format::Tag => {
-                Ok(Object::Tag(key_value_parse(str::from_utf8(&data)?)))
-            },
-            Format::Tree => {
-                let tree = str::from_utf8(&data)?
-                    .split("\n")
-                    .map(|line| Leaf::parse_line(line))
-                    .collect::<Result<Vec<Leaf>>>()?;
-                Ok(Object::Tree(tree))
+                Ok(Object::Tag(Tag::parse(&data, algorithm)?))
             },
-        } 
+            Format::Tree => Ok(Object::Tree(parse_tree(&data, algorithm)?)),
+        }
     }
 
     pub fn serialize(&self) -> Option<Vec<u8>> {
         match self {
             Object::Blob(data) => Some(data.clone()),
-            Object::Tag(map) | Object::Commit(map) => Some(key_value_serialize(map)) ,
+            Object::Commit(commit) => Some(commit.serialize()),
+            Object::Tag(tag) => Some(tag.serialize()),
             Object::Tree(tree) => {
-                let mut cloned_tree = tree.clone();
-                cloned_tree.sort_by_key(|leaf| leaf.remap_dirs());
-                let serialized_tree = tree.into_iter() 
-                    .map(|leaf| leaf.mode.clone() + " " + &leaf.path + "\x00" + &leaf.sha)
-                    .join("\n")
-                    .as_bytes()
-                    .to_vec();
-                Some(serialized_tree)
+                let mut sorted_tree = tree.clone();
+                sorted_tree.sort_by(|a, b| a.cmp_entries(b));
+                Some(sorted_tree.iter().flat_map(|leaf| leaf.serialize()).collect())
             }
 
         }
     }
-    
-    pub fn deserialize(&mut self, data: Vec<u8>) -> Result<()>{
+
+    pub fn deserialize(&mut self, data: Vec<u8>, algorithm: HashAlgorithm) -> Result<()>{
         match self {
             Object::Blob(curr_data) => {
-                *curr_data = data;            
+                *curr_data = data;
             },
-            Object::Tag(map) | Object::Commit(map) => {
-                *map = key_value_parse(str::from_utf8(&data[..])?);
+            Object::Commit(commit) => {
+                *commit = Commit::parse(&data, algorithm)?;
+            },
+            Object::Tag(tag) => {
+                *tag = Tag::parse(&data, algorithm)?;
             },
             Object::Tree(tree) => {
-                *tree = str::from_utf8(&data)?
-                    .split("\n")
-                    .map(|line| Leaf::parse_line(line))
-                    .collect::<Result<Vec<Leaf>>>()?;
+                *tree = parse_tree(&data, algorithm)?;
             },
         }
 
@@ -120,62 +152,100 @@ impl Object {
         }
     }
 
-    pub fn write(&self) -> Result<(String, String)> {
-        let raw = self.serialize().unwrap();
-        let data = str::from_utf8(&raw[..])?;
-        let size = format!("{}", data.len());
-        let result = format!("{}", self.format()) + " " + &size + "\x00" + data;
-        let sha = Sha256::digest(&result);
-        Ok((format!("{:x}", &sha), result))
+    pub fn write(&self, algorithm: HashAlgorithm) -> Result<(ObjectId, Vec<u8>)> {
+        let data = self.serialize()
+            .ok_or(Error::MalformedObject(String::from("object could not be serialized")))?;
+        let mut result = format!("{} {}\x00", self.format(), data.len()).into_bytes();
+        result.extend(data);
+        Ok((algorithm.digest(&result), result))
+    }
+}
+
+/// Hash an object of `size` bytes read from `reader` without holding it in memory
+pub fn hash_stream<R: Read>(format: &Format, size: u64, mut reader: R, algorithm: HashAlgorithm) -> Result<ObjectId> {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(format!("{} {}\x00", format, size).as_bytes());
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+        total += read as u64;
+    }
+    if total != size {
+        return Err(Error::MalformedObject(format!("expected {} bytes but read {}", size, total)));
     }
+    ObjectId::from_bytes(&hasher.finalize(), algorithm)
 }
 
-/// TODO:
-fn key_value_serialize(map: &IndexMap<String, Vec<String>>) -> Vec<u8> {
-    map.iter().map(|(k, v)| {
-        if k == "message" {
-            return String::from("\n") + &v.join("");
-        } 
-        v.iter()
-            .map(|v| k.clone() + " " + v)
-            .collect::<Vec<String>>()
-            .join("\n")
-    }).join("\n").as_bytes().to_vec()
+/// Walk a binary tree body entry by entry; each digest is a fixed number of raw bytes.
+fn parse_tree(data: &[u8], algorithm: HashAlgorithm) -> Result<Vec<Leaf>> {
+    let mut leaves = Vec::new();
+    let mut idx = 0;
+    while idx < data.len() {
+        let (leaf, len) = Leaf::parse(&data[idx..], algorithm)?;
+        leaves.push(leaf);
+        idx += len;
+    }
+    Ok(leaves)
 }
 
-/// TODO:
-fn key_value_parse(raw: &str) -> IndexMap<String, Vec<String>> {
-    let mut map: IndexMap<String, Vec<String>> = IndexMap::new();
-    raw.split("\n").into_iter()
-        .map(|x| x.to_string())
-        .coalesce(|x, y| {
-            if let Some(ch) = y.get(..1) {
-                if ch == " " {
-                    Ok(x + "\n" + &y[1..])
-                } else {
-                    Err((x, y))
-                }
-            } else {
-                Err((x, y))
+/// A commit or tag header: its key and raw value, with continuation lines joined by `\n`
+type Header = (String, Vec<u8>);
+
+/// Write headers one per line, indenting continuation lines, then a blank line and the
+/// message. This is the exact inverse of `key_value_parse`.
+fn key_value_serialize(headers: &[Header], message: Option<&[u8]>) -> Vec<u8> {
+    let mut raw = Vec::new();
+    for (key, value) in headers {
+        raw.extend_from_slice(key.as_bytes());
+        raw.push(b' ');
+        for (i, line) in value.split(|&c| c == b'\n').enumerate() {
+            if i > 0 {
+                raw.extend_from_slice(b"\n ");
             }
-        })
-    .coalesce(|x, y| {
-        if x == "" {
-            Ok(String::from("message ") + &y)
-        } else {
-            Err((x, y))
+            raw.extend_from_slice(line);
         }
-    })
-    .for_each(|line| {
-        let space_idx = line.find(" ").expect("Tag or commit is incorrectly formatted");
-        let key = String::from(&line[..space_idx]);
-        let value = String::from(&line[space_idx+1..]);
-        if let Some(val) = map.get_mut(&key) {
-            val.push(value);
+        raw.push(b'\n');
+    }
+    if let Some(message) = message {
+        raw.push(b'\n');
+        raw.extend_from_slice(message);
+    }
+    raw
+}
+
+/// Split a commit or tag into its headers, in file order, and the message after the first
+/// blank line, which is `None` when the object ends after its headers. A line starting with
+/// a space continues the value of the header above it. Values are kept as raw bytes since
+/// names and messages may be in any encoding.
+fn key_value_parse(raw: &[u8]) -> Result<(Vec<Header>, Option<Vec<u8>>)> {
+    let malformed = |reason: &str| Error::MalformedObject(format!("commit or tag {}", reason));
+    let mut headers: Vec<Header> = Vec::new();
+    let mut idx = 0;
+    while idx < raw.len() {
+        if raw[idx] == b'\n' {
+            return Ok((headers, Some(raw[idx+1..].to_vec())));
+        }
+        let end = idx + raw[idx..].iter()
+            .position(|&c| c == b'\n')
+            .ok_or(malformed("header is not terminated by a newline"))?;
+        let line = &raw[idx..end];
+        if let Some(rest) = line.strip_prefix(b" ") {
+            let (_, value) = headers.last_mut()
+                .ok_or(malformed("starts with a continuation line"))?;
+            value.push(b'\n');
+            value.extend_from_slice(rest);
         } else {
-            map.insert(key, vec![value]);
+            let space_idx = line.iter()
+                .position(|&c| c == b' ')
+                .ok_or(malformed("header has no value"))?;
+            headers.push((String::from_utf8(line[..space_idx].to_vec())?, line[space_idx+1..].to_vec()));
         }
-    });
-    map
+        idx = end + 1;
+    }
+    Ok((headers, None))
 }
-
@@ -0,0 +1,207 @@
+use std::fmt;
+use crate::error::{Error, Result};
+use crate::hash::{HashAlgorithm, ObjectId};
+use super::{key_value_parse, key_value_serialize};
+
+/// A person and the moment they authored, committed or tagged something,
+/// as in `Name <email> 1700000000 +0100`.
+///
+/// Names and emails are raw bytes: git stores them in whatever encoding the
+/// commit's `encoding` header names, or UTF-8 if it has none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    /// Minutes east of UTC
+    pub offset: i32,
+    /// The identity as written, kept when the fields can't spell it again
+    /// (e.g. a `-0000` timezone, or one of the malformed forms `lenient` accepts)
+    raw: Option<Vec<u8>>,
+}
+
+impl Identity {
+    pub fn new(name: impl Into<Vec<u8>>, email: impl Into<Vec<u8>>, time: i64, offset: i32) -> Self {
+        Identity { name: name.into(), email: email.into(), time, offset, raw: None }
+    }
+
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let malformed = || Error::MalformedObject(format!("invalid identity {:?}", String::from_utf8_lossy(raw)));
+        let lt = raw.iter().position(|&c| c == b'<').ok_or_else(malformed)?;
+        let gt = lt + raw[lt..].iter().position(|&c| c == b'>').ok_or_else(malformed)?;
+        raw[..lt].strip_suffix(b" ").ok_or_else(malformed)?;
+        let date = raw[gt + 1..].strip_prefix(b" ").ok_or_else(malformed)?;
+        let (time, tz) = str::from_utf8(date)
+            .ok()
+            .and_then(|date| date.split_once(' '))
+            .ok_or_else(malformed)?;
+
+        // git rejects zero padded and signed timestamps
+        if time.is_empty() || !time.bytes().all(|c| c.is_ascii_digit()) || (time.starts_with('0') && time.len() > 1) {
+            return Err(malformed());
+        }
+        time.parse::<i64>().map_err(|_| malformed())?;
+
+        let bytes = tz.as_bytes();
+        if bytes.len() != 5 || !matches!(bytes[0], b'+' | b'-') || !bytes[1..].iter().all(|c| c.is_ascii_digit()) {
+            return Err(malformed());
+        }
+        Ok(Identity::lenient(raw))
+    }
+
+    /// Read an identity the way git reads one out of an existing commit or tag: whatever
+    /// can't be made sense of is left at zero, and the raw form is kept to write it back.
+    pub fn lenient(raw: &[u8]) -> Self {
+        let (name, email, time, offset) = Identity::fields(raw);
+        let mut identity = Identity::new(name, email, time, offset);
+        if identity.to_bytes() != raw {
+            identity.raw = Some(raw.to_vec());
+        }
+        identity
+    }
+
+    fn fields(raw: &[u8]) -> (&[u8], &[u8], i64, i32) {
+        let trim = |bytes: &[u8]| bytes.trim_ascii_end().len();
+        let Some(lt) = raw.iter().position(|&c| c == b'<') else {
+            return (&raw[..trim(raw)], &[], 0, 0);
+        };
+        let gt = raw[lt..].iter().position(|&c| c == b'>').map_or(raw.len(), |gt| lt + gt);
+        let name = &raw[..trim(&raw[..lt])];
+        let email = &raw[lt + 1..gt];
+        let mut date = raw.get(gt + 1..).unwrap_or_default().split(|&c| c == b' ').filter(|part| !part.is_empty());
+        let time = date.next()
+            .and_then(|time| str::from_utf8(time).ok())
+            .and_then(|time| time.parse::<i64>().ok())
+            .unwrap_or(0);
+        let offset = date.next()
+            .and_then(|tz| str::from_utf8(tz).ok())
+            .filter(|tz| tz.len() == 5 && tz[1..].bytes().all(|c| c.is_ascii_digit()))
+            .and_then(|tz| {
+                let minutes = tz[1..3].parse::<i32>().ok()? * 60 + tz[3..5].parse::<i32>().ok()?;
+                match tz.as_bytes()[0] {
+                    b'+' => Some(minutes),
+                    b'-' => Some(-minutes),
+                    _ => None,
+                }
+            })
+            .unwrap_or(0);
+        (name, email, time, offset)
+    }
+
+    /// The identity exactly as it is stored in a commit or tag header
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // The raw form only stands while the fields still say what it says
+        if let Some(raw) = self.raw.as_ref()
+            .filter(|raw| Identity::fields(raw) == (&self.name[..], &self.email[..], self.time, self.offset)) {
+            return raw.clone();
+        }
+        let mut raw = self.name.clone();
+        raw.extend_from_slice(b" <");
+        raw.extend_from_slice(&self.email);
+        raw.extend_from_slice(format!("> {} {}", self.time, self.format_offset()).as_bytes());
+        raw
+    }
+
+    fn format_offset(&self) -> String {
+        let sign = if self.offset < 0 { '-' } else { '+' };
+        let minutes = self.offset.unsigned_abs();
+        format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+    }
+}
+
+/// Lossy for names that aren't UTF-8; use `to_bytes` to write an identity back out
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub author: Identity,
+    pub committer: Identity,
+    /// The encoding of the identities and message, when it isn't UTF-8
+    pub encoding: Option<String>,
+    /// Headers minit doesn't interpret, such as `mergetag`, in the order they were written.
+    /// Known headers that appear out of git's order also land here so they round-trip.
+    pub extra_headers: Vec<(String, Vec<u8>)>,
+    /// The signature git appends as the last header
+    pub gpgsig: Option<Vec<u8>>,
+    /// Everything after the blank line that ends the headers, or `None` if there is no
+    /// blank line. git always writes one, but accepts commits without.
+    pub message: Option<Vec<u8>>,
+}
+
+impl Commit {
+    pub fn parse(raw: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        let (headers, message) = key_value_parse(raw)?;
+        let mut headers = headers.into_iter().peekable();
+        let mut take = |key: &str| headers.next_if(|(k, _)| k == key).map(|(_, v)| v);
+
+        let tree = take("tree").ok_or(Error::MalformedObject(String::from("commit has no tree")))?;
+        let tree = ObjectId::from_hex(str::from_utf8(&tree)?, algorithm)?;
+        let mut parents = Vec::new();
+        while let Some(parent) = take("parent") {
+            parents.push(ObjectId::from_hex(str::from_utf8(&parent)?, algorithm)?);
+        }
+        let author = take("author").ok_or(Error::MalformedObject(String::from("commit has no author")))?;
+        let committer = take("committer").ok_or(Error::MalformedObject(String::from("commit has no committer")))?;
+        let encoding = take("encoding").map(String::from_utf8).transpose()?;
+
+        let mut extra_headers = headers.collect::<Vec<(String, Vec<u8>)>>();
+        let gpgsig = match extra_headers.last() {
+            Some((key, _)) if key == "gpgsig" => extra_headers.pop().map(|(_, v)| v),
+            _ => None,
+        };
+        Ok(Commit {
+            tree,
+            parents,
+            author: Identity::lenient(&author),
+            committer: Identity::lenient(&committer),
+            encoding,
+            extra_headers,
+            gpgsig,
+            message,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut headers = vec![(String::from("tree"), self.tree.to_string().into_bytes())];
+        headers.extend(self.parents.iter().map(|parent| (String::from("parent"), parent.to_string().into_bytes())));
+        headers.push((String::from("author"), self.author.to_bytes()));
+        headers.push((String::from("committer"), self.committer.to_bytes()));
+        headers.extend(self.encoding.iter().map(|encoding| (String::from("encoding"), encoding.clone().into_bytes())));
+        headers.extend(self.extra_headers.iter().cloned());
+        headers.extend(self.gpgsig.iter().map(|sig| (String::from("gpgsig"), sig.clone())));
+        key_value_serialize(&headers, self.message.as_deref())
+    }
+}
+
+/// Tidy a commit message the way git does before committing: trailing whitespace and
+/// surrounding blank lines go, runs of blank lines become one, and the message ends in a
+/// newline. With `strip_comments`, lines starting with `#` are dropped first, as in a
+/// message written in an editor. An empty result means there is no message.
+pub fn cleanup_message(raw: &[u8], strip_comments: bool) -> Vec<u8> {
+    let mut cleaned: Vec<u8> = Vec::new();
+    let mut blank = false;
+    for line in raw.split(|&c| c == b'\n') {
+        if strip_comments && line.starts_with(b"#") {
+            continue;
+        }
+        let end = line.iter().rposition(|c| !c.is_ascii_whitespace()).map_or(0, |i| i + 1);
+        if end == 0 {
+            blank = !cleaned.is_empty();
+            continue;
+        }
+        if blank {
+            cleaned.push(b'\n');
+            blank = false;
+        }
+        cleaned.extend_from_slice(&line[..end]);
+        cleaned.push(b'\n');
+    }
+    cleaned
+}
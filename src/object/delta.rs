@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use crate::error::{Error, Result};
+use crate::cli::Format;
+
+/// Width of the rolling hash window; shorter matches are cheaper to insert than to copy
+const BLOCK: usize = 16;
+/// Base positions remembered per hash value, so repetitive data can't blow up the index
+const MAX_BUCKET: usize = 64;
+/// Largest copy a single instruction may express; git reads a size of zero as this value
+const MAX_COPY: usize = 0x10000;
+/// Largest insert a single instruction may express
+const MAX_INSERT: usize = 0x7f;
+const HASH_BASE: u32 = 257;
+
+/// Tuning for the delta search
+#[derive(Debug, Clone)]
+pub struct DeltaOptions {
+    /// How many neighbouring objects to try as bases for each object (0 disables deltas)
+    pub window: usize,
+    /// Longest chain of deltas-of-deltas that may be built
+    pub max_depth: usize,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        DeltaOptions { window: 10, max_depth: 50 }
+    }
+}
+
+/// An object offered to the delta search
+pub struct DeltaObject<'a> {
+    pub format: Format,
+    pub data: &'a [u8],
+    /// The worktree path the object was found at, if any
+    pub path: Option<&'a str>,
+}
+
+/// The outcome of a delta search over a set of objects
+pub struct DeltaPlan {
+    /// Indices of the objects in an order where every base precedes its deltas
+    pub order: Vec<usize>,
+    /// For each object, the index of its base and the delta against it
+    pub deltas: Vec<Option<(usize, Vec<u8>)>>,
+}
+
+/// Choose a base for each object among the `window` objects that sort next to it.
+///
+/// Objects are grouped by type, then by a hash of their path that favours the file name,
+/// then by decreasing size, so that successive versions of a file end up side by side and
+/// the larger version is the base.
+pub fn plan(objects: &[DeltaObject], options: &DeltaOptions) -> DeltaPlan {
+    let mut order = (0..objects.len()).collect::<Vec<usize>>();
+    order.sort_by_key(|&i| {
+        let object = &objects[i];
+        (type_rank(&object.format), object.path.map_or(0, name_hash), std::cmp::Reverse(object.data.len()))
+    });
+
+    let mut deltas: Vec<Option<(usize, Vec<u8>)>> = (0..objects.len()).map(|_| None).collect();
+    let mut depths = vec![0usize; objects.len()];
+    if options.window == 0 || options.max_depth == 0 {
+        return DeltaPlan { order, deltas };
+    }
+
+    for (pos, &i) in order.iter().enumerate() {
+        let target = &objects[i];
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        for &base_idx in &order[pos.saturating_sub(options.window)..pos] {
+            let base = &objects[base_idx];
+            if base.format != target.format || depths[base_idx] >= options.max_depth {
+                continue;
+            }
+            // A base far smaller than the target can't save much
+            if base.data.len() < target.data.len() / 32 {
+                continue;
+            }
+            let delta = encode(base.data, target.data);
+            let limit = best.as_ref().map_or(target.data.len() / 2, |(_, d)| d.len());
+            if delta.len() < limit {
+                best = Some((base_idx, delta));
+            }
+        }
+        if let Some((base_idx, _)) = &best {
+            depths[i] = depths[*base_idx] + 1;
+        }
+        deltas[i] = best;
+    }
+    DeltaPlan { order, deltas }
+}
+
+/// Encode `target` as git copy/insert instructions against `base`.
+///
+/// Every `BLOCK` aligned window of the base is indexed by a rolling hash. The target is
+/// then scanned one byte at a time; on a hash hit the match is verified, grown forwards as
+/// far as the data agrees and backwards into any pending literal bytes.
+pub fn encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_varint(&mut delta, base.len());
+    write_varint(&mut delta, target.len());
+
+    let index = index_base(base);
+    let top = HASH_BASE.wrapping_pow(BLOCK as u32 - 1);
+    let mut insert: Vec<u8> = Vec::new();
+    let mut i = 0;
+    let mut hash = None;
+    while i < target.len() {
+        if i + BLOCK > target.len() {
+            insert.extend_from_slice(&target[i..]);
+            break;
+        }
+        let h = *hash.get_or_insert_with(|| rolling_hash(&target[i..i + BLOCK]));
+
+        let mut best: Option<(usize, usize, usize)> = None;
+        for &start in index.get(&h).into_iter().flatten() {
+            if base[start..start + BLOCK] != target[i..i + BLOCK] {
+                continue;
+            }
+            let mut len = BLOCK;
+            while start + len < base.len() && i + len < target.len() && base[start + len] == target[i + len] {
+                len += 1;
+            }
+            let mut back = 0;
+            while back < insert.len() && back < start && base[start - back - 1] == insert[insert.len() - back - 1] {
+                back += 1;
+            }
+            if best.is_none_or(|(_, best_len, best_back)| len + back > best_len + best_back) {
+                best = Some((start, len, back));
+            }
+        }
+
+        match best {
+            Some((start, len, back)) => {
+                insert.truncate(insert.len() - back);
+                flush_insert(&mut delta, &mut insert);
+                push_copy(&mut delta, start - back, len + back);
+                i += len;
+                hash = None;
+            },
+            None => {
+                insert.push(target[i]);
+                if i + BLOCK < target.len() {
+                    hash = Some(h.wrapping_sub((target[i] as u32).wrapping_mul(top))
+                        .wrapping_mul(HASH_BASE)
+                        .wrapping_add(target[i + BLOCK] as u32));
+                }
+                i += 1;
+            },
+        }
+    }
+    flush_insert(&mut delta, &mut insert);
+    delta
+}
+
+/// Apply a git delta (source size, target size, then copy/insert instructions) to `base`.
+pub fn apply(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut idx = 0;
+    let source_size = read_varint(delta, &mut idx)?;
+    let target_size = read_varint(delta, &mut idx)?;
+    if source_size != base.len() {
+        return Err(Error::MalformedDelta(String::from("base has the wrong size")));
+    }
+
+    // Only trust the declared size as far as the instructions could plausibly produce it
+    let mut target = Vec::with_capacity(target_size.min(delta.len().saturating_mul(MAX_COPY)));
+    while idx < delta.len() {
+        let cmd = delta[idx];
+        idx += 1;
+        if cmd & 0x80 != 0 {
+            let mut offset = 0usize;
+            let mut len = 0usize;
+            for bit in 0..4 {
+                if cmd & (1 << bit) != 0 {
+                    offset |= (*delta.get(idx).ok_or(truncated())? as usize) << (bit * 8);
+                    idx += 1;
+                }
+            }
+            for bit in 0..3 {
+                if cmd & (0x10 << bit) != 0 {
+                    len |= (*delta.get(idx).ok_or(truncated())? as usize) << (bit * 8);
+                    idx += 1;
+                }
+            }
+            if len == 0 {
+                len = MAX_COPY;
+            }
+            let chunk = base.get(offset..offset + len)
+                .ok_or(Error::MalformedDelta(String::from("copy runs past the end of the base")))?;
+            target.extend_from_slice(chunk);
+        } else if cmd != 0 {
+            let chunk = delta.get(idx..idx + cmd as usize).ok_or(truncated())?;
+            target.extend_from_slice(chunk);
+            idx += cmd as usize;
+        } else {
+            return Err(Error::MalformedDelta(String::from("uses the reserved instruction 0")));
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(Error::MalformedDelta(String::from("produced the wrong size")));
+    }
+    Ok(target)
+}
+
+/// git's pack name hash: the last characters of a path dominate, so files with the same
+/// name in different directories sort together.
+fn name_hash(path: &str) -> u32 {
+    path.bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+fn type_rank(format: &Format) -> u8 {
+    match format {
+        Format::Commit => 0,
+        Format::Tree => 1,
+        Format::Blob => 2,
+        Format::Tag => 3,
+    }
+}
+
+fn rolling_hash(window: &[u8]) -> u32 {
+    window.iter().fold(0u32, |hash, &c| hash.wrapping_mul(HASH_BASE).wrapping_add(c as u32))
+}
+
+fn index_base(base: &[u8]) -> HashMap<u32, Vec<usize>> {
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    for start in (0..base.len().saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        let bucket = index.entry(rolling_hash(&base[start..start + BLOCK])).or_default();
+        if bucket.len() < MAX_BUCKET {
+            bucket.push(start);
+        }
+    }
+    index
+}
+
+fn flush_insert(delta: &mut Vec<u8>, insert: &mut Vec<u8>) {
+    for chunk in insert.chunks(MAX_INSERT) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+    insert.clear();
+}
+
+fn push_copy(delta: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let chunk = len.min(MAX_COPY);
+        let mut cmd = 0x80u8;
+        let mut args = Vec::with_capacity(7);
+        for bit in 0..4 {
+            let byte = (offset >> (bit * 8)) as u8;
+            if byte != 0 {
+                cmd |= 1 << bit;
+                args.push(byte);
+            }
+        }
+        let size = if chunk == MAX_COPY { 0 } else { chunk };
+        for bit in 0..3 {
+            let byte = (size >> (bit * 8)) as u8;
+            if byte != 0 {
+                cmd |= 0x10 << bit;
+                args.push(byte);
+            }
+        }
+        delta.push(cmd);
+        delta.extend(args);
+        offset += chunk;
+        len -= chunk;
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], idx: &mut usize) -> Result<usize> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        if shift >= usize::BITS {
+            return Err(Error::MalformedDelta(String::from("size does not fit in a usize")));
+        }
+        let byte = *data.get(*idx).ok_or(truncated())?;
+        *idx += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn truncated() -> Error {
+    Error::MalformedDelta(String::from("instructions are truncated"))
+}
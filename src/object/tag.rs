@@ -0,0 +1,51 @@
+use crate::error::{Error, Result};
+use crate::cli::Format;
+use crate::hash::{HashAlgorithm, ObjectId};
+use super::{key_value_parse, key_value_serialize, commit::Identity};
+
+/// An annotated tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub object: ObjectId,
+    /// The type of `object`
+    pub kind: Format,
+    pub name: String,
+    /// Tags written before git 0.99.9 have no tagger
+    pub tagger: Option<Identity>,
+    /// Headers minit doesn't interpret, in the order they were written
+    pub extra_headers: Vec<(String, Vec<u8>)>,
+    /// Everything after the blank line that ends the headers, or `None` if there is none
+    pub message: Option<Vec<u8>>,
+}
+
+impl Tag {
+    pub fn parse(raw: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        let (headers, message) = key_value_parse(raw)?;
+        let mut headers = headers.into_iter().peekable();
+        let mut take = |key: &str| headers.next_if(|(k, _)| k == key).map(|(_, v)| v);
+
+        let object = take("object").ok_or(Error::MalformedObject(String::from("tag has no object")))?;
+        let kind = take("type").ok_or(Error::MalformedObject(String::from("tag has no type")))?;
+        let name = take("tag").ok_or(Error::MalformedObject(String::from("tag has no name")))?;
+        let tagger = take("tagger").map(|tagger| Identity::lenient(&tagger));
+        Ok(Tag {
+            object: ObjectId::from_hex(str::from_utf8(&object)?, algorithm)?,
+            kind: str::from_utf8(&kind)?.parse()?,
+            name: String::from_utf8(name)?,
+            tagger,
+            extra_headers: headers.collect(),
+            message,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut headers = vec![
+            (String::from("object"), self.object.to_string().into_bytes()),
+            (String::from("type"), self.kind.to_string().into_bytes()),
+            (String::from("tag"), self.name.clone().into_bytes()),
+        ];
+        headers.extend(self.tagger.iter().map(|tagger| (String::from("tagger"), tagger.to_bytes())));
+        headers.extend(self.extra_headers.iter().cloned());
+        key_value_serialize(&headers, self.message.as_deref())
+    }
+}
@@ -0,0 +1,471 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+use flate2::{
+    read::ZlibDecoder,
+    write::ZlibEncoder,
+    Compression,
+    Crc,
+};
+use crate::error::{Error, Result};
+use crate::cli::Format;
+use crate::hash::{HashAlgorithm, Hasher, ObjectId, ObjectIdPrefix, to_hex};
+use crate::object::delta::{self, DeltaObject, DeltaOptions};
+
+const IDX_MAGIC: &[u8] = b"\xfftOc";
+const IDX_HEADER_LEN: usize = 8;
+const FANOUT_LEN: usize = 256 * 4;
+
+const OBJ_COMMIT: u8 = 1;
+const OBJ_TREE: u8 = 2;
+const OBJ_BLOB: u8 = 3;
+const OBJ_TAG: u8 = 4;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// The longest delta chain `read_at` follows before deciding the pack loops back on itself
+const MAX_DELTA_CHAIN: usize = 10_000;
+/// The most `inflate` reserves up front; larger entries grow as they are read
+const MAX_PREALLOC: u64 = 1 << 20;
+
+/// A version 2 `.idx` file and the `.pack` it describes.
+///
+/// The layout is a header, a 256 entry fanout table of cumulative counts by first digest
+/// byte, the sorted digests, their CRC32s, 4 byte offsets (with the MSB pointing into a
+/// table of 8 byte offsets for large packs), then the pack and index checksums.
+#[derive(Debug)]
+pub struct PackIndex {
+    pub pack_path: PathBuf,
+    data: Vec<u8>,
+    algorithm: HashAlgorithm,
+    digest_len: usize,
+    count: usize,
+}
+
+impl PackIndex {
+    pub fn open(idx_path: &Path, algorithm: HashAlgorithm) -> Result<Self> {
+        let data = fs::read(idx_path)?;
+        let digest_len = algorithm.digest_len();
+        if data.len() < IDX_HEADER_LEN + FANOUT_LEN || &data[0..4] != IDX_MAGIC {
+            return Err(Error::MalformedPack(format!("{:?} is not a version 2 pack index", idx_path)));
+        }
+        if read_u32(&data, 4) != 2 {
+            return Err(Error::MalformedPack(format!("{:?} has an unsupported index version", idx_path)));
+        }
+        let mut previous = 0;
+        for byte in 0..256 {
+            let total = read_u32(&data, IDX_HEADER_LEN + byte * 4);
+            if total < previous {
+                return Err(Error::MalformedPack(format!("{:?} has a fanout table that goes backwards", idx_path)));
+            }
+            previous = total;
+        }
+        let count = previous as usize;
+        let min_len = IDX_HEADER_LEN + FANOUT_LEN + count * (digest_len + 8) + 2 * digest_len;
+        if data.len() < min_len {
+            return Err(Error::MalformedPack(format!("{:?} is truncated", idx_path)));
+        }
+        // Whatever lies between the 4 byte offsets and the checksums is the 8 byte offset table
+        let offsets = IDX_HEADER_LEN + FANOUT_LEN + count * (digest_len + 4);
+        let large_count = (data.len() - min_len) / 8;
+        for i in 0..count {
+            let offset = read_u32(&data, offsets + i * 4);
+            if offset & 0x8000_0000 != 0 && (offset & 0x7fff_ffff) as usize >= large_count {
+                return Err(Error::MalformedPack(format!("{:?} has an offset outside its large offset table", idx_path)));
+            }
+        }
+        Ok(PackIndex { pack_path: idx_path.with_extension("pack"), data, algorithm, digest_len, count })
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn fanout(&self, byte: usize) -> usize {
+        read_u32(&self.data, IDX_HEADER_LEN + byte * 4) as usize
+    }
+
+    /// Raw digest of the `i`th object in sorted order
+    fn digest_at(&self, i: usize) -> &[u8] {
+        let start = IDX_HEADER_LEN + FANOUT_LEN + i * self.digest_len;
+        &self.data[start..start + self.digest_len]
+    }
+
+    /// Id of the `i`th object in sorted order
+    pub fn id_at(&self, i: usize) -> ObjectId {
+        ObjectId::from_bytes(self.digest_at(i), self.algorithm).unwrap()
+    }
+
+    /// Offset of the `i`th object within the pack
+    pub fn offset_at(&self, i: usize) -> u64 {
+        let offsets = IDX_HEADER_LEN + FANOUT_LEN + self.count * (self.digest_len + 4);
+        let offset = read_u32(&self.data, offsets + i * 4);
+        if offset & 0x8000_0000 == 0 {
+            return offset as u64;
+        }
+        let large_offsets = offsets + self.count * 4;
+        let idx = large_offsets + (offset & 0x7fff_ffff) as usize * 8;
+        ((read_u32(&self.data, idx) as u64) << 32) | read_u32(&self.data, idx + 4) as u64
+    }
+
+    /// Range of sorted positions whose digest starts with `byte`
+    fn bucket(&self, byte: u8) -> (usize, usize) {
+        let start = if byte == 0 { 0 } else { self.fanout(byte as usize - 1) };
+        (start, self.fanout(byte as usize))
+    }
+
+    /// Pack offset of the object `sha`, if this pack contains it
+    pub fn find(&self, sha: &ObjectId) -> Option<u64> {
+        if sha.algorithm() != self.algorithm {
+            return None;
+        }
+        let digest = sha.as_bytes();
+        let (mut lo, mut hi) = self.bucket(digest[0]);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.digest_at(mid).cmp(digest) {
+                std::cmp::Ordering::Equal => return Some(self.offset_at(mid)),
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        None
+    }
+
+    /// Every object in this pack whose id starts with `prefix`
+    pub fn find_prefix(&self, prefix: &ObjectIdPrefix) -> Vec<ObjectId> {
+        let (lo, hi) = self.bucket(prefix.first_byte());
+        (lo..hi)
+            .map(|i| self.id_at(i))
+            .filter(|sha| prefix.matches(sha))
+            .collect()
+    }
+
+    /// Ids of every object in the pack, in sorted order
+    pub fn ids(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        (0..self.count).map(|i| self.id_at(i))
+    }
+
+    /// Read and fully resolve the object stored at `offset`. Bases of REF_DELTA entries
+    /// that live outside this pack are looked up through `resolve_ref`.
+    pub fn read_at<F>(&self, offset: u64, resolve_ref: &F) -> Result<(Format, Vec<u8>)>
+    where F: Fn(&ObjectId) -> Result<(Format, Vec<u8>)>
+    {
+        // Walk down the chain to its base, then apply the deltas on the way back up
+        let mut deltas: Vec<Vec<u8>> = Vec::new();
+        let mut entry = offset;
+        let (format, mut data) = loop {
+            if deltas.len() > MAX_DELTA_CHAIN {
+                return Err(Error::MalformedPack(format!("delta chain at {} is longer than {} entries", offset, MAX_DELTA_CHAIN)));
+            }
+            let (kind, size, mut reader) = self.entry_at(entry)?;
+            match kind {
+                OBJ_COMMIT | OBJ_TREE | OBJ_BLOB | OBJ_TAG => break (format_of(kind)?, inflate(&mut reader, size)?),
+                OBJ_OFS_DELTA => {
+                    let malformed = || Error::MalformedPack(format!("delta at {} does not point back to an earlier entry", entry));
+                    let mut byte = read_byte(&mut reader)?;
+                    let mut distance = (byte & 0x7f) as u64;
+                    while byte & 0x80 != 0 {
+                        byte = read_byte(&mut reader)?;
+                        distance = distance.checked_add(1)
+                            .and_then(|distance| distance.checked_mul(0x80))
+                            .ok_or_else(malformed)? | (byte & 0x7f) as u64;
+                    }
+                    // A distance of 0 would make the entry its own base
+                    let base_offset = entry.checked_sub(distance)
+                        .filter(|base_offset| *base_offset < entry)
+                        .ok_or_else(malformed)?;
+                    deltas.push(inflate(&mut reader, size)?);
+                    entry = base_offset;
+                },
+                OBJ_REF_DELTA => {
+                    let mut digest = vec![0; self.digest_len];
+                    reader.read_exact(&mut digest)?;
+                    deltas.push(inflate(&mut reader, size)?);
+                    let base_sha = ObjectId::from_bytes(&digest, self.algorithm)?;
+                    match self.find(&base_sha) {
+                        Some(base_offset) => entry = base_offset,
+                        None => break resolve_ref(&base_sha)?,
+                    }
+                },
+                other => return Err(Error::MalformedPack(format!("unknown pack entry type {} at {}", other, entry))),
+            }
+        };
+        for delta in deltas.iter().rev() {
+            data = delta::apply(&data, delta)?;
+        }
+        Ok((format, data))
+    }
+
+    /// Stream the body of the entry at `offset` when it is stored whole, returning its type
+    /// and size. Deltas need their base in memory, so `None` is returned for them.
+    pub fn open_at(&self, offset: u64) -> Result<Option<(Format, u64, impl Read + use<>)>> {
+        let (kind, size, reader) = self.entry_at(offset)?;
+        match kind {
+            OBJ_COMMIT | OBJ_TREE | OBJ_BLOB | OBJ_TAG => {
+                Ok(Some((format_of(kind)?, size, ZlibDecoder::new(reader).take(size))))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Parse the type and size header of the entry at `offset`, leaving the reader at the
+    /// start of what follows it
+    fn entry_at(&self, offset: u64) -> Result<(u8, u64, BufReader<File>)> {
+        let mut reader = BufReader::new(File::open(&self.pack_path)?);
+        reader.seek(SeekFrom::Start(offset))?;
+
+        let mut byte = read_byte(&mut reader)?;
+        let kind = (byte >> 4) & 0x7;
+        let mut size = (byte & 0x0f) as u64;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            if shift >= u64::BITS {
+                return Err(Error::MalformedPack(format!("entry at {} has an oversized length", offset)));
+            }
+            byte = read_byte(&mut reader)?;
+            size |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+        }
+        Ok((kind, size, reader))
+    }
+}
+
+/// Every `.idx` under `objects/pack`, each opened on its own so one that is damaged
+/// doesn't hide the others
+pub fn list_packs(pack_dir: &Path, algorithm: HashAlgorithm) -> Result<Vec<(PathBuf, Result<PackIndex>)>> {
+    if !pack_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut paths = pack_dir.read_dir()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    Ok(paths.into_iter()
+        .map(|path| {
+            let index = PackIndex::open(&path, algorithm);
+            (path, index)
+        })
+        .collect())
+}
+
+/// An object queued for packing
+pub struct PackObject {
+    pub sha: ObjectId,
+    pub format: Format,
+    pub data: Vec<u8>,
+    /// The worktree path the object was reached through, used to pair delta bases
+    pub path: Option<String>,
+}
+
+/// Write `objects` into `pack_dir` as `pack-<checksum>.pack` with a matching version 2 `.idx`,
+/// returning the index path. Objects are stored as OFS_DELTA entries wherever the delta
+/// search in `options` finds a base that saves space.
+pub fn write_pack(pack_dir: &Path, objects: Vec<PackObject>, algorithm: HashAlgorithm, options: &DeltaOptions) -> Result<PathBuf> {
+    fs::create_dir_all(pack_dir)?;
+    let plan = delta::plan(
+        &objects.iter()
+            .map(|object| DeltaObject { format: object.format.clone(), data: &object.data, path: object.path.as_deref() })
+            .collect::<Vec<DeltaObject>>(),
+        options,
+    );
+
+    static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let suffix = format!("{}_{}", std::process::id(), TMP_COUNTER.fetch_add(1, AtomicOrdering::Relaxed));
+    let tmp_pack = pack_dir.join(format!("tmp_pack_{}", suffix));
+    let tmp_idx = pack_dir.join(format!("tmp_idx_{}", suffix));
+
+    let result = (|| -> Result<PathBuf> {
+        let mut writer = HashingWriter::new(File::create(&tmp_pack)?, algorithm);
+        writer.write_all(b"PACK")?;
+        writer.write_all(&2u32.to_be_bytes())?;
+        writer.write_all(&(objects.len() as u32).to_be_bytes())?;
+
+        let mut offsets: Vec<u64> = vec![0; objects.len()];
+        let mut crcs: Vec<u32> = vec![0; objects.len()];
+        for &i in &plan.order {
+            let object = &objects[i];
+            let offset = writer.offset;
+            let entry = match &plan.deltas[i] {
+                Some((base_idx, delta)) => {
+                    let mut entry = entry_header(OBJ_OFS_DELTA, delta.len());
+                    entry.extend(encode_ofs_distance(offset - offsets[*base_idx]));
+                    entry.extend(deflate(delta)?);
+                    entry
+                },
+                None => {
+                    let mut entry = entry_header(kind_of(&object.format), object.data.len());
+                    entry.extend(deflate(&object.data)?);
+                    entry
+                },
+            };
+            let mut crc = Crc::new();
+            crc.update(&entry);
+            writer.write_all(&entry)?;
+            offsets[i] = offset;
+            crcs[i] = crc.sum();
+        }
+        let checksum = writer.finish()?;
+        let name = format!("pack-{}", to_hex(&checksum));
+        let pack_path = pack_dir.join(format!("{}.pack", name));
+        fs::rename(&tmp_pack, &pack_path)?;
+
+        // The index is written last so readers never see an index without its pack
+        let mut order = (0..objects.len()).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| objects[a].sha.cmp(&objects[b].sha));
+        let digests = order.iter()
+            .map(|&i| objects[i].sha.as_bytes())
+            .collect::<Vec<&[u8]>>();
+
+        let mut writer = HashingWriter::new(File::create(&tmp_idx)?, algorithm);
+        writer.write_all(IDX_MAGIC)?;
+        writer.write_all(&2u32.to_be_bytes())?;
+        let mut fanout = [0u32; 256];
+        digests.iter().for_each(|digest| fanout[digest[0] as usize] += 1);
+        let mut total = 0;
+        for count in fanout {
+            total += count;
+            writer.write_all(&total.to_be_bytes())?;
+        }
+        for digest in &digests {
+            writer.write_all(digest)?;
+        }
+        for &i in &order {
+            writer.write_all(&crcs[i].to_be_bytes())?;
+        }
+        let mut large_offsets: Vec<u64> = Vec::new();
+        for &i in &order {
+            if offsets[i] < 0x8000_0000 {
+                writer.write_all(&(offsets[i] as u32).to_be_bytes())?;
+            } else {
+                writer.write_all(&(0x8000_0000 | large_offsets.len() as u32).to_be_bytes())?;
+                large_offsets.push(offsets[i]);
+            }
+        }
+        for offset in large_offsets {
+            writer.write_all(&offset.to_be_bytes())?;
+        }
+        writer.write_all(&checksum)?;
+        writer.finish()?;
+        let idx_path = pack_dir.join(format!("{}.idx", name));
+        fs::rename(&tmp_idx, &idx_path)?;
+        Ok(idx_path)
+    })();
+    if result.is_err() {
+        _ = fs::remove_file(&tmp_pack);
+        _ = fs::remove_file(&tmp_idx);
+    }
+    result
+}
+
+fn entry_header(kind: u8, size: usize) -> Vec<u8> {
+    let mut header = vec![(kind << 4) | (size & 0x0f) as u8];
+    let mut size = size >> 4;
+    while size > 0 {
+        *header.last_mut().unwrap() |= 0x80;
+        header.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
+    header
+}
+
+pub(crate) fn encode_ofs_distance(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Writes through to a file while hashing and counting every byte
+struct HashingWriter {
+    inner: BufWriter<File>,
+    hasher: Hasher,
+    offset: u64,
+}
+
+impl HashingWriter {
+    fn new(file: File, algorithm: HashAlgorithm) -> Self {
+        HashingWriter { inner: BufWriter::new(file), hasher: Hasher::new(algorithm), offset: 0 }
+    }
+
+    /// Append the checksum of everything written so far and flush to disk
+    fn finish(mut self) -> Result<Vec<u8>> {
+        let checksum = self.hasher.finalize();
+        self.inner.write_all(&checksum)?;
+        self.inner.flush()?;
+        self.inner.get_ref().sync_all()?;
+        Ok(checksum)
+    }
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn kind_of(format: &Format) -> u8 {
+    match format {
+        Format::Commit => OBJ_COMMIT,
+        Format::Tree => OBJ_TREE,
+        Format::Blob => OBJ_BLOB,
+        Format::Tag => OBJ_TAG,
+    }
+}
+
+fn format_of(kind: u8) -> Result<Format> {
+    match kind {
+        OBJ_COMMIT => Ok(Format::Commit),
+        OBJ_TREE => Ok(Format::Tree),
+        OBJ_BLOB => Ok(Format::Blob),
+        OBJ_TAG => Ok(Format::Tag),
+        other => Err(Error::MalformedPack(format!("{} is not a base object type", other))),
+    }
+}
+
+fn inflate<R: Read>(reader: &mut R, size: u64) -> Result<Vec<u8>> {
+    // The size comes from the pack, so it is only trusted as far as the data bears it out
+    let mut data = Vec::with_capacity(size.min(MAX_PREALLOC) as usize);
+    ZlibDecoder::new(reader).take(size.saturating_add(1)).read_to_end(&mut data)?;
+    if data.len() as u64 != size {
+        return Err(Error::MalformedPack(String::from("inflated entry has the wrong size")));
+    }
+    Ok(data)
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(data: &[u8], idx: usize) -> u32 {
+    u32::from_be_bytes([data[idx], data[idx + 1], data[idx + 2], data[idx + 3]])
+}
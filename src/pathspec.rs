@@ -0,0 +1,187 @@
+use std::path::{Component, Path, PathBuf};
+use crate::error::{Error, Result};
+
+/// Paths and wildcard patterns given on the command line, resolved against the worktree.
+///
+/// A plain path matches itself and everything beneath it; `.` at the root of the worktree
+/// matches everything. A pattern containing `*`, `?` or `[` is matched against the whole
+/// path, with wildcards free to cross `/` as in git.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pathspec {
+    patterns: Vec<String>,
+}
+
+impl Pathspec {
+    /// Resolve `specs`, which are relative to `cwd`, into patterns relative to `worktree`
+    pub fn new(worktree: &Path, cwd: &Path, specs: &[String]) -> Result<Self> {
+        let worktree = normalize(&worktree.canonicalize()?);
+        let cwd = normalize(&cwd.canonicalize()?);
+        let patterns = specs.iter()
+            .map(|spec| {
+                let absolute = normalize(&cwd.join(spec));
+                let relative = absolute.strip_prefix(&worktree)
+                    .map_err(|_| Error::InvalidFilePath(absolute.clone()))?;
+                Ok(relative.components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .collect::<Vec<String>>()
+                    .join("/"))
+            })
+            .collect::<Result<Vec<String>>>()?;
+        Ok(Pathspec { patterns })
+    }
+
+    /// Patterns that are already relative to the worktree root
+    pub fn from_patterns(patterns: Vec<String>) -> Self {
+        Pathspec { patterns }
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether any pattern selects `path`, which is slash separated and relative to the
+    /// worktree root
+    pub fn matches(&self, path: &str) -> bool {
+        self.patterns.iter().any(|pattern| matches_pattern(pattern, path))
+    }
+}
+
+/// Whether a single resolved pattern selects `path`
+pub fn matches_pattern(pattern: &str, path: &str) -> bool {
+    if pattern.is_empty() {
+        return true;
+    }
+    if pattern.contains(['*', '?', '[']) {
+        return wildmatch(pattern.as_bytes(), path.as_bytes(), false);
+    }
+    path == pattern || path.strip_prefix(pattern).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Match `text` against a shell glob with `*`, `?`, `[...]` classes and `\` escapes.
+///
+/// With `pathname` set, `*`, `?` and classes never match `/`, while `**` between slashes
+/// matches any number of directories, as in gitignore files. Without it every wildcard
+/// matches `/` like a pathspec does.
+pub fn wildmatch(pattern: &[u8], text: &[u8], pathname: bool) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the most recent `*`: the pattern after it and the text it ate to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    let double = pattern.get(p + 1) == Some(&b'*');
+                    if pathname && double {
+                        let at_start = p == 0 || pattern[p - 1] == b'/';
+                        let mut rest = p + 2;
+                        while pattern.get(rest) == Some(&b'*') {
+                            rest += 1;
+                        }
+                        if at_start && (rest == pattern.len() || pattern[rest] == b'/') {
+                            // `**/` may also match no directories at all
+                            let skip = if rest < pattern.len() { rest + 1 } else { rest };
+                            return (t..=text.len())
+                                .filter(|&i| i == t || text[i - 1] == b'/' || skip == pattern.len())
+                                .any(|i| wildmatch(&pattern[skip..], &text[i..], pathname));
+                        }
+                    }
+                    while pattern.get(p) == Some(&b'*') {
+                        p += 1;
+                    }
+                    backtrack = Some((p, t));
+                    continue;
+                },
+                b'?' if !(pathname && text[t] == b'/') => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                },
+                b'[' => {
+                    if let Some((matched, len)) = match_class(&pattern[p..], text[t])
+                        && matched
+                        && !(pathname && text[t] == b'/')
+                    {
+                        p += len;
+                        t += 1;
+                        continue;
+                    }
+                },
+                b'\\' if p + 1 < pattern.len() && pattern[p + 1] == text[t] => {
+                    p += 2;
+                    t += 1;
+                    continue;
+                },
+                // An escaped character that differs must not match the backslash itself
+                b'\\' if p + 1 < pattern.len() => {},
+                c if c == text[t] => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                },
+                _ => {},
+            }
+        }
+        // Mismatch: let the last `*` swallow one more character, unless it would cross a `/`
+        match backtrack {
+            Some((star_p, star_t)) if !(pathname && text[star_t] == b'/') => {
+                backtrack = Some((star_p, star_t + 1));
+                p = star_p;
+                t = star_t + 1;
+            },
+            _ => return false,
+        }
+    }
+    while pattern.get(p) == Some(&b'*') {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+/// Match one byte against the class at the start of `pattern`, returning whether it
+/// matched and how long the class is, or `None` if the class is never closed
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some(b'!') | Some(b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(i)?;
+        if start == b']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&end| end != b']') {
+            if (start..=pattern[i + 2]).contains(&c) {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if start == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Resolve `.` and `..` without touching the filesystem, since the path may not exist
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,43 @@
+use crate::error::{Error, Result};
+use crate::hash::{HashAlgorithm, ObjectId};
+use crate::object::commit::Identity;
+
+/// One update of a ref as recorded in `.minit/logs/<ref>`, written as
+/// `<old> <new> <name> <<email>> <time> <tz>\t<message>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// What the ref held before, all zeros if it didn't exist
+    pub old: ObjectId,
+    pub new: ObjectId,
+    /// Who moved the ref, and when
+    pub identity: Identity,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    pub fn parse(line: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        let malformed = || Error::MalformedObject(format!("invalid reflog entry {:?}", String::from_utf8_lossy(line)));
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let (head, message) = match line.iter().position(|&c| c == b'\t') {
+            Some(tab) => (&line[..tab], &line[tab + 1..]),
+            None => (line, &b""[..]),
+        };
+        let len = algorithm.hex_len();
+        if head.len() < 2 * len + 2 || head[len] != b' ' || head[2 * len + 1] != b' ' {
+            return Err(malformed());
+        }
+        let old = ObjectId::from_hex(str::from_utf8(&head[..len])?, algorithm).map_err(|_| malformed())?;
+        let new = ObjectId::from_hex(str::from_utf8(&head[len + 1..2 * len + 1])?, algorithm).map_err(|_| malformed())?;
+        let identity = Identity::parse(&head[2 * len + 2..])?;
+        Ok(ReflogEntry { old, new, identity, message: String::from_utf8_lossy(message).into_owned() })
+    }
+
+    /// The entry as a line of a reflog, without the trailing newline
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut line = format!("{} {} ", self.old, self.new).into_bytes();
+        line.extend_from_slice(&self.identity.to_bytes());
+        line.push(b'\t');
+        line.extend_from_slice(self.message.as_bytes());
+        line
+    }
+}
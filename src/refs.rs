@@ -0,0 +1,393 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use crate::error::{Error, Result};
+use crate::hash::{HashAlgorithm, ObjectId};
+use crate::object::Object;
+use crate::repository::{check_ref_format, Repository};
+
+/// The header minit writes at the top of `packed-refs`: every annotated tag is followed
+/// by the object it peels to, and the refs are sorted by name
+const PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+/// What a ref file holds: an object, or another ref as in `ref: refs/heads/main`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefValue {
+    Object(ObjectId),
+    Symbolic(String),
+}
+
+/// What a ref must resolve to for a transaction to touch it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    /// Whatever it holds, or nothing
+    Any,
+    /// The ref must not exist yet
+    Missing,
+    Value(ObjectId),
+}
+
+impl Expected {
+    /// `Missing` for `None`, as `update_ref` takes it
+    pub fn from_option(expected: Option<&ObjectId>) -> Self {
+        expected.map_or(Expected::Missing, |sha| Expected::Value(*sha))
+    }
+}
+
+/// A ref stored in `packed-refs` rather than in a file of its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub sha: ObjectId,
+    /// What an annotated tag peels to, from the `^<sha>` line that follows it
+    pub peeled: Option<ObjectId>,
+}
+
+/// The contents of `.minit/packed-refs`, sorted by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackedRefs {
+    pub refs: Vec<PackedRef>,
+}
+
+impl PackedRefs {
+    pub fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        let mut refs: Vec<PackedRef> = Vec::new();
+        let mut sorted = false;
+        for (number, line) in str::from_utf8(data)?.lines().enumerate() {
+            let malformed = |reason: &str| Error::MalformedPackedRefs(format!("line {}: {}", number + 1, reason));
+            if let Some(traits) = line.strip_prefix("# pack-refs with:") {
+                sorted = traits.split_whitespace().any(|word| word == "sorted");
+                continue;
+            }
+            if let Some(peeled) = line.strip_prefix('^') {
+                let last = refs.last_mut().ok_or_else(|| malformed("peeled line without a ref"))?;
+                last.peeled = Some(ObjectId::from_hex(peeled, algorithm).map_err(|_| malformed("invalid peeled object id"))?);
+                continue;
+            }
+            let (sha, name) = line.split_once(' ').ok_or_else(|| malformed("expected '<object id> <ref name>'"))?;
+            let sha = ObjectId::from_hex(sha, algorithm).map_err(|_| malformed("invalid object id"))?;
+            if !check_ref_format(name) {
+                return Err(malformed(&format!("invalid ref name '{}'", name)));
+            }
+            refs.push(PackedRef { name: name.to_string(), sha, peeled: None });
+        }
+        if !sorted {
+            refs.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        Ok(PackedRefs { refs })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = String::from(PACKED_REFS_HEADER);
+        for packed in &self.refs {
+            data.push_str(&format!("{} {}\n", packed.sha, packed.name));
+            if let Some(peeled) = packed.peeled {
+                data.push_str(&format!("^{}\n", peeled));
+            }
+        }
+        data.into_bytes()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PackedRef> {
+        self.refs.binary_search_by(|packed| packed.name.as_str().cmp(name)).ok().map(|i| &self.refs[i])
+    }
+
+    /// Add `packed`, replacing any ref of the same name
+    pub fn insert(&mut self, packed: PackedRef) {
+        match self.refs.binary_search_by(|other| other.name.cmp(&packed.name)) {
+            Ok(i) => self.refs[i] = packed,
+            Err(i) => self.refs.insert(i, packed),
+        }
+    }
+
+    /// Drop `name`, returning whether it was there
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.refs.binary_search_by(|packed| packed.name.as_str().cmp(name)) {
+            Ok(i) => {
+                self.refs.remove(i);
+                true
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+struct RefUpdate {
+    name: String,
+    /// `None` deletes the ref
+    new: Option<RefValue>,
+    expected: Expected,
+    message: String,
+}
+
+/// A `<ref>.lock` file, removed when dropped unless it was renamed into place or
+/// already released
+struct Lock {
+    path: PathBuf,
+    file: File,
+    released: bool,
+}
+
+impl Lock {
+    /// Take the lock on the ref `name` stored at `path` by creating `<path>.lock`
+    fn acquire(path: &Path, name: &str) -> Result<Lock> {
+        let lock_path = PathBuf::from(format!("{}.lock", path.display()));
+        let file = OpenOptions::new().write(true).create_new(true).open(&lock_path)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::AlreadyExists => Error::Refused(format!(
+                    "cannot lock ref '{}': '{}' exists; another process may be updating it", name, lock_path.display(),
+                )),
+                _ => Error::Refused(format!("cannot lock ref '{}': {}", name, err)),
+            })?;
+        Ok(Lock { path: lock_path, file, released: false })
+    }
+
+    /// Write `contents` into the lock file and make sure they are on disk
+    fn write(&mut self, contents: &[u8]) -> Result<()> {
+        self.file.write_all(contents).and_then(|_| self.file.sync_all())?;
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        if !self.released {
+            _ = fs::remove_file(&self.path);
+            self.released = true;
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// An update whose ref is locked and checked, with what the ref held before
+struct Prepared<'u> {
+    update: &'u RefUpdate,
+    lock: Lock,
+    /// The ref file as it was, to put back if the transaction has to be undone
+    raw: Option<Vec<u8>>,
+    old: Option<ObjectId>,
+}
+
+/// A set of ref updates that happen together or not at all. Committing locks every ref
+/// by creating `<ref>.lock`, checks that each still holds what it is expected to, writes
+/// and syncs the new values into the lock files, and only then renames them into place.
+/// If anything fails before that the refs are untouched; if a rename fails, the refs
+/// already moved are put back. Updated refs are written as loose files, which shadow
+/// `packed-refs`; deleted refs are removed from both.
+pub struct RefTransaction<'a> {
+    repo: &'a Repository,
+    updates: Vec<RefUpdate>,
+}
+
+impl<'a> RefTransaction<'a> {
+    pub fn new(repo: &'a Repository) -> Self {
+        RefTransaction { repo, updates: Vec::new() }
+    }
+
+    /// Point `name` at `new`, logging `message` in its reflog
+    pub fn update(&mut self, name: &str, new: RefValue, expected: Expected, message: &str) -> &mut Self {
+        self.updates.push(RefUpdate { name: name.to_string(), new: Some(new), expected, message: message.to_string() });
+        self
+    }
+
+    /// Delete `name` along with its reflog
+    pub fn delete(&mut self, name: &str, expected: Expected) -> &mut Self {
+        self.updates.push(RefUpdate { name: name.to_string(), new: None, expected, message: String::new() });
+        self
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        let repo = self.repo;
+        self.updates.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(pair) = self.updates.windows(2).find(|pair| pair[0].name == pair[1].name) {
+            return Err(Error::Refused(format!("multiple updates for ref '{}' not allowed", pair[0].name)));
+        }
+        if let Some(update) = self.updates.iter().find(|update| update.name != "HEAD" && !(update.name.starts_with("refs/") && check_ref_format(&update.name))) {
+            return Err(Error::Refused(format!("'{}' is not a valid ref name", update.name)));
+        }
+        let head = repo.head_ref()?;
+
+        // Lock and check every ref before any of them changes
+        let mut prepared: Vec<Prepared> = Vec::new();
+        let mut packed = repo.packed_refs()?;
+        let deleted = self.updates.iter().filter(|update| update.new.is_none()).map(|update| update.name.as_str()).collect::<Vec<&str>>();
+        for update in &self.updates {
+            let path = repo.repo_path(update.name.split('/').collect());
+            let in_packed = packed.get(&update.name).map(|packed| packed.sha);
+            if (update.new.is_some() || in_packed.is_some()) && let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|_| Error::Refused(format!("cannot lock ref '{}': a ref is in the way of its directory", update.name)))?;
+            }
+            let nested = |a: &str, b: &str| a.strip_prefix(b).is_some_and(|rest| rest.starts_with('/'));
+            let packed_conflict = packed.refs.iter()
+                .find(|other| !deleted.contains(&other.name.as_str())
+                    && (nested(&update.name, &other.name) || nested(&other.name, &update.name)));
+            if update.new.is_some() && let Some(other) = packed_conflict {
+                return Err(Error::Refused(format!("cannot lock ref '{}': '{}' exists", update.name, other.name)));
+            }
+            if path.is_dir() {
+                return Err(Error::Refused(format!("cannot lock ref '{}': there are refs under it", update.name)));
+            }
+            let mut lock = Lock::acquire(&path, &update.name)?;
+
+            let raw = match fs::read(&path) {
+                Ok(raw) => Some(raw),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err.into()),
+            };
+            let current = match raw {
+                Some(_) => repo.resolve_ref(&update.name).ok(),
+                None => in_packed,
+            };
+            let exists = raw.is_some() || in_packed.is_some();
+            let matches = match update.expected {
+                Expected::Any => true,
+                Expected::Missing => !exists,
+                Expected::Value(sha) => current == Some(sha),
+            };
+            if !matches {
+                let found = match (exists, current) {
+                    (false, _) => String::from("missing"),
+                    (true, Some(sha)) => format!("at {}", sha),
+                    (true, None) => String::from("unborn"),
+                };
+                let expected = match update.expected {
+                    Expected::Value(sha) => format!("{}", sha),
+                    _ => String::from("it not to exist"),
+                };
+                return Err(Error::Refused(format!("cannot lock ref '{}': it is {} but expected {}", update.name, found, expected)));
+            }
+            if let Some(new) = &update.new {
+                let contents = match new {
+                    RefValue::Object(sha) => format!("{}\n", sha),
+                    RefValue::Symbolic(target) => format!("ref: {}\n", target),
+                };
+                lock.write(contents.as_bytes())?;
+            }
+            prepared.push(Prepared { update, lock, raw, old: current });
+        }
+
+        // Deleted refs that are packed come out of packed-refs, under its own lock
+        let packed_path = repo.repo_path(vec!["packed-refs"]);
+        let mut packed_lock = None;
+        if deleted.iter().any(|name| packed.get(name).is_some()) {
+            let mut lock = Lock::acquire(&packed_path, "packed-refs")?;
+            let raw = fs::read(&packed_path).ok();
+            for name in &deleted {
+                packed.remove(name);
+            }
+            lock.write(&packed.serialize())?;
+            packed_lock = Some((lock, raw));
+        }
+
+        // Every ref is locked and checked, so this is where they move
+        let mut done: Vec<(PathBuf, &Option<Vec<u8>>)> = Vec::new();
+        if let Some((lock, raw)) = packed_lock.as_mut() {
+            fs::rename(&lock.path, &packed_path)?;
+            lock.released = true;
+            done.push((packed_path.clone(), raw));
+        }
+        for Prepared { update, lock, raw, .. } in prepared.iter_mut() {
+            let path = repo.repo_path(update.name.split('/').collect());
+            let result = match update.new {
+                Some(_) => fs::rename(&lock.path, &path).inspect(|_| lock.released = true),
+                None => match raw {
+                    Some(_) => fs::remove_file(&path),
+                    None => Ok(()),
+                },
+            };
+            if let Err(err) = result {
+                for (path, raw) in done.into_iter().rev() {
+                    _ = match raw {
+                        Some(raw) => fs::write(&path, raw),
+                        None => fs::remove_file(&path),
+                    };
+                }
+                return Err(err.into());
+            }
+            done.push((path, raw));
+        }
+
+        for Prepared { update, lock, old, .. } in prepared.iter_mut() {
+            let Some(new) = &update.new else {
+                lock.release();
+                repo.delete_reflog(&update.name)?;
+                remove_empty_dirs(&repo.repo_path(update.name.split('/').collect()), &repo.repo_path(vec!["refs"]));
+                continue;
+            };
+            let new = match new {
+                RefValue::Object(sha) => Some(*sha),
+                RefValue::Symbolic(target) => repo.resolve_ref(target).ok(),
+            };
+            if let Some(new) = new {
+                repo.append_reflog(&update.name, old.as_ref(), &new, &update.message)?;
+                // HEAD's log follows the branch it points at, unless HEAD moved too
+                let head_moved = self.updates.iter().any(|update| update.name == "HEAD");
+                if !head_moved && head.as_deref() == Some(update.name.as_str()) {
+                    repo.append_reflog("HEAD", old.as_ref(), &new, &update.message)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove the directories above `file` that are left empty, up to but not including the
+/// directories directly under `root` like `refs/heads`
+pub(crate) fn remove_empty_dirs(file: &Path, root: &Path) {
+    let mut dir = file.parent();
+    while let Some(parent) = dir.filter(|parent| parent.strip_prefix(root).is_ok_and(|rest| rest.components().count() > 1)) {
+        if fs::remove_dir(parent).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+}
+
+/// Move the loose refs into `packed-refs`: every ref with `all`, otherwise tags and refs
+/// that are already packed. Annotated tags are written with what they peel to. With
+/// `prune` the loose files are then removed, unless they changed in the meantime.
+/// Symbolic refs always stay loose.
+pub(crate) fn pack_refs(repo: &Repository, all: bool, prune: bool) -> Result<()> {
+    let packed_path = repo.repo_path(vec!["packed-refs"]);
+    let mut packed_lock = Lock::acquire(&packed_path, "packed-refs")?;
+    let mut packed = repo.packed_refs()?;
+    let mut loose = Vec::new();
+    for name in repo.loose_ref_names()? {
+        let raw = fs::read(repo.repo_path(name.split('/').collect()))?;
+        let Ok(sha) = ObjectId::from_hex(str::from_utf8(&raw)?.trim_end(), repo.algorithm()) else {
+            continue;
+        };
+        if all || name.starts_with("refs/tags/") || packed.get(&name).is_some() {
+            let peeled = match repo.read_object(&sha)? {
+                Object::Tag(_) => Some(repo.peel(&sha, None)?),
+                _ => None,
+            };
+            packed.insert(PackedRef { name: name.clone(), sha, peeled });
+            loose.push((name, raw));
+        }
+    }
+    packed_lock.write(&packed.serialize())?;
+    fs::rename(&packed_lock.path, &packed_path)?;
+    packed_lock.released = true;
+
+    if prune {
+        for (name, raw) in loose {
+            let path = repo.repo_path(name.split('/').collect());
+            let Ok(mut lock) = Lock::acquire(&path, &name) else {
+                continue;
+            };
+            if fs::read(&path).is_ok_and(|current| current == raw) {
+                fs::remove_file(&path)?;
+            }
+            lock.release();
+            remove_empty_dirs(&path, &repo.repo_path(vec!["refs"]));
+        }
+    }
+    Ok(())
+}
@@ -1,32 +1,120 @@
 use core::str;
 use std::{
-
-    fs::{self, DirEntry, OpenOptions}, io::{Read, Write}, path::{Path, PathBuf}
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet},
+    fs::{self, DirEntry, File, OpenOptions},
+    io::{BufReader, BufWriter, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+    time::{SystemTime, UNIX_EPOCH},
 };
 use crate::error::{
     Error,
     Result
 };
-use regex::Regex;
 use flate2::{
     write::ZlibEncoder,
     read::ZlibDecoder,
     Compression
 };
 use indexmap::IndexMap;
-use crate::object::Object;
+use crate::object::{self, Leaf, Object, commit::{Commit, Identity}, delta::DeltaOptions, tag::Tag};
 use crate::cli::Format;
+use crate::hash::{HashAlgorithm, Hasher, ObjectId, ObjectIdPrefix};
+use crate::pack::{self, PackIndex, PackObject};
+use crate::fsck::{self, FsckIssue, FsckReport};
+use crate::index::{self, Index, IndexChange, IndexEntry};
+use crate::pathspec::{self, Pathspec};
+use crate::ignore::Ignore;
+use crate::status::{Change, Status, StatusEntry, UntrackedFiles, Version};
+use crate::reflog::ReflogEntry;
+use crate::refs::{self, Expected, PackedRefs, RefTransaction, RefValue};
+use crate::revision::{PeelTarget, ReflogSelector, Revision};
 use configparser::ini::Ini;
+use regex::Regex;
+
+/// How `Repository::add` picks and stages paths
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Stage the whole worktree when no pathspec is given
+    pub all: bool,
+    /// Only refresh or remove paths that are already staged, never add new ones
+    pub update: bool,
+    /// Work out what would change without touching the index or the object database
+    pub dry_run: bool,
+    /// Record new paths as intended to be added, without staging their content yet
+    pub intent_to_add: bool,
+    /// Add files even if they are ignored
+    pub force: bool,
+}
+
+/// How `Repository::rm` removes paths
+#[derive(Debug, Clone, Default)]
+pub struct RmOptions {
+    /// Only unstage, leaving the worktree files in place
+    pub cached: bool,
+    /// Allow a directory to remove everything staged beneath it
+    pub recursive: bool,
+    /// Remove even when staged or local changes would be lost
+    pub force: bool,
+}
+
+/// How `Repository::commit` builds the new commit
+#[derive(Debug, Clone, Default)]
+pub struct CommitOptions {
+    /// Replace the commit HEAD points at instead of adding a child to it
+    pub amend: bool,
+    /// Commit even if the tree is the same as the parent's
+    pub allow_empty: bool,
+    /// Stage changes and deletions of tracked files first, like `add -u`
+    pub all: bool,
+}
+
+/// What `Repository::ls_files` lists. With none of them set it lists the staged paths.
+#[derive(Debug, Clone, Default)]
+pub struct LsFilesOptions {
+    /// Staged paths
+    pub cached: bool,
+    /// Staged entries as `<mode> <id> <stage>\t<path>`
+    pub stage: bool,
+    /// Staged paths missing from the worktree
+    pub deleted: bool,
+    /// Staged paths whose worktree file differs, including deleted ones
+    pub modified: bool,
+    /// Untracked files
+    pub others: bool,
+    /// Leave ignored files out of `others`
+    pub exclude_standard: bool,
+    /// Only entries with unresolved conflicts, in the `stage` format
+    pub unmerged: bool,
+}
+
+/// How `Repository::checkout` switches the worktree to another commit
+#[derive(Debug, Clone, Default)]
+pub struct CheckoutOptions {
+    /// Throw away local changes to tracked files instead of refusing to switch
+    pub force: bool,
+    /// Create this branch at the target and switch to it
+    pub new_branch: Option<String>,
+    /// Detach HEAD at the target even if it names a branch
+    pub detach: bool,
+}
 
 #[derive(Debug)]
 pub struct Repository {
     worktree: PathBuf,
     pub minit_dir: PathBuf,
-    conf: Ini,
+    conf: RefCell<Ini>,
+    algorithm: HashAlgorithm,
+    /// The pack indexes, read on first use and dropped whenever packs are written or removed
+    packs: RefCell<Option<Rc<Vec<PackIndex>>>>,
 }
 
 impl Repository {
-    pub fn create(path: &Path) -> Result<Self> {
+    /// Create a repository at `path`. An explicit `object_format` is recorded as the
+    /// `extensions.objectformat` setting; otherwise the repository uses the SHA-256 default.
+    pub fn create(path: &Path, object_format: Option<HashAlgorithm>) -> Result<Self> {
         let mut repo = Repository::new(path, true)?;
 
         if repo.worktree.exists() {
@@ -49,31 +137,39 @@ impl Repository {
         OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&file_path)?
-            .write("Unnamed repository; edit this file 'description' to name the repository.\n".as_bytes())?;
+            .write_all("Unnamed repository; edit this file 'description' to name the repository.\n".as_bytes())?;
 
+        let branch = global_config().get("init", "defaultbranch").unwrap_or_else(|| String::from("master"));
         file_path = repo.repo_file(vec!["HEAD"], false)?.unwrap();
         OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&file_path)?
-            .write("ref: refs/heads/master\n".as_bytes())?;
+            .write_all(format!("ref: {}\n", branch_ref(&branch)?).as_bytes())?;
 
         file_path = repo.repo_file(vec!["config"], false)?.unwrap();
-        repo.init_config(&file_path)?;
+        repo.init_config(&file_path, object_format)?;
 
         Ok(repo)
     }
 
-    fn init_config(&mut self, path: &Path) -> Result<()> {
-        self.conf.set("core", "repositoryformatversion", Some(String::from("0")));
-        self.conf.set("core", "filemode", Some(String::from("false")));
-        self.conf.set("core", "bare", Some(String::from("false")));
-        self.conf.write(path)?;
+    fn init_config(&mut self, path: &Path, object_format: Option<HashAlgorithm>) -> Result<()> {
+        // Extensions are only honoured by version 1 repositories
+        let version = if object_format.is_some() { "1" } else { "0" };
+        self.conf.get_mut().set("core", "repositoryformatversion", Some(String::from(version)));
+        self.conf.get_mut().set("core", "bare", Some(String::from("false")));
+        self.conf.get_mut().set("core", "filemode", Some(String::from("false")));
+        if let Some(algorithm) = object_format {
+            self.conf.get_mut().set("extensions", "objectformat", Some(algorithm.to_string()));
+            self.algorithm = algorithm;
+        }
+        self.conf.get_mut().write(path)?;
         Ok(())
     }
 
-    /// 
     pub fn new(path: &Path, force: bool) -> Result<Self> {
         let worktree = path.to_path_buf();
         let minit_dir = worktree.join(".minit");
@@ -81,33 +177,1207 @@ impl Repository {
             return Err(Error::InvalidFilePath(minit_dir));
         }
         let mut repo = Repository {
-            worktree, minit_dir, conf: Ini::new()
+            worktree, minit_dir, conf: RefCell::new(Ini::new()), algorithm: HashAlgorithm::default(), packs: RefCell::new(None)
         };
         let config_path = repo.repo_file(vec!["config"], false)?.unwrap();
         if config_path.exists() {
-            repo.conf.load(&config_path).unwrap();
+            repo.conf.get_mut().load(&config_path).unwrap();
         } else {
             if !force { panic!("Configuration file missing"); }
         }
                
         if !force {
-            _ = repo.conf
+            let version = repo.conf.get_mut()
                 .get("core", "repositoryformatversion")
-                .ok_or(Error::ConfigKeyDoesntExist(String::from("core")))
-                .map(|version|
-                    if version != "0" {
-                        Err(Error::UnsupportedRepositoryVersion)
-                    } else {
-                        Ok(())
+                .ok_or(Error::ConfigKeyDoesntExist(String::from("core")))?;
+            match version.as_str() {
+                "0" => {},
+                "1" => {
+                    if let Some(format) = repo.conf.get_mut().get("extensions", "objectformat") {
+                        repo.algorithm = format.parse()?;
                     }
-                )?;
+                },
+                _ => return Err(Error::UnsupportedRepositoryVersion),
+            }
         }
         Ok(repo)
     }
 
-    ///
+    /// The hash function this repository names objects with
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// The value of `section.key` in the repository's config. A section with a subsection
+    /// is named as git writes it, as in `branch "main"`.
+    pub fn config(&self, section: &str, key: &str) -> Option<String> {
+        self.conf.borrow().get(section, key)
+    }
+
+    /// Set `section.key`, or unset it with `None`, and save the config file
+    pub fn set_config(&self, section: &str, key: &str, value: Option<&str>) -> Result<()> {
+        let mut conf = self.conf.borrow_mut();
+        match value {
+            Some(value) => _ = conf.set(section, key, Some(value.to_string())),
+            None => _ = conf.remove_key(section, key),
+        }
+        conf.write(self.repo_path(vec!["config"]))?;
+        Ok(())
+    }
+
+    /// Move every key of the section `from` into `to`, or drop them with `None`, and save
+    /// the config file if there were any
+    fn move_config_section(&self, from: &str, to: Option<&str>) -> Result<()> {
+        let mut conf = self.conf.borrow_mut();
+        let Some(keys) = conf.remove_section(from) else {
+            return Ok(());
+        };
+        if let Some(to) = to {
+            for (key, value) in keys {
+                conf.set(to, &key, value);
+            }
+        }
+        conf.write(self.repo_path(vec!["config"]))?;
+        Ok(())
+    }
+
+    /// The staging area, empty if nothing has been staged yet
+    pub fn read_index(&self) -> Result<Index> {
+        let path = self.repo_path(vec!["index"]);
+        if !path.is_file() {
+            return Ok(Index::default());
+        }
+        Index::parse(&fs::read(path)?, self.algorithm)
+    }
+
+    /// Replace the staging area. The new index is written to `index.lock` and renamed over
+    /// the old one, so readers never see half of it; an existing lock means another
+    /// process is writing and is an error.
+    pub fn write_index(&self, index: &Index) -> Result<()> {
+        let lock_path = self.repo_path(vec!["index.lock"]);
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&lock_path)?;
+        let result = file.write_all(&index.serialize(self.algorithm)).and_then(|_| file.sync_all());
+        if let Err(err) = result {
+            _ = fs::remove_file(&lock_path);
+            return Err(err.into());
+        }
+        fs::rename(&lock_path, self.repo_path(vec!["index"]))?;
+        Ok(())
+    }
+
+    /// Stage the worktree files selected by `pathspec`, or the whole worktree if it is
+    /// empty and `all` or `update` is set. Changed files are hashed into the object
+    /// database, and staged paths whose file is gone are removed. Returns what changed, or
+    /// would change on a dry run.
+    pub fn add(&self, pathspec: &Pathspec, options: &AddOptions) -> Result<Vec<IndexChange>> {
+        if pathspec.is_empty() && !options.all && !options.update {
+            return Ok(Vec::new());
+        }
+        let everything = Pathspec::from_patterns(vec![String::new()]);
+        let pathspec = if pathspec.is_empty() { &everything } else { pathspec };
+        let mut index = self.read_index()?;
+
+        let mut candidates: BTreeSet<String> = index.entries.iter()
+            .map(|entry| entry.path.clone())
+            .filter(|path| pathspec.matches(path))
+            .collect();
+        if !options.update {
+            let files = match options.force {
+                true => self.worktree_files()?,
+                false => {
+                    let tracked = index.entries.iter().map(|entry| entry.path.clone()).collect::<BTreeSet<String>>();
+                    let mut untracked = Vec::new();
+                    self.collect_untracked("", &tracked, Some(&mut self.ignore()?), UntrackedFiles::All, &mut untracked)?;
+                    untracked
+                },
+            };
+            candidates.extend(files.into_iter().filter(|path| pathspec.matches(path)));
+        }
+        let unmatched = pathspec.patterns().iter()
+            .filter(|pattern| !pattern.is_empty() && !candidates.iter().any(|path| pathspec::matches_pattern(pattern, path)))
+            .collect::<Vec<&String>>();
+        if let Some(pattern) = unmatched.first() {
+            // Naming an ignored file is a mistake of its own
+            let files = self.worktree_files()?;
+            let ignored = unmatched.iter()
+                .filter(|pattern| !options.update && files.iter().any(|path| pathspec::matches_pattern(pattern, path)))
+                .map(|pattern| pattern.as_str())
+                .collect::<Vec<&str>>();
+            if !ignored.is_empty() {
+                return Err(Error::Refused(format!(
+                    "the following paths are ignored by one of your ignore files:\n{}\nuse -f if you really want to add them",
+                    ignored.join("\n"),
+                )));
+            }
+            return Err(Error::PathspecNoMatch(pattern.to_string()));
+        }
+
+        let mut changes: Vec<IndexChange> = Vec::new();
+        for path in candidates {
+            let staged = index.entries.iter().filter(|entry| entry.path == path).collect::<Vec<&IndexEntry>>();
+            let metadata = fs::symlink_metadata(self.worktree.join(&path)).ok().filter(|m| !m.is_dir());
+            let Some(metadata) = metadata else {
+                if !staged.is_empty() {
+                    index.remove(&path);
+                    changes.push(IndexChange::Remove(path));
+                }
+                continue;
+            };
+            let conflicted = staged.iter().any(|entry| entry.stage != 0);
+            let current = staged.iter().find(|entry| entry.stage == 0).map(|entry| (*entry).clone());
+            if current.as_ref().is_some_and(|entry| !entry.intent_to_add && entry.stat_matches(&metadata)) && !conflicted {
+                continue;
+            }
+
+            let entry = if current.is_none() && options.intent_to_add {
+                let mut entry = IndexEntry::new(path.clone(), index::mode_of(&metadata), self.algorithm.digest(b"blob 0\x00"));
+                entry.intent_to_add = true;
+                entry
+            } else {
+                let sha = self.stage_blob(&self.worktree.join(&path), &metadata, options.dry_run)?;
+                IndexEntry::from_metadata(path.clone(), sha, &metadata)
+            };
+            let unchanged = current.is_some_and(|current| {
+                !current.intent_to_add && current.sha == entry.sha && current.mode == entry.mode
+            });
+            if !unchanged || conflicted {
+                changes.push(IndexChange::Add(path.clone()));
+            }
+            // A file replaces any directory of the same name and vice versa
+            index.entries.retain(|staged| {
+                staged.path != path
+                    && !staged.path.strip_prefix(&path).is_some_and(|rest| rest.starts_with('/'))
+                    && !path.strip_prefix(&staged.path).is_some_and(|rest| rest.starts_with('/'))
+            });
+            index.add(entry);
+        }
+
+        if !options.dry_run {
+            self.write_index(&index)?;
+        }
+        Ok(changes)
+    }
+
+    /// Hash the file or symlink at `path` as a blob, storing it unless `dry_run` is set.
+    /// A symlink's blob is its target.
+    fn stage_blob(&self, path: &Path, metadata: &fs::Metadata, dry_run: bool) -> Result<ObjectId> {
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(path)?;
+            #[cfg(unix)]
+            let target = std::os::unix::ffi::OsStrExt::as_bytes(target.as_os_str()).to_vec();
+            #[cfg(not(unix))]
+            let target = target.to_string_lossy().as_bytes().to_vec();
+            return match dry_run {
+                true => Ok(Object::Blob(target).write(self.algorithm)?.0),
+                false => self.write_object(Object::Blob(target)),
+            };
+        }
+        let file = File::open(path)?;
+        match dry_run {
+            true => object::hash_stream(&Format::Blob, metadata.len(), file, self.algorithm),
+            false => self.write_stream(Format::Blob, metadata.len(), file),
+        }
+    }
+
+    /// Every file and symlink in the worktree, slash separated and relative to its root,
+    /// in index order. The `.minit` directory is left out.
+    pub fn worktree_files(&self) -> Result<Vec<String>> {
+        let mut files: Vec<String> = Vec::new();
+        let mut stack = vec![(self.worktree.clone(), String::new())];
+        while let Some((dir, prefix)) = stack.pop() {
+            for entry in dir.read_dir()?.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name == ".minit" {
+                    continue;
+                }
+                let path = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+                if entry.file_type()?.is_dir() {
+                    stack.push((entry.path(), path));
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn worktree(&self) -> &Path {
+        &self.worktree
+    }
+
+    /// Unstage the paths selected by `pathspec` and, unless `cached` is set, delete them
+    /// from the worktree. Paths whose staged content differs from HEAD or from the
+    /// worktree are refused unless `force` is set, since the change would be lost.
+    pub fn rm(&self, pathspec: &Pathspec, options: &RmOptions) -> Result<Vec<String>> {
+        let mut index = self.read_index()?;
+        let mut paths: Vec<String> = index.entries.iter()
+            .filter(|entry| pathspec.matches(&entry.path))
+            .map(|entry| entry.path.clone())
+            .collect();
+        paths.dedup();
+        for pattern in pathspec.patterns() {
+            let mut matched = paths.iter().filter(|path| pathspec::matches_pattern(pattern, path)).peekable();
+            if matched.peek().is_none() {
+                return Err(Error::PathspecNoMatch(pattern.clone()));
+            }
+            if !options.recursive && matched.any(|path| path != pattern) && !pattern.contains(['*', '?', '[']) {
+                return Err(Error::Refused(format!("not removing '{}' recursively without -r", pattern)));
+            }
+        }
+
+        if !options.force {
+            let head = match self.head_tree()? {
+                Some(tree) => self.flatten_tree(&tree)?,
+                None => BTreeMap::new(),
+            };
+            for entry in index.entries.iter().filter(|entry| entry.stage == 0 && paths.contains(&entry.path)) {
+                let staged = head.get(&entry.path) != Some(&(entry.mode, entry.sha)) && !entry.intent_to_add;
+                let exists = fs::symlink_metadata(self.worktree.join(&entry.path)).is_ok();
+                let modified = exists && self.is_modified(entry)?;
+                let reason = match (staged, modified) {
+                    (true, true) => "has staged content different from both the file and the HEAD",
+                    (true, false) if !options.cached => "has changes staged in the index",
+                    (false, true) if !options.cached => "has local modifications",
+                    _ => continue,
+                };
+                return Err(Error::Refused(format!("'{}' {}", entry.path, reason)));
+            }
+        }
+
+        for path in &paths {
+            index.remove(path);
+        }
+        self.write_index(&index)?;
+        if !options.cached {
+            for path in &paths {
+                let file = self.worktree.join(path);
+                if fs::symlink_metadata(&file).is_ok_and(|metadata| !metadata.is_dir()) {
+                    fs::remove_file(&file)?;
+                }
+                self.remove_empty_parents(&file);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Move the file or directory `source` to `destination`, both relative to the
+    /// worktree root, in the worktree and the index together. A destination that is a
+    /// directory receives `source` under its own name. Returns the renamed index paths.
+    pub fn mv(&self, source: &str, destination: &str) -> Result<Vec<(String, String)>> {
+        let mut index = self.read_index()?;
+        let under = |path: &str, dir: &str| path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'));
+        let moved = index.entries.iter()
+            .filter(|entry| entry.path == source || under(&entry.path, source))
+            .cloned()
+            .collect::<Vec<IndexEntry>>();
+        let from = self.worktree.join(source);
+        if moved.is_empty() || fs::symlink_metadata(&from).is_err() {
+            return Err(Error::Refused(format!("bad source, source={}, destination={}", source, destination)));
+        }
+        if moved.iter().any(|entry| entry.stage != 0) {
+            return Err(Error::Refused(format!("'{}' has unresolved conflicts", source)));
+        }
+
+        let mut destination = destination.to_string();
+        if self.worktree.join(&destination).is_dir() || destination.is_empty() {
+            let name = source.rsplit('/').next().unwrap_or(source);
+            destination = if destination.is_empty() { name.to_string() } else { format!("{}/{}", destination, name) };
+        }
+        let to = self.worktree.join(&destination);
+        if destination == source || under(&destination, source) {
+            return Err(Error::Refused(format!("can not move directory into itself, source={}, destination={}", source, destination)));
+        }
+        if fs::symlink_metadata(&to).is_ok() || index.entries.iter().any(|entry| entry.path == destination || under(&entry.path, &destination)) {
+            return Err(Error::Refused(format!("destination exists, source={}, destination={}", source, destination)));
+        }
+        if !to.parent().is_some_and(|parent| parent.is_dir()) {
+            return Err(Error::Refused(format!("destination directory does not exist, source={}, destination={}", source, destination)));
+        }
+
+        let mut renames: Vec<(String, String)> = Vec::new();
+        for mut entry in moved {
+            let old = entry.path.clone();
+            index.remove(&old);
+            entry.path = format!("{}{}", destination, &old[source.len()..]);
+            renames.push((old, entry.path.clone()));
+            index.add(entry);
+        }
+        fs::rename(&from, &to)?;
+        // Put the worktree back if the index can't follow, so the two never disagree
+        if let Err(err) = self.write_index(&index) {
+            _ = fs::rename(&to, &from);
+            return Err(err);
+        }
+        self.remove_empty_parents(&from);
+        Ok(renames)
+    }
+
+    /// Whether the worktree file for `entry` differs from what is staged, in content or
+    /// mode. A missing file counts as modified.
+    pub fn is_modified(&self, entry: &IndexEntry) -> Result<bool> {
+        let path = self.worktree.join(&entry.path);
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return Ok(true);
+        };
+        if metadata.is_dir() {
+            return Ok(true);
+        }
+        if entry.stat_matches(&metadata) {
+            return Ok(false);
+        }
+        Ok(index::mode_of(&metadata) != entry.mode || self.stage_blob(&path, &metadata, true)? != entry.sha)
+    }
+
+    /// Delete the now empty directories between `path` and the worktree root
+    fn remove_empty_parents(&self, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(parent) = dir.filter(|parent| parent.starts_with(&self.worktree) && *parent != self.worktree) {
+            if fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+    }
+
+    /// Commit the index on top of HEAD and move the branch HEAD points at, or HEAD
+    /// itself when detached. `message` may only be left out when amending, to keep the
+    /// amended commit's message.
+    pub fn commit(&self, message: Option<Vec<u8>>, options: &CommitOptions) -> Result<ObjectId> {
+        if options.all {
+            self.add(&Pathspec::default(), &AddOptions { update: true, ..AddOptions::default() })?;
+        }
+        let tree = self.write_tree(&self.read_index()?)?;
+        let head = match self.resolve_ref("HEAD") {
+            Ok(head) => Some(head),
+            Err(Error::InvalidFilePath(_)) => None,
+            Err(err) => return Err(err),
+        };
+        let committer = self.identity("COMMITTER")?;
+        let (parents, author, message) = if options.amend {
+            let head = head.ok_or_else(|| Error::Refused(String::from("you have nothing to amend")))?;
+            let Object::Commit(amended) = self.read_object(&head)? else {
+                return Err(Error::WrongObjectType(head, Format::Commit, self.read_raw(&head)?.0));
+            };
+            (amended.parents, amended.author, message.or(amended.message))
+        } else {
+            (head.into_iter().collect(), self.identity("AUTHOR")?, message)
+        };
+        let message = message.filter(|message| !message.is_empty())
+            .ok_or_else(|| Error::Refused(String::from("aborting commit due to empty commit message")))?;
+
+        // A merge records history even when its tree matches the first parent
+        if !options.allow_empty && parents.len() <= 1 {
+            let base = match parents.first() {
+                Some(parent) => match self.read_object(parent)? {
+                    Object::Commit(commit) => commit.tree,
+                    _ => return Err(Error::WrongObjectType(*parent, Format::Commit, self.read_raw(parent)?.0)),
+                },
+                None => Object::Tree(Vec::new()).write(self.algorithm)?.0,
+            };
+            if base == tree {
+                return Err(Error::Refused(String::from("nothing to commit")));
+            }
+        }
+
+        let parents_len = parents.len();
+        let subject = String::from_utf8_lossy(message.split(|&c| c == b'\n').next().unwrap_or_default()).into_owned();
+        let sha = self.write_object(Object::Commit(Commit {
+            tree,
+            parents,
+            author,
+            committer,
+            encoding: None,
+            extra_headers: Vec::new(),
+            gpgsig: None,
+            message: Some(message),
+        }))?;
+        let target = self.head_ref()?.unwrap_or_else(|| String::from("HEAD"));
+        let kind = match () {
+            _ if options.amend => " (amend)",
+            _ if head.is_none() => " (initial)",
+            _ if parents_len > 1 => " (merge)",
+            _ => "",
+        };
+        self.update_ref(&target, &sha, head.as_ref(), &format!("commit{}: {}", kind, subject))?;
+        Ok(sha)
+    }
+
+    /// Write the staged entries as nested trees and return the root. Intent-to-add
+    /// entries are left out; unresolved conflicts can't be written.
+    pub fn write_tree(&self, index: &Index) -> Result<ObjectId> {
+        if let Some(entry) = index.entries.iter().find(|entry| entry.stage != 0) {
+            return Err(Error::Refused(format!("'{}' has unresolved conflicts", entry.path)));
+        }
+        let entries = index.entries.iter()
+            .filter(|entry| !entry.intent_to_add)
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect::<Vec<(&str, &IndexEntry)>>();
+        self.write_subtree(&entries)
+    }
+
+    /// Write the tree for `entries`, whose paths are relative to it. Entries in the same
+    /// subdirectory are next to each other since the index is sorted by path.
+    fn write_subtree(&self, entries: &[(&str, &IndexEntry)]) -> Result<ObjectId> {
+        let mut leaves: Vec<Leaf> = Vec::new();
+        let mut i = 0;
+        while i < entries.len() {
+            let (path, entry) = entries[i];
+            match path.split_once('/') {
+                None => {
+                    leaves.push(Leaf::new(format!("{:o}", entry.mode), path.to_string(), entry.sha));
+                    i += 1;
+                },
+                Some((dir, _)) => {
+                    let children = entries[i..].iter()
+                        .map_while(|(path, entry)| {
+                            path.strip_prefix(dir).and_then(|rest| rest.strip_prefix('/')).map(|rest| (rest, *entry))
+                        })
+                        .collect::<Vec<(&str, &IndexEntry)>>();
+                    i += children.len();
+                    leaves.push(Leaf::new(String::from("40000"), dir.to_string(), self.write_subtree(&children)?));
+                },
+            }
+        }
+        self.write_object(Object::Tree(leaves))
+    }
+
+    /// Who is committing, from `MINIT_<ROLE>_NAME`, `MINIT_<ROLE>_EMAIL` and
+    /// `MINIT_<ROLE>_DATE` or else `user.name`, `user.email` and the current time. Dates
+    /// are written as git stores them, `1700000000 +0100`.
+    fn identity(&self, role: &str) -> Result<Identity> {
+        let setting = |key: &str| {
+            std::env::var(format!("MINIT_{}_{}", role, key.to_uppercase())).ok()
+                .or_else(|| self.config("user", key))
+                .ok_or_else(|| Error::ConfigKeyDoesntExist(format!("user.{}", key)))
+        };
+        let (name, email) = (setting("name")?, setting("email")?);
+        let date = match std::env::var(format!("MINIT_{}_DATE", role)) {
+            Ok(date) => date.trim_start_matches('@').to_string(),
+            Err(_) => format!("{} +0000", SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())),
+        };
+        Identity::parse(format!("{} <{}> {}", name, email, date).as_bytes())
+    }
+
+    /// The branch HEAD points at, as in `refs/heads/master`, or `None` when detached
+    pub fn head_ref(&self) -> Result<Option<String>> {
+        let head = fs::read_to_string(self.repo_path(vec!["HEAD"]))?;
+        Ok(head.trim_end().strip_prefix("ref: ").map(String::from))
+    }
+
+    /// Start a set of ref updates that happen all together or not at all
+    pub fn transaction(&self) -> RefTransaction<'_> {
+        RefTransaction::new(self)
+    }
+
+    /// Point `name` at `sha` if it still holds `expected`, where `None` means the ref must
+    /// not exist yet. The ref is locked while it is checked and written so that two
+    /// writers can't both win. The update is recorded in the reflog of `name` with
+    /// `message`, and in HEAD's as well when HEAD points at `name`.
+    pub fn update_ref(&self, name: &str, sha: &ObjectId, expected: Option<&ObjectId>, message: &str) -> Result<()> {
+        let mut transaction = self.transaction();
+        transaction.update(name, RefValue::Object(*sha), Expected::from_option(expected), message);
+        transaction.commit()
+    }
+
+    /// Switch the worktree, the index and HEAD to `target`, a branch name or anything else
+    /// that names a commit. Only paths that differ between the current commit and the new
+    /// one are touched, so changes to other paths carry over. If a local change or an
+    /// untracked file would be overwritten nothing is done, unless `force` is set to reset
+    /// every tracked path instead. Returns the branch HEAD now points at, or `None` when
+    /// it is detached.
+    pub fn checkout(&self, target: &str, options: &CheckoutOptions) -> Result<Option<String>> {
+        let branch = match self.head_ref()? {
+            Some(current) if target == "HEAD" => current,
+            _ => format!("refs/heads/{}", target),
+        };
+        let on_branch = !options.detach
+            && options.new_branch.is_none()
+            && check_ref_format(&branch)
+            && self.ref_exists(&branch);
+        let sha = match on_branch {
+            true => self.resolve_ref(&branch)?,
+            false => self.find_object(target, Some(Format::Commit), true)?,
+        };
+        let Object::Commit(commit) = self.read_object(&sha)? else {
+            return Err(Error::WrongObjectType(sha, Format::Commit, self.read_raw(&sha)?.0));
+        };
+        if let Some(name) = &options.new_branch
+            && self.ref_exists(&branch_ref(name)?)
+        {
+            return Err(Error::Refused(format!("a branch named '{}' already exists", name)));
+        }
+
+        let old = match self.head_tree()? {
+            Some(tree) => self.flatten_tree(&tree)?,
+            None => BTreeMap::new(),
+        };
+        let new = self.flatten_tree(&commit.tree)?;
+        verify_tree_paths(&commit.tree, &new)?;
+        let mut index = self.read_index()?;
+        let (removed, written) = if options.force {
+            let removed = index.entries.iter()
+                .map(|entry| entry.path.clone())
+                .filter(|path| !new.contains_key(path))
+                .collect::<BTreeSet<String>>();
+            let mut written = Vec::new();
+            for (path, version) in &new {
+                let unchanged = match index.get(path) {
+                    Some(entry) => !entry.intent_to_add && (entry.mode, entry.sha) == *version && !self.is_modified(entry)?,
+                    None => false,
+                };
+                if !unchanged {
+                    written.push(path.clone());
+                }
+            }
+            (removed.into_iter().collect::<Vec<String>>(), written)
+        } else {
+            self.check_checkout(&old, &new, &index)?;
+            // Paths already staged the way the target has them are left alone
+            old.keys().chain(new.keys())
+                .filter(|path| old.get(*path) != new.get(*path))
+                .filter(|path| {
+                    let staged = index.get(path).filter(|entry| !entry.intent_to_add).map(|entry| (entry.mode, entry.sha));
+                    staged.as_ref() != new.get(*path)
+                })
+                .cloned()
+                .collect::<BTreeSet<String>>()
+                .into_iter()
+                .partition(|path| !new.contains_key(path))
+        };
+
+        // Removals go first so that a file can take the place of a directory and back
+        for path in &removed {
+            index.remove(path);
+            let file = self.worktree.join(path);
+            match fs::symlink_metadata(&file) {
+                Ok(metadata) if metadata.is_dir() => _ = fs::remove_dir(&file),
+                Ok(_) => fs::remove_file(&file)?,
+                Err(_) => {},
+            }
+            self.remove_empty_parents(&file);
+        }
+        for path in &written {
+            let (mode, sha) = new[path];
+            index.remove(path);
+            index.add(self.checkout_entry(path, mode, &sha)?);
+        }
+        self.write_index(&index)?;
+
+        let from = match self.head_ref()? {
+            Some(current) => current.strip_prefix("refs/heads/").unwrap_or(&current).to_string(),
+            None => self.resolve_ref("HEAD")?.to_hex(),
+        };
+        let mut transaction = self.transaction();
+        let head = match &options.new_branch {
+            Some(name) => {
+                let name = format!("refs/heads/{}", name);
+                transaction.update(&name, RefValue::Object(sha), Expected::Missing, &format!("branch: Created from {}", target));
+                Some(name)
+            },
+            None if on_branch => Some(branch),
+            None => None,
+        };
+        let to = options.new_branch.as_deref().unwrap_or(target);
+        let value = match &head {
+            Some(name) => RefValue::Symbolic(name.clone()),
+            None => RefValue::Object(sha),
+        };
+        transaction.update("HEAD", value, Expected::Any, &format!("checkout: moving from {} to {}", from, to));
+        transaction.commit()?;
+        Ok(head.map(|name| name["refs/heads/".len()..].to_string()))
+    }
+
+    /// Refuse to check out `new` over `old` if staged or unstaged changes would be lost or
+    /// untracked files that aren't ignored would be overwritten, listing all of them
+    fn check_checkout(&self, old: &BTreeMap<String, Version>, new: &BTreeMap<String, Version>, index: &Index) -> Result<()> {
+        if index.entries.iter().any(|entry| entry.stage != 0) {
+            return Err(Error::Refused(String::from("you need to resolve your current index first")));
+        }
+        let mut ignore = self.ignore()?;
+        let tracked = index.entries.iter().map(|entry| entry.path.clone()).collect::<BTreeSet<String>>();
+        let removed = |path: &str| old.contains_key(path) && !new.contains_key(path);
+        let mut local: BTreeSet<String> = BTreeSet::new();
+        let mut untracked: BTreeSet<String> = BTreeSet::new();
+
+        for path in old.keys().chain(new.keys()).collect::<BTreeSet<&String>>() {
+            let (before, after) = (old.get(path).copied(), new.get(path).copied());
+            if before == after {
+                continue;
+            }
+            let entry = index.get(path);
+            let staged = entry.filter(|entry| !entry.intent_to_add).map(|entry| (entry.mode, entry.sha));
+            if staged == after && !entry.is_some_and(|entry| entry.intent_to_add) {
+                continue;
+            }
+            let metadata = fs::symlink_metadata(self.worktree.join(path)).ok();
+            match entry {
+                Some(entry) if staged != before || entry.intent_to_add => _ = local.insert(path.clone()),
+                Some(entry) if metadata.is_some() && self.is_modified(entry)? => _ = local.insert(path.clone()),
+                Some(_) => {},
+                // A staged removal the target would undo
+                None if before.is_some() => _ = local.insert(path.clone()),
+                None => match metadata {
+                    Some(metadata) if metadata.is_dir() => {
+                        let prefix = format!("{}/", path);
+                        tracked.range(prefix.clone()..)
+                            .take_while(|tracked| tracked.starts_with(&prefix))
+                            .filter(|tracked| !removed(tracked))
+                            .for_each(|tracked| _ = local.insert(tracked.clone()));
+                        let mut inside = Vec::new();
+                        self.collect_untracked(path, &tracked, Some(&mut ignore), UntrackedFiles::All, &mut inside)?;
+                        untracked.extend(inside);
+                    },
+                    Some(_) if !ignore.is_ignored(path, false)? => _ = untracked.insert(path.clone()),
+                    _ => {},
+                },
+            }
+            // A file where the target needs a directory
+            if after.is_some() {
+                for (end, _) in path.match_indices('/') {
+                    let parent = &path[..end];
+                    if fs::symlink_metadata(self.worktree.join(parent)).is_ok_and(|metadata| !metadata.is_dir()) {
+                        if tracked.contains(parent) {
+                            if !removed(parent) {
+                                local.insert(parent.to_string());
+                            }
+                        } else if !ignore.is_ignored(parent, false)? {
+                            untracked.insert(parent.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        let list = |paths: &BTreeSet<String>| paths.iter().cloned().collect::<Vec<String>>().join("\n\t");
+        let mut reasons = Vec::new();
+        if !local.is_empty() {
+            reasons.push(format!(
+                "your local changes to the following files would be overwritten by checkout:\n\t{}\nplease commit your changes or stash them before you switch branches",
+                list(&local),
+            ));
+        }
+        if !untracked.is_empty() {
+            reasons.push(format!(
+                "the following untracked working tree files would be overwritten by checkout:\n\t{}\nplease move or remove them before you switch branches",
+                list(&untracked),
+            ));
+        }
+        match reasons.is_empty() {
+            true => Ok(()),
+            false => Err(Error::Refused(reasons.join("\n"))),
+        }
+    }
+
+    /// Write the blob `sha` to the worktree at `path` and return its index entry
+    fn checkout_entry(&self, path: &str, mode: u32, sha: &ObjectId) -> Result<IndexEntry> {
+        self.write_entry(&self.worktree, path, mode, sha)?;
+        match mode {
+            index::MODE_GITLINK => Ok(IndexEntry::new(path.to_string(), mode, *sha)),
+            _ => Ok(IndexEntry::from_metadata(path.to_string(), *sha, &fs::symlink_metadata(self.worktree.join(path))?)),
+        }
+    }
+
+    /// Write the blob `sha` to `path` under `root` as a file of `mode`, replacing whatever
+    /// is in the way. Mode 100755 files are made executable, a symlink's blob is its
+    /// target and a submodule is left as an empty directory.
+    fn write_entry(&self, root: &Path, path: &str, mode: u32, sha: &ObjectId) -> Result<()> {
+        let file = root.join(path);
+        // Never follow a symlink out of the tree being written
+        for (end, _) in path.match_indices('/') {
+            let parent = root.join(&path[..end]);
+            if fs::symlink_metadata(&parent).is_ok_and(|metadata| !metadata.is_dir()) {
+                fs::remove_file(&parent)?;
+            }
+        }
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::symlink_metadata(&file) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&file)?,
+            Ok(_) => fs::remove_file(&file)?,
+            Err(_) => {},
+        }
+
+        match mode {
+            index::MODE_GITLINK => fs::create_dir(&file)?,
+            index::MODE_SYMLINK => {
+                let Object::Blob(target) = self.read_object(sha)? else {
+                    return Err(Error::WrongObjectType(*sha, Format::Blob, self.read_raw(sha)?.0));
+                };
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(&target), &file)?;
+                #[cfg(not(unix))]
+                fs::write(&file, &target)?;
+            },
+            _ => {
+                let mut options = OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, if mode == index::MODE_EXECUTABLE { 0o777 } else { 0o666 });
+                let mut out = options.open(&file)?;
+                std::io::copy(&mut self.read_blob_stream(sha)?, &mut out)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Check out `tree` into `directory`, which must be empty or not exist yet. Every path
+    /// is checked before anything is written.
+    pub fn checkout_tree(&self, tree: &ObjectId, directory: &Path) -> Result<()> {
+        if directory.exists() && (!directory.is_dir() || directory.read_dir()?.next().is_some()) {
+            return Err(Error::Refused(format!("'{}' is not an empty directory", directory.display())));
+        }
+        let entries = self.flatten_tree(tree)?;
+        verify_tree_paths(tree, &entries)?;
+        fs::create_dir_all(directory)?;
+        for (path, (mode, sha)) in &entries {
+            self.write_entry(directory, path, *mode, sha)?;
+        }
+        Ok(())
+    }
+
+    /// Every branch by its name without `refs/heads/`, with the commit it points at
+    pub fn branches(&self) -> Result<Vec<(String, ObjectId)>> {
+        let heads = self.repo_path(vec!["refs", "heads"]);
+        let mut branches = self.ls_ref(Some(&heads))?
+            .into_iter()
+            .map(|(name, sha)| (name["refs/heads/".len()..].to_string(), sha))
+            .collect::<Vec<(String, ObjectId)>>();
+        branches.sort();
+        Ok(branches)
+    }
+
+    /// Create the branch `name` at the commit `start_point` names. An existing branch is
+    /// only moved if `force` is set, and never the one HEAD points at.
+    pub fn create_branch(&self, name: &str, start_point: &str, force: bool) -> Result<ObjectId> {
+        let refname = branch_ref(name)?;
+        let sha = self.find_object(start_point, Some(Format::Commit), true)?;
+        let current = self.resolve_ref(&refname).ok();
+        if current.is_some() {
+            if !force {
+                return Err(Error::Refused(format!("a branch named '{}' already exists", name)));
+            }
+            if self.head_ref()?.as_deref() == Some(refname.as_str()) {
+                return Err(Error::Refused(format!("cannot force update the current branch '{}'", name)));
+            }
+        }
+        let message = match current {
+            Some(_) => format!("branch: Reset to {}", start_point),
+            None => format!("branch: Created from {}", start_point),
+        };
+        self.update_ref(&refname, &sha, current.as_ref(), &message)?;
+        Ok(sha)
+    }
+
+    /// Delete the branch `name` with its reflog and config, returning the commit it
+    /// pointed at. Unless `force` is set the branch must be merged into its upstream, or
+    /// into HEAD if it has none. The branch HEAD points at can't be deleted.
+    pub fn delete_branch(&self, name: &str, force: bool) -> Result<ObjectId> {
+        let refname = format!("refs/heads/{}", name);
+        let sha = self.resolve_ref(&refname).map_err(|_| Error::Refused(format!("branch '{}' not found", name)))?;
+        if self.head_ref()?.as_deref() == Some(refname.as_str()) {
+            return Err(Error::Refused(format!("cannot delete branch '{}' checked out at '{}'", name, self.worktree.display())));
+        }
+        if !force {
+            let base = self.upstream(name)
+                .and_then(|upstream| self.resolve_ref(&upstream).ok())
+                .or_else(|| self.resolve_ref("HEAD").ok());
+            if !base.map_or(Ok(false), |base| self.is_ancestor(&sha, &base))? {
+                return Err(Error::Refused(format!(
+                    "the branch '{}' is not fully merged\nif you are sure you want to delete it, run 'minit branch -D {}'",
+                    name, name,
+                )));
+            }
+        }
+        let mut transaction = self.transaction();
+        transaction.delete(&refname, Expected::Value(sha));
+        transaction.commit()?;
+        self.move_config_section(&format!("branch \"{}\"", name), None)?;
+        Ok(sha)
+    }
+
+    /// Rename the branch `old` to `new` along with its reflog and config, following it
+    /// with HEAD if HEAD points at it. An existing `new` is only replaced if `force` is set.
+    pub fn rename_branch(&self, old: &str, new: &str, force: bool) -> Result<()> {
+        let old_ref = format!("refs/heads/{}", old);
+        let new_ref = branch_ref(new)?;
+        let head = self.head_ref()?;
+        let current = head.as_deref() == Some(old_ref.as_str());
+        let sha = match self.resolve_ref(&old_ref) {
+            Ok(sha) => Some(sha),
+            // A branch with no commits yet only exists in HEAD
+            Err(_) if current => None,
+            Err(_) => return Err(Error::Refused(format!("no branch named '{}'", old))),
+        };
+        if old_ref == new_ref {
+            return Ok(());
+        }
+        let existing = self.resolve_ref(&new_ref).ok();
+        if existing.is_some() && !force {
+            return Err(Error::Refused(format!("a branch named '{}' already exists", new)));
+        }
+
+        let message = format!("Branch: renamed {} to {}", old_ref, new_ref);
+        let mut rename = self.transaction();
+        if current {
+            rename.update("HEAD", RefValue::Symbolic(new_ref.clone()), Expected::Any, &message);
+        }
+        // The old log carries over, then the rename is logged on top of it
+        let log_path = |name: &str| -> Result<PathBuf> {
+            let path = [vec!["logs"], name.split('/').collect()].concat();
+            self.repo_file(path.clone(), true)?.ok_or_else(|| Error::InvalidFilePath(self.repo_path(path)))
+        };
+        let read_log = |name: &str| fs::read(self.repo_path([vec!["logs"], name.split('/').collect()].concat())).ok();
+        let old_log = read_log(&old_ref);
+        let nested = |a: &str, b: &str| a.strip_prefix(b).is_some_and(|rest| rest.starts_with('/'));
+        match sha {
+            None => rename.commit()?,
+            Some(sha) if !nested(&old_ref, &new_ref) && !nested(&new_ref, &old_ref) => {
+                // Both refs move in one transaction, so the branch is never missing
+                let new_log = read_log(&new_ref);
+                if let Some(log) = &old_log {
+                    fs::write(log_path(&new_ref)?, log)?;
+                }
+                rename.delete(&old_ref, Expected::Value(sha));
+                rename.update(&new_ref, RefValue::Object(sha), Expected::from_option(existing.as_ref()), &message);
+                if let Err(err) = rename.commit() {
+                    match new_log {
+                        Some(log) => fs::write(log_path(&new_ref)?, log)?,
+                        None => self.delete_reflog(&new_ref)?,
+                    }
+                    return Err(err);
+                }
+            },
+            Some(sha) => {
+                // `a` becoming `a/b` or back can't hold both refs at once, so the old one
+                // goes first and is put back if the new one can't be written
+                let mut deletion = self.transaction();
+                deletion.delete(&old_ref, Expected::Value(sha));
+                if let Some(existing) = existing {
+                    deletion.delete(&new_ref, Expected::Value(existing));
+                }
+                deletion.commit()?;
+                let move_log = |to: &str| -> Result<()> {
+                    if let Some(log) = &old_log {
+                        fs::write(log_path(to)?, log)?;
+                    }
+                    Ok(())
+                };
+                move_log(&new_ref)?;
+                rename.update(&new_ref, RefValue::Object(sha), Expected::Missing, &message);
+                if let Err(err) = rename.commit() {
+                    self.delete_reflog(&new_ref)?;
+                    let mut restore = self.transaction();
+                    restore.update(&old_ref, RefValue::Object(sha), Expected::Missing, &message);
+                    restore.commit()?;
+                    move_log(&old_ref)?;
+                    return Err(err);
+                }
+            },
+        }
+        self.move_config_section(&format!("branch \"{}\"", old), Some(&format!("branch \"{}\"", new)))
+    }
+
+    /// The ref the branch `name` tracks, from its `branch.<name>.remote` and
+    /// `branch.<name>.merge` settings: a local branch for the remote `.`, otherwise a
+    /// remote-tracking branch
+    pub fn upstream(&self, name: &str) -> Option<String> {
+        let section = format!("branch \"{}\"", name);
+        let (remote, merge) = (self.config(&section, "remote")?, self.config(&section, "merge")?);
+        match remote.as_str() {
+            "." => Some(merge),
+            remote => merge.strip_prefix("refs/heads/").map(|rest| format!("refs/remotes/{}/{}", remote, rest)),
+        }
+    }
+
+    /// Make `upstream`, a remote-tracking branch like `origin/main` or else a local
+    /// branch, the upstream of the branch `name`
+    pub fn set_upstream(&self, name: &str, upstream: &str) -> Result<()> {
+        if self.resolve_ref(&format!("refs/heads/{}", name)).is_err() {
+            return Err(Error::Refused(format!("branch '{}' does not exist", name)));
+        }
+        let (remote, merge) = match upstream.split_once('/') {
+            Some((remote, rest)) if self.resolve_ref(&format!("refs/remotes/{}", upstream)).is_ok() => {
+                (remote.to_string(), format!("refs/heads/{}", rest))
+            },
+            _ if self.resolve_ref(&format!("refs/heads/{}", upstream)).is_ok() => {
+                (String::from("."), format!("refs/heads/{}", upstream))
+            },
+            _ => return Err(Error::Refused(format!("the requested upstream branch '{}' does not exist", upstream))),
+        };
+        let section = format!("branch \"{}\"", name);
+        self.set_config(&section, "remote", Some(&remote))?;
+        self.set_config(&section, "merge", Some(&merge))
+    }
+
+    /// Whether `ancestor` is `descendant` or reachable from it through parents
+    pub fn is_ancestor(&self, ancestor: &ObjectId, descendant: &ObjectId) -> Result<bool> {
+        let mut seen: HashSet<ObjectId> = HashSet::new();
+        let mut queue = vec![*descendant];
+        while let Some(sha) = queue.pop() {
+            if sha == *ancestor {
+                return Ok(true);
+            }
+            if seen.insert(sha) && let Object::Commit(commit) = self.read_object(&sha)? {
+                queue.extend(commit.parents);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Compare HEAD's tree with the index and the index with the worktree, and list the
+    /// untracked files that aren't ignored. Files whose stat data matches the index are
+    /// taken to be unchanged without hashing them; files that only looked changed have
+    /// their stat data refreshed in the index if it isn't locked.
+    pub fn status(&self, untracked: UntrackedFiles) -> Result<Status> {
+        let head = match self.resolve_ref("HEAD") {
+            Ok(head) => Some(head),
+            Err(Error::InvalidFilePath(_)) => None,
+            Err(err) => return Err(err),
+        };
+        let branch = self.head_ref()?.map(|target| target.trim_start_matches("refs/heads/").to_string());
+        let mut head_entries = match self.head_tree()? {
+            Some(tree) => self.flatten_tree(&tree)?,
+            None => BTreeMap::new(),
+        };
+        let mut index = self.read_index()?;
+        let kind = |mode: u32| mode & 0o170000;
+        let mut entries: Vec<StatusEntry> = Vec::new();
+        let mut refreshed = false;
+
+        let mut i = 0;
+        while i < index.entries.len() {
+            let path = index.entries[i].path.clone();
+            let stages = index.entries[i..].iter().take_while(|entry| entry.path == path).count();
+            let head_version = head_entries.remove(&path);
+            let metadata = fs::symlink_metadata(self.worktree.join(&path)).ok().filter(|m| !m.is_dir());
+            let worktree = metadata.as_ref().map(index::mode_of);
+            let mut entry = StatusEntry {
+                path: path.clone(),
+                staged: None,
+                unstaged: None,
+                head: head_version,
+                index: None,
+                worktree,
+                conflict: None,
+            };
+
+            if index.entries[i].stage != 0 {
+                let mut conflict = [None, None, None];
+                for staged in &index.entries[i..i + stages] {
+                    conflict[(staged.stage as usize).clamp(1, 3) - 1] = Some((staged.mode, staged.sha));
+                }
+                entry.conflict = Some(conflict);
+                entries.push(entry);
+                i += stages;
+                continue;
+            }
+
+            let staged = &mut index.entries[i];
+            i += stages;
+            entry.index = Some((staged.mode, staged.sha));
+            entry.staged = match head_version {
+                _ if staged.intent_to_add => None,
+                None => Some(Change::Added),
+                Some((mode, _)) if kind(mode) != kind(staged.mode) => Some(Change::TypeChanged),
+                Some(version) if version != (staged.mode, staged.sha) => Some(Change::Modified),
+                Some(_) => None,
+            };
+            entry.unstaged = match &metadata {
+                _ if staged.skip_worktree => None,
+                None => Some(Change::Deleted),
+                Some(_) if staged.intent_to_add => Some(Change::Added),
+                // Submodules aren't looked into
+                Some(_) if staged.mode == index::MODE_GITLINK => None,
+                Some(metadata) if staged.stat_matches(metadata) => None,
+                Some(metadata) if kind(index::mode_of(metadata)) != kind(staged.mode) => Some(Change::TypeChanged),
+                Some(metadata) => {
+                    let path = self.worktree.join(&staged.path);
+                    if index::mode_of(metadata) != staged.mode || self.stage_blob(&path, metadata, true)? != staged.sha {
+                        Some(Change::Modified)
+                    } else {
+                        let mut fresh = IndexEntry::from_metadata(staged.path.clone(), staged.sha, metadata);
+                        fresh.assume_valid = staged.assume_valid;
+                        *staged = fresh;
+                        refreshed = true;
+                        None
+                    }
+                },
+            };
+            if entry.staged.is_some() || entry.unstaged.is_some() {
+                entries.push(entry);
+            }
+        }
+        // Whatever is left in HEAD has been removed from the index
+        for (path, version) in head_entries {
+            entries.push(StatusEntry {
+                path,
+                staged: Some(Change::Deleted),
+                unstaged: None,
+                head: Some(version),
+                index: None,
+                worktree: None,
+                conflict: None,
+            });
+        }
+        entries.sort_by(|a, b| a.path.as_bytes().cmp(b.path.as_bytes()));
+        if refreshed {
+            _ = self.write_index(&index);
+        }
+
+        let mut untracked_paths = Vec::new();
+        if untracked != UntrackedFiles::No {
+            let tracked: BTreeSet<String> = index.entries.into_iter().map(|entry| entry.path).collect();
+            self.collect_untracked("", &tracked, Some(&mut self.ignore()?), untracked, &mut untracked_paths)?;
+        }
+        Ok(Status { branch, head, entries, untracked: untracked_paths })
+    }
+
+    /// List the index the way `ls-files` does: untracked files first if asked for, then
+    /// each staged entry followed by another line for it if it is deleted or modified in
+    /// the worktree. Only paths matching `pathspec` are listed, or all of them if it is empty.
+    pub fn ls_files(&self, pathspec: &Pathspec, options: &LsFilesOptions) -> Result<Vec<String>> {
+        let index = self.read_index()?;
+        let selected = |path: &str| pathspec.is_empty() || pathspec.matches(path);
+        let stage = options.stage || options.unmerged;
+        let cached = options.cached || !(options.deleted || options.modified || options.others || stage);
+        let mut lines = Vec::new();
+
+        if options.others {
+            let tracked = index.entries.iter().map(|entry| entry.path.clone()).collect::<BTreeSet<String>>();
+            let mut ignore = match options.exclude_standard {
+                true => Some(self.ignore()?),
+                false => None,
+            };
+            let mut others = Vec::new();
+            self.collect_untracked("", &tracked, ignore.as_mut(), UntrackedFiles::All, &mut others)?;
+            lines.extend(others.into_iter().filter(|path| selected(path)));
+        }
+        for entry in index.entries.iter().filter(|entry| selected(&entry.path)) {
+            if !options.unmerged || entry.stage != 0 {
+                if stage {
+                    lines.push(format!("{:06o} {} {}\t{}", entry.mode, entry.sha, entry.stage, entry.path));
+                } else if cached {
+                    lines.push(entry.path.clone());
+                }
+            }
+            if entry.skip_worktree || !(options.deleted || options.modified) {
+                continue;
+            }
+            let deleted = fs::symlink_metadata(self.worktree.join(&entry.path)).is_err();
+            if options.deleted && deleted {
+                lines.push(entry.path.clone());
+            }
+            if options.modified && (deleted || self.is_modified(entry)?) {
+                lines.push(entry.path.clone());
+            }
+        }
+        Ok(lines)
+    }
+
+    /// The ignore rules of the worktree. `core.excludesFile` may start with `~/` for the
+    /// home directory; a relative path is taken from the worktree root.
+    pub fn ignore(&self) -> Result<Ignore> {
+        let excludes_file = self.config("core", "excludesfile").map(|path| match path.strip_prefix("~/") {
+            Some(rest) => std::env::var("HOME").map_or(PathBuf::from(&path), |home| Path::new(&home).join(rest)),
+            None => self.worktree.join(&path),
+        });
+        Ignore::new(&self.worktree, &self.minit_dir, excludes_file.as_deref())
+    }
+
+    /// Add the untracked, unignored files below the worktree directory `dir` to `found`.
+    /// In `Normal` mode a directory with nothing tracked in it is listed once as `dir/`.
+    fn collect_untracked(
+        &self,
+        dir: &str,
+        tracked: &BTreeSet<String>,
+        mut ignore: Option<&mut Ignore>,
+        mode: UntrackedFiles,
+        found: &mut Vec<String>,
+    ) -> Result<()> {
+        let mut children = self.worktree.join(dir).read_dir()?
+            .flatten()
+            .map(|entry| Ok((entry.file_name().to_string_lossy().to_string(), entry.file_type()?.is_dir())))
+            .collect::<Result<Vec<(String, bool)>>>()?;
+        children.sort();
+        for (name, is_dir) in children {
+            let path = if dir.is_empty() { name.clone() } else { format!("{}/{}", dir, name) };
+            if name == ".minit" {
+                continue;
+            }
+            if let Some(ignore) = ignore.as_deref_mut()
+                && ignore.is_ignored(&path, is_dir)?
+            {
+                continue;
+            }
+            if !is_dir {
+                if !tracked.contains(&path) {
+                    found.push(path);
+                }
+                continue;
+            }
+            let prefix = format!("{}/", path);
+            let has_tracked = tracked.range(prefix.clone()..).next().is_some_and(|tracked| tracked.starts_with(&prefix));
+            if mode == UntrackedFiles::Normal && !has_tracked {
+                let mut inside = Vec::new();
+                self.collect_untracked(&path, tracked, ignore.as_deref_mut(), mode, &mut inside)?;
+                if !inside.is_empty() {
+                    found.push(prefix);
+                }
+            } else {
+                self.collect_untracked(&path, tracked, ignore.as_deref_mut(), mode, found)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The tree of the commit HEAD points at, or `None` on a branch with no commits yet
+    pub fn head_tree(&self) -> Result<Option<ObjectId>> {
+        let head = match self.resolve_ref("HEAD") {
+            Ok(head) => head,
+            Err(Error::InvalidFilePath(_)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        match self.read_object(&head)? {
+            Object::Commit(commit) => Ok(Some(commit.tree)),
+            _ => Err(Error::WrongObjectType(head, Format::Commit, self.read_raw(&head)?.0)),
+        }
+    }
+
+    /// Every non-tree entry below `tree` by its full slash separated path, with its mode
+    pub fn flatten_tree(&self, tree: &ObjectId) -> Result<BTreeMap<String, (u32, ObjectId)>> {
+        let mut entries: BTreeMap<String, (u32, ObjectId)> = BTreeMap::new();
+        let mut stack = vec![(*tree, String::new())];
+        while let Some((sha, prefix)) = stack.pop() {
+            let Object::Tree(leaves) = self.read_object(&sha)? else {
+                return Err(Error::WrongObjectType(sha, Format::Tree, self.read_raw(&sha)?.0));
+            };
+            for leaf in leaves {
+                let path = format!("{}{}", prefix, leaf.path_str()?);
+                if leaf.is_tree() {
+                    stack.push((leaf.sha, format!("{}/", path)));
+                } else {
+                    let mode = u32::from_str_radix(&leaf.mode, 8)
+                        .map_err(|_| Error::MalformedObject(format!("invalid mode {} in tree {}", leaf.mode, sha)))?;
+                    entries.insert(path, (mode, leaf.sha));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Path of the loose object `sha` as `objects/xx/yyyy...`
+    fn object_path(&self, sha: &ObjectId, mkdir: bool) -> Result<Option<PathBuf>> {
+        let hex = sha.to_hex();
+        if sha.algorithm() != self.algorithm {
+            return Err(Error::ObjectNotDefined(hex));
+        }
+        self.repo_file(vec!["objects", &hex[0..2], &hex[2..]], mkdir)
+    }
+
     pub fn repo_path(&self, paths: Vec<&str>) -> PathBuf {
-        assert!(paths.len() > 0);
+        assert!(!paths.is_empty());
         let mut builder_string = self.minit_dir.clone();
         paths.iter().for_each(|&p| builder_string = builder_string.join(p));
         builder_string
@@ -115,7 +1385,7 @@ impl Repository {
 
     /// ONLY CREATES THE DIRECTORIES
     pub fn repo_dir(&self, paths: Vec<&str>, mkdir: bool) -> Result<Option<PathBuf>> {
-        assert!(paths.len() > 0);
+        assert!(!paths.is_empty());
         let path = self.repo_path(paths);
         // NOTE: is there a way to tidy this up?
         if path.exists() {
@@ -134,9 +1404,8 @@ impl Repository {
         }
     }
 
-    ///
     pub fn repo_file(&self, paths: Vec<&str>, mkdir: bool) -> Result<Option<PathBuf>> {
-        assert!(paths.len() > 0);
+        assert!(!paths.is_empty());
         if paths.len() == 1 {
             return Ok(Some(self.minit_dir.join(paths[0])));
         }
@@ -166,15 +1435,41 @@ impl Repository {
         }
     }
 
-    pub fn read_object(&self, sha: &str) -> Result<Object> {
-        let path = self.repo_file(vec!["objects", &sha[0..2], &sha[2..]], false)?
-            .ok_or(Error::ObjectNotDefined(sha.to_string()))?;
+    pub fn read_object(&self, sha: &ObjectId) -> Result<Object> {
+        let (format, data) = self.read_raw(sha)?;
+        Object::new(format, data, self.algorithm)
+    }
 
-        if !path.is_file() {
-            return Err(Error::ObjectNotDefined(sha.to_string()));
+    /// Return the type and body of `sha`, whether it is stored loose or in a pack
+    pub fn read_raw(&self, sha: &ObjectId) -> Result<(Format, Vec<u8>)> {
+        if let Some(path) = self.object_path(sha, false)?.filter(|path| path.is_file()) {
+            return self.read_loose(sha, &path);
         }
 
-        let mut file = OpenOptions::new().read(true).open(&path)?;
+        for pack in self.packs()?.iter() {
+            if let Some(offset) = pack.find(sha) {
+                return pack.read_at(offset, &|base: &ObjectId| self.read_raw(base));
+            }
+        }
+        Err(Error::ObjectNotDefined(sha.to_string()))
+    }
+
+    /// Every readable pack index under `objects/pack`. Damaged ones are left out here and
+    /// reported by `fsck`.
+    pub fn packs(&self) -> Result<Rc<Vec<PackIndex>>> {
+        if let Some(packs) = self.packs.borrow().as_ref() {
+            return Ok(Rc::clone(packs));
+        }
+        let packs = Rc::new(pack::list_packs(&self.repo_path(vec!["objects", "pack"]), self.algorithm)?
+            .into_iter()
+            .filter_map(|(_, index)| index.ok())
+            .collect::<Vec<PackIndex>>());
+        *self.packs.borrow_mut() = Some(Rc::clone(&packs));
+        Ok(packs)
+    }
+
+    fn read_loose(&self, sha: &ObjectId, path: &Path) -> Result<(Format, Vec<u8>)> {
+        let mut file = OpenOptions::new().read(true).open(path)?;
         let mut buf: Vec<u8> = Vec::new();
         file.read_to_end(&mut buf)?;
 
@@ -182,193 +1477,945 @@ impl Repository {
         let mut data: Vec<u8> = Vec::new();
         decoder.read_to_end(&mut data)?;
 
+        let malformed = |reason: &str| Error::MalformedObject(format!("object {} {}", sha, reason));
         let mut it = data.iter();
-        let obj_idx = it.position(|&b| b == b' ').unwrap();
-        let size_idx = obj_idx + 1 + it.position(|&b| b == b'\x00').unwrap();
-        let fmt = String::from_utf8(data[0..obj_idx].to_vec())?;
-        let size = String::from_utf8(data[obj_idx+1..size_idx].to_vec())?.parse::<usize>()?;
+        let obj_idx = it.position(|&b| b == b' ').ok_or(malformed("has no type"))?;
+        let size_idx = obj_idx + 1 + it.position(|&b| b == b'\x00').ok_or(malformed("has no size"))?;
+        let format = str::from_utf8(&data[0..obj_idx])?.parse::<Format>()?;
+        let size = str::from_utf8(&data[obj_idx+1..size_idx])?.parse::<usize>()?;
         if size != data.len() - size_idx - 1 {
-            panic!("Malformed object {}: bad length", sha);
+            return Err(malformed("has a bad length"));
         }
+        Ok((format, data[size_idx+1..].to_vec()))
+    }
 
-        let data = data[size_idx+1..].to_vec();
-        return match fmt.as_str() {
-            "commit" => Ok(Object::new(Format::Commit, data)?),
-            "blob" => Ok(Object::new(Format::Blob, data)?),
-            "tree" => Ok(Object::new(Format::Tree, data)?),
-            "tag" => Ok(Object::new(Format::Tag, data)?),
-            _ => panic!("Unknown object type {} for object {}", fmt, sha),
+    /// Return the hash
+    pub fn write_object(&self, obj: Object) -> Result<ObjectId>{
+        let data = obj.serialize()
+            .ok_or(Error::MalformedObject(String::from("object could not be serialized")))?;
+        self.write_stream(obj.format(), data.len() as u64, &data[..])
+    }
+
+    /// Hash and store an object of `size` bytes read from `reader`, holding no more than a
+    /// buffer of it in memory. The object is compressed into a temporary file under
+    /// `objects/` and renamed into place once its name is known. Return the hash.
+    pub fn write_stream<R: Read>(&self, format: Format, size: u64, mut reader: R) -> Result<ObjectId> {
+        static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let objects = self.repo_dir(vec!["objects"], true)?.unwrap();
+        let tmp_path = objects.join(format!(
+            "tmp_obj_{}_{}", std::process::id(), TMP_COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+
+        let result = (|| -> Result<ObjectId> {
+            let mut hasher = Hasher::new(self.algorithm);
+            let mut encoder = ZlibEncoder::new(BufWriter::new(File::create(&tmp_path)?), Compression::default());
+            let header = format!("{} {}\x00", format, size);
+            hasher.update(header.as_bytes());
+            encoder.write_all(header.as_bytes())?;
+
+            let mut buf = vec![0u8; 64 * 1024];
+            let mut total = 0u64;
+            loop {
+                let read = reader.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buf[..read]);
+                encoder.write_all(&buf[..read])?;
+                total += read as u64;
+            }
+            if total != size {
+                return Err(Error::MalformedObject(format!("expected {} bytes but read {}", size, total)));
+            }
+            encoder.finish()?.flush()?;
+            ObjectId::from_bytes(&hasher.finalize(), self.algorithm)
+        })();
+
+        let sha = match result {
+            Ok(sha) => sha,
+            Err(err) => {
+                _ = fs::remove_file(&tmp_path);
+                return Err(err);
+            },
+        };
+        let path = self.object_path(&sha, true)?.unwrap();
+        if path.exists() {
+            fs::remove_file(&tmp_path)?;
+        } else {
+            fs::rename(&tmp_path, &path)?;
         }
+        Ok(sha)
     }
 
-    /// Return the hash
-    pub fn write_object(&self, obj: Object) -> Result<String>{
-        let (sha, result) = obj.write()?;
-        let path = self.repo_file(vec!["objects", &sha[0..2], &sha[2..]], true)?.unwrap();
-        if !path.exists() {
-            let mut file = OpenOptions::new().create(true).write(true).open(&path)?;
-            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    /// Stream the body of the blob `sha`. Loose blobs and whole pack entries are inflated as
+    /// they are read; only deltified blobs have to be rebuilt in memory.
+    pub fn read_blob_stream(&self, sha: &ObjectId) -> Result<impl Read + use<>> {
+        let (format, reader): (Format, Box<dyn Read>) = match self.object_path(sha, false)?.filter(|path| path.is_file()) {
+            Some(path) => {
+                let mut decoder = ZlibDecoder::new(BufReader::new(File::open(&path)?));
+                let mut header: Vec<u8> = Vec::new();
+                let mut byte = [0u8; 1];
+                loop {
+                    decoder.read_exact(&mut byte)?;
+                    if byte[0] == b'\x00' {
+                        break;
+                    }
+                    header.push(byte[0]);
+                }
+                let header = String::from_utf8(header)?;
+                let (fmt, size) = header.split_once(' ')
+                    .ok_or(Error::MalformedObject(format!("object {} has a malformed header", sha)))?;
+                (fmt.parse::<Format>()?, Box::new(decoder.take(size.parse::<u64>()?)))
+            },
+            None => self.open_packed(sha)?,
+        };
+        if format != Format::Blob {
+            return Err(Error::WrongObjectType(*sha, Format::Blob, format));
+        }
+        Ok(reader)
+    }
 
-            encoder.write_all(result.as_bytes())?;
-            let compressed = encoder.finish()?; 
-            file.write_all(&compressed[..])?;
+    fn open_packed(&self, sha: &ObjectId) -> Result<(Format, Box<dyn Read>)> {
+        for pack in self.packs()?.iter() {
+            if let Some(offset) = pack.find(sha) {
+                if let Some((format, _, reader)) = pack.open_at(offset)? {
+                    return Ok((format, Box::new(reader)));
+                }
+                let (format, data) = pack.read_at(offset, &|base: &ObjectId| self.read_raw(base))?;
+                return Ok((format, Box::new(Cursor::new(data))));
+            }
         }
-        return Ok(sha);
+        Err(Error::ObjectNotDefined(sha.to_string()))
     }
 
-    /// Return the hash
-    pub fn find_object(&self, name: &str, format: Option<Format>, follow: bool) -> Result<String> {
-        // NOTE: throw error when the found object is not the appropriate format
-        let sha = self.resolve_object(name)?;
-        if sha.len() != 1 {
-            return Err(Error::AmbiguousReference(sha))
-        }
-        let mut sha: String = sha.get(0).unwrap().clone();
-
-        if format.is_none() {
-            return Ok(sha.clone());
-        } 
-        let format = format.unwrap();
-
-        while true {
-            let obj = self.read_object(&sha)?;
-            if obj.format() == format {
-                return Ok(sha);
-            }
-            if !follow {
-                return Err(Error::ObjectNotFound);
-            }
-            match obj {
-                Object::Tag(map) => {
-                    sha = map.get("object").unwrap().get(0).unwrap().clone();
-                },
-                Object::Commit(map) => {
-                    sha = map.get("tree").unwrap().get(0).unwrap().clone();
-                },
-                _ => return Err(Error::ObjectNotFound)
+    /// The object `name` stands for. With a `format`, it must be of that type, or with
+    /// `follow` set, peel to one (see `peel`).
+    pub fn find_object(&self, name: &str, format: Option<Format>, follow: bool) -> Result<ObjectId> {
+        let candidates = self.resolve_object(name)?;
+        let sha = match candidates.as_slice() {
+            [] => return Err(Error::ObjectNotDefined(name.to_string())),
+            [sha] => *sha,
+            _ => return Err(Error::AmbiguousReference(candidates)),
+        };
+        match format {
+            None => Ok(sha),
+            Some(format) if follow => self.peel(&sha, Some(format)),
+            Some(format) => match self.read_raw(&sha)?.0 {
+                found if found == format => Ok(sha),
+                found => Err(Error::WrongObjectType(sha, format, found)),
+            },
+        }
+    }
+
+    /// Follow `sha` through any number of tags to an object of type `format`, going from
+    /// a commit to its tree if a tree is wanted. Without a `format`, stop at the first
+    /// object that isn't a tag.
+    pub fn peel(&self, sha: &ObjectId, format: Option<Format>) -> Result<ObjectId> {
+        let mut sha = *sha;
+        let mut seen: HashSet<ObjectId> = HashSet::new();
+        loop {
+            if !seen.insert(sha) {
+                return Err(Error::MalformedObject(format!("tag {} points back at itself", sha)));
             }
-            break;
+            match (self.read_object(&sha)?, &format) {
+                (object, Some(format)) if object.format() == *format => return Ok(sha),
+                (Object::Tag(tag), _) => sha = tag.object,
+                (Object::Commit(commit), Some(Format::Tree)) => sha = commit.tree,
+                (_, None) => return Ok(sha),
+                (object, Some(format)) => return Err(Error::WrongObjectType(sha, format.clone(), object.format())),
+            }
+        }
+    }
+
+    /// The blob or tree at `path`, slash separated, in the tree `sha` peels to. An empty
+    /// path is the tree itself.
+    pub fn peel_path(&self, sha: &ObjectId, path: &str) -> Result<ObjectId> {
+        let mut sha = self.peel(sha, Some(Format::Tree))?;
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            let leaves = match self.read_object(&sha)? {
+                Object::Tree(leaves) => leaves,
+                object => return Err(Error::WrongObjectType(sha, Format::Tree, object.format())),
+            };
+            sha = leaves.into_iter()
+                .find(|leaf| leaf.path == component.as_bytes())
+                .ok_or_else(|| Error::PathNotFound(sha, path.to_string()))?
+                .sha;
         }
-        Ok(name.to_string())
+        Ok(sha)
     }
 
-    pub fn resolve_ref(&self, reference: &str) -> Result<String> {
+    /// The object the ref `reference` points at, following symbolic refs. A loose ref
+    /// shadows one of the same name in `packed-refs`.
+    pub fn resolve_ref(&self, reference: &str) -> Result<ObjectId> {
         let path = self.repo_file(vec![reference], false)?.unwrap();
 
         if !path.is_file() {
-            return Err(Error::InvalidFilePath(path));
+            return match self.packed_refs()?.get(reference) {
+                Some(packed) => Ok(packed.sha),
+                None => Err(Error::InvalidFilePath(path)),
+            };
         }
 
         let mut file = OpenOptions::new().read(true).open(&path)?;
         let mut buf = Vec::new();
-        file.read(&mut buf)?;
-        let data= str::from_utf8(&buf)?;
-        if data.starts_with("ref: ") {
-            self.resolve_ref(&data[5..]) 
+        file.read_to_end(&mut buf)?;
+        let data = str::from_utf8(&buf)?.trim_end();
+        if let Some(target) = data.strip_prefix("ref: ") {
+            self.resolve_ref(target)
         } else {
-            Ok(data.to_string())
+            ObjectId::from_hex(data, self.algorithm)
+        }
+    }
+
+    /// Whether the ref `name` exists, as a loose file or in `packed-refs`
+    pub fn ref_exists(&self, name: &str) -> bool {
+        self.repo_path(name.split('/').collect()).is_file()
+            || self.packed_refs().is_ok_and(|packed| packed.get(name).is_some())
+    }
+
+    /// The refs in `.minit/packed-refs`, empty if there is no such file
+    pub fn packed_refs(&self) -> Result<PackedRefs> {
+        match fs::read(self.repo_path(vec!["packed-refs"])) {
+            Ok(data) => PackedRefs::parse(&data, self.algorithm),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(PackedRefs::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The names of the refs stored in files of their own under `refs/`, symbolic ones included
+    pub(crate) fn loose_ref_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut dirs = vec![self.repo_path(vec!["refs"])];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = dir.read_dir() else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let entry_path = entry.path();
+                if entry_path.is_dir() {
+                    dirs.push(entry_path);
+                } else if entry_path.extension().is_none_or(|extension| extension != "lock") {
+                    let reference = entry_path
+                        .strip_prefix(&self.minit_dir)
+                        .map_err(|_| Error::InvalidFilePath(entry_path.clone()))?
+                        .to_str()
+                        .ok_or(Error::InvalidFilePath(entry_path.clone()))?;
+                    names.push(reference.to_string());
+                }
+            }
         }
+        names.sort();
+        Ok(names)
     }
 
-    pub fn ls_ref(&self, path: Option<&Path>) -> Result<IndexMap<String, String>> {
-        let mut map: IndexMap<String, String> = IndexMap::new();
-        self.populate_ref_map(&mut map, path)?;
+    /// Move loose refs into `packed-refs`, see [`refs::pack_refs`]
+    pub fn pack_refs(&self, all: bool, prune: bool) -> Result<()> {
+        refs::pack_refs(self, all, prune)
+    }
+
+    /// Every ref under `path` (all of `refs/` by default) with the object it points at,
+    /// sorted by name. Loose refs shadow packed ones.
+    pub fn ls_ref(&self, path: Option<&Path>) -> Result<IndexMap<String, ObjectId>> {
+        let ref_path = match path {
+            Some(path) => path.to_path_buf(),
+            None => self.repo_path(vec!["refs"]),
+        };
+        let prefix = ref_path
+            .strip_prefix(&self.minit_dir)
+            .map_err(|_| Error::InvalidFilePath(ref_path.clone()))?
+            .to_str()
+            .ok_or(Error::InvalidFilePath(ref_path.clone()))?
+            .to_string();
+        let mut map: IndexMap<String, ObjectId> = IndexMap::new();
+        if ref_path.is_dir() {
+            self.populate_ref_map(&mut map, &ref_path)?;
+        }
+        for packed in self.packed_refs()?.refs {
+            if packed.name.strip_prefix(&prefix).is_some_and(|rest| rest.starts_with('/')) && !map.contains_key(&packed.name) {
+                map.insert(packed.name, packed.sha);
+            }
+        }
+        map.sort_keys();
         Ok(map)
     }
 
-    fn populate_ref_map(&self, map: &mut IndexMap<String, String>, path: Option<&Path>) -> Result<()> {
-        let ref_path = path
-            .map(|p| p.to_path_buf())
-            .unwrap_or(self.repo_dir(vec!["refs"], false)?.expect("refs directory must be defined"));
+    fn populate_ref_map(&self, map: &mut IndexMap<String, ObjectId>, ref_path: &Path) -> Result<()> {
         let mut v = ref_path
             .read_dir()?
             .flatten()
             .collect::<Vec<DirEntry>>();
         v.sort_by_key(|d| d.path());
-        let _ = v.iter().map(|p| -> Result<()> {
-            let dir_entry = p.path();
-            if dir_entry.is_dir() {
-                let dir_path = ref_path.join(dir_entry);
-                _ = self.populate_ref_map(map, Some(&dir_path));
+        for entry in v {
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                self.populate_ref_map(map, &entry_path)?;
+            } else if entry_path.extension().is_some_and(|extension| extension == "lock") {
+                // A ref that is being updated
+                continue;
             } else {
-                let reference = ref_path.to_str().unwrap();
+                let reference = entry_path
+                    .strip_prefix(&self.minit_dir)
+                    .map_err(|_| Error::InvalidFilePath(entry_path.clone()))?
+                    .to_str()
+                    .ok_or(Error::InvalidFilePath(entry_path.clone()))?;
                 let sha = self.resolve_ref(reference)?;
-                map.insert(ref_path.to_str().unwrap().to_string(), sha);
+                map.insert(reference.to_string(), sha);
             }
-            Ok(())
-        });
+        }
+        Ok(())
+    }
+
+    /// Every object reachable from HEAD, the refs, the reflogs and the index. Blobs are named by
+    /// the trees that contain them and never read.
+    pub fn reachable_objects(&self) -> Result<Vec<ObjectId>> {
+        Ok(self.reachable_paths()?.into_iter().map(|(sha, _)| sha).collect())
+    }
+
+    /// Like `reachable_objects`, paired with the worktree path each tree or blob was first
+    /// reached through
+    pub fn reachable_paths(&self) -> Result<Vec<(ObjectId, Option<String>)>> {
+        let mut stack = self.ls_ref(None)?
+            .into_values()
+            .map(|sha| (sha, None))
+            .collect::<Vec<(ObjectId, Option<String>)>>();
+        if let Ok(head) = self.resolve_ref("HEAD") {
+            stack.push((head, None));
+        }
+        // A reflog entry whose object is already gone has nothing left to keep
+        stack.extend(self.reflog_objects()?.into_iter()
+            .filter(|sha| self.read_raw(sha).is_ok())
+            .map(|sha| (sha, None)));
+        let mut seen: HashSet<ObjectId> = HashSet::new();
+        let mut reachable: Vec<(ObjectId, Option<String>)> = Vec::new();
+        // Staged content must survive a prune even before it is committed
+        for entry in self.read_index()?.entries {
+            if !entry.intent_to_add && entry.mode != index::MODE_GITLINK && seen.insert(entry.sha) {
+                reachable.push((entry.sha, Some(entry.path)));
+            }
+        }
+        while let Some((sha, path)) = stack.pop() {
+            if !seen.insert(sha) {
+                continue;
+            }
+            match self.read_object(&sha)? {
+                Object::Commit(commit) => {
+                    stack.push((commit.tree, None));
+                    stack.extend(commit.parents.into_iter().map(|parent| (parent, None)));
+                },
+                Object::Tag(tag) => {
+                    stack.push((tag.object, None));
+                },
+                Object::Tree(leaves) => {
+                    for leaf in leaves {
+                        // Only used to group delta candidates, so a lossy name will do
+                        let name = String::from_utf8_lossy(&leaf.path);
+                        let leaf_path = match &path {
+                            Some(prefix) => format!("{}/{}", prefix, name),
+                            None => name.into_owned(),
+                        };
+                        match leaf.get_type()? {
+                            "tree" => stack.push((leaf.sha, Some(leaf_path))),
+                            "blob" if seen.insert(leaf.sha) => reachable.push((leaf.sha, Some(leaf_path))),
+                            // Submodule commits live in another repository
+                            _ => {},
+                        }
+                    }
+                },
+                Object::Blob(_) => {},
+            }
+            reachable.push((sha, path));
+        }
+        Ok(reachable)
+    }
+
+    /// Re-hash and parse every loose and packed copy of every object, then walk from HEAD,
+    /// the refs and the reflogs to find objects that are missing or unreachable.
+    pub fn fsck(&self) -> Result<FsckReport> {
+        let mut report = FsckReport::default();
+        let mut formats: HashMap<ObjectId, Format> = HashMap::new();
+        let mut links: HashMap<ObjectId, Vec<(ObjectId, Format)>> = HashMap::new();
+        let mut check = |sha: ObjectId, read: Result<(Format, Vec<u8>)>, report: &mut FsckReport| {
+            report.checked += 1;
+            match read {
+                Ok((format, data)) => {
+                    formats.entry(sha).or_insert(format.clone());
+                    if let Some(object) = fsck::check_object(&sha, format, data, self.algorithm, &mut report.issues) {
+                        links.entry(sha).or_insert_with(|| fsck::links(&object));
+                    }
+                },
+                Err(err) => report.issues.push(FsckIssue::Corrupt(sha, err.to_string())),
+            }
+        };
+
+        let mut present: HashSet<ObjectId> = HashSet::new();
+        for sha in self.loose_objects()? {
+            let path = self.object_path(&sha, false)?.unwrap();
+            check(sha, self.read_loose(&sha, &path), &mut report);
+            present.insert(sha);
+        }
+        for (path, index) in pack::list_packs(&self.repo_path(vec!["objects", "pack"]), self.algorithm)? {
+            let pack = match index {
+                Ok(pack) => pack,
+                Err(err) => {
+                    report.issues.push(FsckIssue::BadPack(path, err.to_string()));
+                    continue;
+                },
+            };
+            for sha in pack.ids() {
+                let offset = pack.find(&sha).unwrap();
+                check(sha, pack.read_at(offset, &|base: &ObjectId| self.read_raw(base)), &mut report);
+                present.insert(sha);
+            }
+        }
+
+        let mut stack: Vec<(ObjectId, Option<Format>)> = self.ls_ref(None)?
+            .into_values()
+            .map(|sha| (sha, None))
+            .collect();
+        if let Ok(head) = self.resolve_ref("HEAD") {
+            stack.push((head, Some(Format::Commit)));
+        }
+        stack.extend(self.reflog_objects()?.into_iter().map(|sha| (sha, None)));
+        stack.extend(self.read_index()?.entries.into_iter()
+            .filter(|entry| !entry.intent_to_add && entry.mode != index::MODE_GITLINK)
+            .map(|entry| (entry.sha, Some(Format::Blob))));
+        let mut reachable: HashSet<ObjectId> = HashSet::new();
+        while let Some((sha, expected)) = stack.pop() {
+            if !reachable.insert(sha) {
+                continue;
+            }
+            if !present.contains(&sha) {
+                report.issues.push(FsckIssue::Missing(expected, sha));
+                continue;
+            }
+            if let (Some(expected), Some(found)) = (&expected, formats.get(&sha))
+                && expected != found
+            {
+                report.issues.push(FsckIssue::WrongType(sha, expected.clone(), found.clone()));
+            }
+            for (target, format) in links.get(&sha).into_iter().flatten() {
+                stack.push((*target, Some(format.clone())));
+            }
+        }
+
+        // An unreachable object that another unreachable object points at isn't dangling
+        let mut unreachable = present.difference(&reachable).copied().collect::<Vec<ObjectId>>();
+        unreachable.sort();
+        let referenced: HashSet<ObjectId> = unreachable.iter()
+            .flat_map(|sha| links.get(sha).into_iter().flatten().map(|(target, _)| *target))
+            .collect();
+        for sha in unreachable {
+            if let Some(format) = formats.get(&sha) {
+                report.issues.push(match referenced.contains(&sha) {
+                    true => FsckIssue::Unreachable(format.clone(), sha),
+                    false => FsckIssue::Dangling(format.clone(), sha),
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// Every object stored loose under `objects/xx/`
+    pub fn loose_objects(&self) -> Result<Vec<ObjectId>> {
+        let mut shas: Vec<ObjectId> = Vec::new();
+        let objects = self.repo_path(vec!["objects"]);
+        if !objects.is_dir() {
+            return Ok(shas);
+        }
+        for dir in objects.read_dir()?.flatten() {
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            if prefix.len() != 2 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+            for file in dir.path().read_dir()?.flatten() {
+                let hex = prefix.clone() + &file.file_name().to_string_lossy();
+                if let Ok(sha) = ObjectId::from_hex(&hex, self.algorithm) {
+                    shas.push(sha);
+                }
+            }
+        }
+        shas.sort();
+        Ok(shas)
+    }
+
+    /// Pack every reachable object into one new pack, then delete the loose copies and any
+    /// older packs it makes redundant. Returns the new pack index, or `None` when nothing
+    /// is reachable.
+    pub fn repack(&self, options: &DeltaOptions) -> Result<Option<PathBuf>> {
+        let reachable = self.reachable_paths()?;
+        if reachable.is_empty() {
+            return Ok(None);
+        }
+        let objects = reachable.iter()
+            .map(|(sha, path)| self.read_raw(sha)
+                .map(|(format, data)| PackObject { sha: *sha, format, data, path: path.clone() }))
+            .collect::<Result<Vec<PackObject>>>()?;
+        let shas = reachable.into_iter().map(|(sha, _)| sha).collect::<Vec<ObjectId>>();
+        let old_packs = self.packs()?;
+        let written = pack::write_pack(&self.repo_path(vec!["objects", "pack"]), objects, self.algorithm, options);
+        self.packs.replace(None);
+        let idx_path = written?;
+
+        let packed: HashSet<&ObjectId> = shas.iter().collect();
+        for old in old_packs.iter() {
+            // An identical pack keeps its name, so it is the one just written
+            if old.pack_path == idx_path.with_extension("pack") {
+                continue;
+            }
+            if old.ids().all(|sha| packed.contains(&sha)) {
+                fs::remove_file(old.pack_path.with_extension("idx"))?;
+                fs::remove_file(&old.pack_path)?;
+            }
+        }
+        for sha in &shas {
+            self.remove_loose(sha)?;
+        }
+        Ok(Some(idx_path))
+    }
+
+    /// Delete loose objects that are not reachable from any ref, returning their names
+    pub fn prune_loose(&self) -> Result<Vec<ObjectId>> {
+        let reachable: HashSet<ObjectId> = self.reachable_objects()?.into_iter().collect();
+        let pruned = self.loose_objects()?
+            .into_iter()
+            .filter(|sha| !reachable.contains(sha))
+            .collect::<Vec<ObjectId>>();
+        for sha in &pruned {
+            self.remove_loose(sha)?;
+        }
+        Ok(pruned)
+    }
+
+    fn remove_loose(&self, sha: &ObjectId) -> Result<()> {
+        if let Some(path) = self.object_path(sha, false)?.filter(|path| path.is_file()) {
+            fs::remove_file(&path)?;
+            if let Some(dir) = path.parent().filter(|dir| dir.read_dir().is_ok_and(|mut d| d.next().is_none())) {
+                fs::remove_dir(dir)?;
+            }
+        }
         Ok(())
     }
 
     pub fn create_tag(&self, name: &str, reference: &str, add: bool) -> Result<()> {
         let sha = self.find_object(reference, None, false)?;
+        // Refuse before writing a tag object that nothing would point at. The update below
+        // expects the ref to be missing, so a tag created in between still isn't replaced.
+        let ref_name = format!("refs/tags/{}", name);
+        if !check_ref_format(&ref_name) {
+            return Err(Error::Refused(format!("'{}' is not a valid ref name", ref_name)));
+        }
+        if self.ref_exists(&ref_name) {
+            return Err(Error::Refused(format!("tag '{}' already exists", name)));
+        }
         let sha = if add {
-            let mut map: IndexMap<String, Vec<String>> = IndexMap::new();
-            map.insert(String::from("object"), vec![sha]);
-            map.insert(String::from("type"), vec![String::from("commit")]);
-            map.insert(String::from("tag"), vec![String::from(name)]);
-            map.insert(String::from("tagger"), vec![String::from("Wyag <wyag@example.com>")]);
-            map.insert(String::from("message"), vec![String::from("A tag generated by wyag, which won't let you customize the message!\n")]);
-            let object = Object::Tag(map);
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+            let object = Object::Tag(Tag {
+                object: sha,
+                kind: self.read_raw(&sha)?.0,
+                name: String::from(name),
+                tagger: Some(Identity::new("Wyag", "wyag@example.com", now, 0)),
+                extra_headers: Vec::new(),
+                message: Some(b"A tag generated by wyag, which won't let you customize the message!\n".to_vec()),
+            });
             self.write_object(object)?
         } else {
             sha
         };
-        let ref_name = String::from("tags/") + name;
-        self.create_ref(&ref_name, &sha)?;
+        let message = format!("tag: tagging {} ({})", sha, self.read_raw(&sha)?.0);
+        let mut transaction = self.transaction();
+        transaction.update(&ref_name, RefValue::Object(sha), Expected::Missing, &message);
+        transaction.commit()
+    }
+
+    /// Every object `name` could mean. A plain name may be an abbreviated object id as well
+    /// as a ref, so it can have several candidates or none; anything using the rest of the
+    /// gitrevisions(7) syntax, like `HEAD~2:src` or `main@{yesterday}`, resolves to exactly
+    /// one object or fails.
+    pub fn resolve_object(&self, name: &str) -> Result<Vec<ObjectId>> {
+        if name.is_empty() {
+            return Err(Error::NameNotDefined);
+        }
+        match Revision::parse(name)? {
+            Revision::Name(name) => self.resolve_name(&name),
+            revision => Ok(vec![self.resolve_revision(&revision, name)?]),
+        }
+    }
+
+    fn resolve_name(&self, name: &str) -> Result<Vec<ObjectId>> {
+        let mut candidates: Vec<ObjectId> = Vec::new();
+        if name == "HEAD" {
+            return Ok(vec![self.resolve_ref("HEAD")?])
+        }
+
+        if let Ok(prefix) = ObjectIdPrefix::parse(name, self.algorithm) {
+            let dir = &prefix.as_str()[0..2];
+            if let Some(path) = self.repo_dir(vec!["objects", dir], false)? {
+                path.read_dir()?
+                    .flatten()
+                    .filter_map(|entry| ObjectId::from_hex(&(dir.to_string() + &entry.file_name().to_string_lossy()), self.algorithm).ok())
+                    .filter(|sha| prefix.matches(sha))
+                    .for_each(|sha| candidates.push(sha));
+            }
+            for pack in self.packs()?.iter() {
+                pack.find_prefix(&prefix)
+                    .into_iter()
+                    .for_each(|sha| if !candidates.contains(&sha) { candidates.push(sha) });
+            }
+        }
+
+        if let Some(reference) = self.dwim_ref(name)
+            && let Ok(sha) = self.resolve_ref(&reference)
+            && !candidates.contains(&sha)
+        {
+            candidates.push(sha);
+        }
+        Ok(candidates)
+    }
+
+    /// The full name of the ref `name` stands for, trying it as given (for `refs/...` and
+    /// pseudo-refs like `ORIG_HEAD`) and then under `refs/`, `refs/tags/`, `refs/heads/`
+    /// and `refs/remotes/`
+    pub fn dwim_ref(&self, name: &str) -> Option<String> {
+        let pseudo = name.bytes().all(|c| c.is_ascii_uppercase() || c == b'_');
+        ["{}", "refs/{}", "refs/tags/{}", "refs/heads/{}", "refs/remotes/{}"]
+            .into_iter()
+            .map(|rule| rule.replace("{}", name))
+            .filter(|reference| reference.starts_with("refs/") || pseudo)
+            .find(|reference| check_ref_format(reference) && self.ref_exists(reference))
+    }
+
+    /// The updates of the ref `name` recorded in `.minit/logs`, oldest first
+    pub fn reflog(&self, name: &str) -> Result<Vec<ReflogEntry>> {
+        let path = self.repo_path([vec!["logs"], name.split('/').collect()].concat());
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        data.split(|&c| c == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| ReflogEntry::parse(line, self.algorithm))
+            .collect()
+    }
+
+    /// Every object named on either side of an entry in any reflog
+    fn reflog_objects(&self) -> Result<Vec<ObjectId>> {
+        let mut shas = Vec::new();
+        for name in self.reflogs()? {
+            for entry in self.reflog(&name)? {
+                shas.extend([entry.old, entry.new].into_iter().filter(|sha| !sha.is_null()));
+            }
+        }
+        Ok(shas)
+    }
+
+    /// Every ref with a reflog, HEAD first
+    pub fn reflogs(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        if self.repo_path(vec!["logs", "HEAD"]).is_file() {
+            names.push(String::from("HEAD"));
+        }
+        let mut dirs = vec![self.repo_path(vec!["logs", "refs"])];
+        let mut refs = Vec::new();
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = dir.read_dir() else { continue };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if let Ok(name) = path.strip_prefix(self.repo_path(vec!["logs"])) {
+                    refs.push(name.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/"));
+                }
+            }
+        }
+        refs.sort();
+        names.extend(refs);
+        Ok(names)
+    }
+
+    /// Whether updates of `name` are logged. `core.logAllRefUpdates` is `true` unless the
+    /// repository is bare, which logs HEAD, branches, remote-tracking branches and notes;
+    /// `always` logs every ref. A ref that already has a reflog is always logged.
+    fn should_log(&self, name: &str) -> bool {
+        let setting = self.config("core", "logallrefupdates")
+            .unwrap_or_else(|| String::from(if self.config("core", "bare").as_deref() == Some("true") { "false" } else { "true" }));
+        let logged = match setting.to_lowercase().as_str() {
+            "always" => true,
+            "true" | "yes" | "on" | "1" => {
+                name == "HEAD" || ["refs/heads/", "refs/remotes/", "refs/notes/"].iter().any(|prefix| name.starts_with(prefix))
+            },
+            _ => false,
+        };
+        logged || self.repo_path([vec!["logs"], name.split('/').collect()].concat()).is_file()
+    }
+
+    /// Record that `name` moved from `old` (`None` if it didn't exist) to `new`, if its
+    /// updates are logged. The committer identity is used, or the login name if there is
+    /// none, as git does.
+    pub(crate) fn append_reflog(&self, name: &str, old: Option<&ObjectId>, new: &ObjectId, message: &str) -> Result<()> {
+        if !self.should_log(name) {
+            return Ok(());
+        }
+        let identity = match self.identity("COMMITTER") {
+            Ok(identity) => identity,
+            Err(_) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+                Identity::new(std::env::var("USER").unwrap_or_else(|_| String::from("unknown")), "", now, 0)
+            },
+        };
+        let entry = ReflogEntry {
+            old: old.copied().unwrap_or_else(|| ObjectId::null(self.algorithm)),
+            new: *new,
+            identity,
+            message: message.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<&str>>().join(" "),
+        };
+        let mut path = vec!["logs"];
+        path.extend(name.split('/'));
+        let mut file = OpenOptions::new().create(true).append(true).open(self.repo_file(path, true)?.unwrap())?;
+        let mut line = entry.to_bytes();
+        line.push(b'\n');
+        file.write_all(&line)?;
         Ok(())
     }
 
-    fn create_ref(&self, ref_name: &str, sha: &str) -> Result<()> {
-        let path = self.repo_file(vec!["refs/", ref_name], false)?.unwrap();
-        let mut file = OpenOptions::new().write(true).open(&path)?;
-        file.write(sha.as_bytes())?;
-        file.write("\n".as_bytes())?;
+    /// Remove the reflog of `name` and the directories that leaves empty
+    pub(crate) fn delete_reflog(&self, name: &str) -> Result<()> {
+        let path = self.repo_path([vec!["logs"], name.split('/').collect()].concat());
+        match fs::remove_file(&path) {
+            Ok(()) => {},
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+        refs::remove_empty_dirs(&path, &self.repo_path(vec!["logs", "refs"]));
         Ok(())
     }
 
-    pub fn resolve_object(&self, name: &str) -> Result<Vec<String>> {
-        let mut candidates: Vec<String> = Vec::new();
-        let re = Regex::new(r"^([a-fA-F0-9]{4,64})$").unwrap();
-        if name.len() == 0 {
-            return Err(Error::NameNotDefined);
+    /// Replace the reflog of `name` with `entries`, oldest first
+    fn write_reflog(&self, name: &str, entries: &[ReflogEntry]) -> Result<()> {
+        let path = self.repo_path([vec!["logs"], name.split('/').collect()].concat());
+        let lock_path = PathBuf::from(format!("{}.lock", path.display()));
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&lock_path)
+            .map_err(|_| Error::Refused(format!("cannot lock the reflog of '{}'", name)))?;
+        let data = entries.iter()
+            .flat_map(|entry| [entry.to_bytes(), b"\n".to_vec()])
+            .flatten()
+            .collect::<Vec<u8>>();
+        let result = file.write_all(&data).and_then(|_| file.sync_all());
+        if let Err(err) = result {
+            _ = fs::remove_file(&lock_path);
+            return Err(err.into());
         }
+        fs::rename(&lock_path, &path)?;
+        Ok(())
+    }
 
-        if name == "HEAD" {
-            return Ok(vec![self.resolve_ref("HEAD")?])
+    /// Drop the entries of the reflog of `name` older than `before`, in seconds since the
+    /// Unix epoch, returning how many were dropped
+    pub fn expire_reflog(&self, name: &str, before: i64) -> Result<usize> {
+        let entries = self.reflog(name)?;
+        let kept = entries.iter().filter(|entry| entry.identity.time >= before).cloned().collect::<Vec<ReflogEntry>>();
+        if kept.len() != entries.len() {
+            self.write_reflog(name, &kept)?;
         }
-        
-        if re.is_match(&name) {
-            let name = name.to_lowercase();
-            let prefix = &name[0..2];
-            match self.repo_dir(vec!["objects", prefix], false)? {
-                Some(path) => {
-                    let rem = &name[2..];
-                    path.read_dir()?
-                        .map(|p| p.map(|entry| entry.file_name()))
-                        .flatten()
-                        .filter(|p| p.to_str().unwrap().starts_with(rem))
-                        .for_each(|p| {
-                            candidates.push(prefix.to_string() + p.to_str().unwrap());
-                        });
-                },
-                _ => {}
+        Ok(entries.len() - kept.len())
+    }
+
+    /// Delete the entry `name@{n}` from the reflog of `name`, counting from the newest
+    pub fn delete_reflog_entry(&self, name: &str, n: usize) -> Result<ReflogEntry> {
+        let mut entries = self.reflog(name)?;
+        if n >= entries.len() {
+            return Err(Error::Refused(format!("log for '{}' only has {} entries", name, entries.len())));
+        }
+        let entry = entries.remove(entries.len() - 1 - n);
+        self.write_reflog(name, &entries)?;
+        Ok(entry)
+    }
+
+    /// Resolve a parsed revision; `text` is what it was parsed from, for errors
+    fn resolve_revision(&self, revision: &Revision, text: &str) -> Result<ObjectId> {
+        let unknown = |reason: String| Error::UnknownRevision(text.to_string(), reason);
+        match revision {
+            Revision::Name(name) => {
+                let candidates = self.resolve_name(name)?;
+                match candidates.len() {
+                    0 => Err(unknown(format!("'{}' names no object or ref", name))),
+                    1 => Ok(candidates[0]),
+                    _ => Err(Error::AmbiguousReference(candidates)),
+                }
+            },
+            Revision::Reflog(name, selector) => {
+                let reference = match name.as_str() {
+                    "" => self.head_ref()?.unwrap_or_else(|| String::from("HEAD")),
+                    name => self.dwim_ref(name).ok_or_else(|| unknown(format!("no ref named '{}'", name)))?,
+                };
+                let log = self.reflog(&reference)?;
+                match *selector {
+                    ReflogSelector::Nth(0) if log.is_empty() => self.resolve_ref(&reference),
+                    ReflogSelector::Nth(n) => log.iter().rev().nth(n)
+                        .map(|entry| entry.new)
+                        .ok_or_else(|| unknown(format!("log for '{}' only has {} entries", reference, log.len()))),
+                    ReflogSelector::Date(time) => match log.iter().rev().find(|entry| entry.identity.time <= time) {
+                        Some(entry) => Ok(entry.new),
+                        None => log.first()
+                            .map(|entry| entry.old)
+                            .filter(|old| !old.is_null())
+                            .ok_or_else(|| unknown(format!("log for '{}' doesn't go back that far", reference))),
+                    },
+                }
+            },
+            Revision::Upstream(name) => {
+                let branch = match name.as_str() {
+                    "" | "HEAD" => self.head_ref()?
+                        .and_then(|head| head.strip_prefix("refs/heads/").map(String::from))
+                        .ok_or_else(|| unknown(String::from("HEAD does not point to a branch")))?,
+                    name => name.to_string(),
+                };
+                let upstream = self.upstream(&branch)
+                    .ok_or_else(|| unknown(format!("no upstream configured for branch '{}'", branch)))?;
+                self.resolve_ref(&upstream)
+                    .map_err(|_| unknown(format!("upstream branch '{}' does not exist", upstream)))
+            },
+            Revision::PreviousCheckout(n) => {
+                let previous = self.reflog("HEAD")?
+                    .into_iter()
+                    .rev()
+                    .filter_map(|entry| {
+                        let moves = entry.message.strip_prefix("checkout: moving from ")?;
+                        moves.split_once(" to ").map(|(from, _)| from.to_string())
+                    })
+                    .nth(n - 1)
+                    .ok_or_else(|| unknown(format!("only {} checkouts ago is not in the reflog", n)))?;
+                self.resolve_revision(&Revision::Name(previous), text)
+            },
+            Revision::Parent(base, n) => {
+                let sha = self.peel(&self.resolve_revision(base, text)?, Some(Format::Commit))?;
+                let Object::Commit(commit) = self.read_object(&sha)? else { unreachable!() };
+                match n {
+                    0 => Ok(sha),
+                    n => commit.parents.get(n - 1)
+                        .copied()
+                        .ok_or_else(|| unknown(format!("commit {} has no parent {}", sha, n))),
+                }
+            },
+            Revision::Ancestor(base, n) => {
+                let mut sha = self.peel(&self.resolve_revision(base, text)?, Some(Format::Commit))?;
+                for _ in 0..*n {
+                    let Object::Commit(commit) = self.read_object(&sha)? else {
+                        return Err(Error::WrongObjectType(sha, Format::Commit, self.read_object(&sha)?.format()));
+                    };
+                    sha = *commit.parents.first().ok_or_else(|| unknown(format!("commit {} has no parent", sha)))?;
+                }
+                Ok(sha)
+            },
+            Revision::Peel(base, target) => {
+                let sha = self.resolve_revision(base, text)?;
+                match target {
+                    PeelTarget::Object => self.read_object(&sha).map(|_| sha),
+                    PeelTarget::Tags => self.peel(&sha, None),
+                    PeelTarget::Type(format) => self.peel(&sha, Some(format.clone())),
+                }
+            },
+            Revision::Path(base, path) => self.peel_path(&self.resolve_revision(base, text)?, path),
+            Revision::Index(stage, path) => self.read_index()?
+                .entries
+                .iter()
+                .find(|entry| entry.path == *path && entry.stage == *stage)
+                .map(|entry| entry.sha)
+                .ok_or_else(|| unknown(format!("path '{}' is not in the index at stage {}", path, stage))),
+            Revision::MessageSearch(pattern) => {
+                let regex = Regex::new(pattern).map_err(|err| Error::BadRevision(text.to_string(), 2, err.to_string()))?;
+                self.find_commit_message(&regex)?
+                    .ok_or_else(|| unknown(format!("no commit message matches '{}'", pattern)))
+            },
+        }
+    }
+
+    /// The youngest commit reachable from HEAD or a ref whose message matches `regex`
+    fn find_commit_message(&self, regex: &Regex) -> Result<Option<ObjectId>> {
+        let mut queue: BinaryHeap<(i64, ObjectId)> = BinaryHeap::new();
+        let mut seen: HashSet<ObjectId> = HashSet::new();
+        let mut push = |queue: &mut BinaryHeap<(i64, ObjectId)>, sha: ObjectId| -> Result<()> {
+            if seen.insert(sha) && let Ok(sha) = self.peel(&sha, Some(Format::Commit))
+                && let Object::Commit(commit) = self.read_object(&sha)?
+            {
+                queue.push((commit.committer.time, sha));
+            }
+            Ok(())
+        };
+        if let Ok(head) = self.resolve_ref("HEAD") {
+            push(&mut queue, head)?;
+        }
+        for sha in self.ls_ref(None)?.into_values() {
+            push(&mut queue, sha)?;
+        }
+        while let Some((_, sha)) = queue.pop() {
+            let Object::Commit(commit) = self.read_object(&sha)? else { unreachable!() };
+            if regex.is_match(&String::from_utf8_lossy(commit.message.as_deref().unwrap_or_default())) {
+                return Ok(Some(sha));
+            }
+            for parent in commit.parents {
+                push(&mut queue, parent)?;
             }
         }
+        Ok(None)
+    }
+}
 
-        let reference_path = "refs/tags/".to_string() + name;
-        _ = self.resolve_ref(&reference_path)
-            .inspect(|tag| candidates.push(tag.clone()));
 
-        let branch_path = "refs/heads/".to_string() + name;
-        _ = self.resolve_ref(&branch_path)
-            .inspect(|tag| candidates.push(tag.clone()));
+/// Whether `name` is a well-formed ref name by git's rules: no component may be empty,
+/// start with `.` or end with `.lock`, and `..`, `@{`, control characters, spaces and
+/// any of `~^:?*[\` are not allowed anywhere
+pub fn check_ref_format(name: &str) -> bool {
+    !name.is_empty()
+        && name != "@"
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.chars().any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+        && name.split('/').all(|component| !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock"))
+}
 
-        let remote_branch_path = "refs/remotes/".to_string() + name;
-        _ = self.resolve_ref(&remote_branch_path)
-            .inspect(|tag| candidates.push(tag.clone()));
+/// Whether `path`, slash separated and relative to the worktree root, is safe to write:
+/// no component may be empty, `.` or `..`, and none may name the repository directory
+pub fn verify_path(path: &str) -> bool {
+    !path.contains('\0')
+        && path.split('/').all(|component| {
+            !matches!(component, "" | "." | "..")
+                && !component.eq_ignore_ascii_case(".minit")
+                && !component.eq_ignore_ascii_case(".git")
+        })
+}
 
-        Ok(candidates) 
+/// Refuse the entries of `tree` if one of them is unsafe to write, or is both a file and
+/// the directory of another entry, as only a crafted tree can be
+fn verify_tree_paths(tree: &ObjectId, entries: &BTreeMap<String, Version>) -> Result<()> {
+    for path in entries.keys() {
+        if !verify_path(path) {
+            return Err(Error::MalformedObject(format!("tree {} has the unsafe path '{}'", tree, path)));
+        }
+        if let Some((end, _)) = path.match_indices('/').find(|(end, _)| entries.contains_key(&path[..*end])) {
+            return Err(Error::MalformedObject(format!("tree {} has '{}' as both a file and a directory", tree, &path[..end])));
+        }
     }
+    Ok(())
 }
 
+/// `refs/heads/<name>`, if `name` is allowed as a branch name
+pub fn branch_ref(name: &str) -> Result<String> {
+    let refname = format!("refs/heads/{}", name);
+    if name.starts_with('-') || name == "HEAD" || !check_ref_format(&refname) {
+        return Err(Error::Refused(format!("'{}' is not a valid branch name", name)));
+    }
+    Ok(refname)
+}
+
+/// The user's own config, read from `$MINIT_CONFIG_GLOBAL` or else `~/.minitconfig`
+fn global_config() -> Ini {
+    let mut conf = Ini::new();
+    let path = std::env::var("MINIT_CONFIG_GLOBAL").ok().map(PathBuf::from)
+        .or_else(|| std::env::var("HOME").ok().map(|home| Path::new(&home).join(".minitconfig")));
+    if let Some(path) = path.filter(|path| path.is_file()) {
+        _ = conf.load(path);
+    }
+    conf
+}
@@ -0,0 +1,267 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::cli::Format;
+use crate::error::{Error, Result};
+
+/// A revision as described in gitrevisions(7), parsed but not yet resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Revision {
+    /// An object id or a prefix of one, a ref name, or `HEAD` (which `@` stands for)
+    Name(String),
+    /// `<ref>@{<n>}` or `<ref>@{<date>}`, a past value of a ref from its reflog. An empty
+    /// name is the current branch.
+    Reflog(String, ReflogSelector),
+    /// `<branch>@{upstream}` or `<branch>@{u}`; an empty name is the current branch
+    Upstream(String),
+    /// `@{-<n>}`, the branch or commit checked out n checkouts ago
+    PreviousCheckout(usize),
+    /// `<rev>^<n>`, the n-th parent of a commit; the 0th is the commit itself
+    Parent(Box<Revision>, usize),
+    /// `<rev>~<n>`, the n-th ancestor following first parents
+    Ancestor(Box<Revision>, usize),
+    /// `<rev>^{<type>}`, `<rev>^{}` or `<rev>^{object}`
+    Peel(Box<Revision>, PeelTarget),
+    /// `<rev>:<path>`, the blob or tree at `path` in a tree-ish
+    Path(Box<Revision>, String),
+    /// `:<stage>:<path>`, or `:<path>` for stage 0, an entry of the index
+    Index(u8, String),
+    /// `:/<regex>`, the youngest commit reachable from any ref whose message matches
+    MessageSearch(String),
+}
+
+/// Which entry of a reflog `<ref>@{...}` picks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReflogSelector {
+    /// The value n updates ago
+    Nth(usize),
+    /// The value at a time, in seconds since the Unix epoch
+    Date(i64),
+}
+
+/// What `<rev>^{...}` peels to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeelTarget {
+    /// `^{object}`: anything, as long as it exists
+    Object,
+    /// `^{}`: whatever the chain of tags ends at
+    Tags,
+    /// `^{commit}`, `^{tree}` and so on
+    Type(Format),
+}
+
+impl Revision {
+    /// Parse `text`, reporting where it stops making sense
+    pub fn parse(text: &str) -> Result<Revision> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+        Revision::parse_at(text, now)
+    }
+
+    /// Like `parse`, with dates such as `yesterday` taken relative to `now`
+    pub fn parse_at(text: &str, now: i64) -> Result<Revision> {
+        Parser { text, now }.revision()
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    now: i64,
+}
+
+impl Parser<'_> {
+    fn error(&self, position: usize, reason: impl Into<String>) -> Error {
+        Error::BadRevision(self.text.to_string(), position, reason.into())
+    }
+
+    fn revision(&self) -> Result<Revision> {
+        let text = self.text;
+        if text.is_empty() {
+            return Err(self.error(0, "empty revision"));
+        }
+        if let Some(pattern) = text.strip_prefix(":/") {
+            if pattern.is_empty() {
+                return Err(self.error(2, "expected a pattern after ':/'"));
+            }
+            return Ok(Revision::MessageSearch(pattern.to_string()));
+        }
+        if let Some(rest) = text.strip_prefix(':') {
+            return match rest.as_bytes() {
+                [stage @ b'0'..=b'3', b':', ..] => Ok(Revision::Index(stage - b'0', rest[2..].to_string())),
+                [b'0'..=b'9', b':', ..] => Err(self.error(1, "expected a stage from 0 to 3")),
+                _ => Ok(Revision::Index(0, rest.to_string())),
+            };
+        }
+        match self.find_outside_braces(0, text.len(), b":") {
+            Some(colon) => Ok(Revision::Path(Box::new(self.expression(0, colon)?), text[colon + 1..].to_string())),
+            None => self.expression(0, text.len()),
+        }
+    }
+
+    /// The first of `targets` in `text[start..end]` that isn't inside `{...}`
+    fn find_outside_braces(&self, start: usize, end: usize, targets: &[u8]) -> Option<usize> {
+        let mut depth = 0;
+        for (i, c) in self.text.as_bytes()[start..end].iter().enumerate() {
+            match c {
+                b'{' => depth += 1,
+                b'}' => depth -= 1,
+                c if depth == 0 && targets.contains(c) => return Some(start + i),
+                _ => {},
+            }
+        }
+        None
+    }
+
+    /// A name followed by any number of `^<n>`, `~<n>` and `^{<type>}`
+    fn expression(&self, start: usize, end: usize) -> Result<Revision> {
+        let operators = self.find_outside_braces(start, end, b"^~").unwrap_or(end);
+        let mut revision = self.name(start, operators)?;
+        let bytes = self.text.as_bytes();
+        let mut i = operators;
+        while i < end {
+            let operator = bytes[i];
+            i += 1;
+            if operator == b'^' && i < end && bytes[i] == b'{' {
+                let close = self.text[i..end].find('}')
+                    .map(|offset| i + offset)
+                    .ok_or_else(|| self.error(i, "expected '}'"))?;
+                let target = match &self.text[i + 1..close] {
+                    "" => PeelTarget::Tags,
+                    "object" => PeelTarget::Object,
+                    kind => PeelTarget::Type(kind.parse::<Format>()
+                        .map_err(|_| self.error(i + 1, format!("unknown object type '{}'", kind)))?),
+                };
+                revision = Revision::Peel(Box::new(revision), target);
+                i = close + 1;
+                continue;
+            }
+            if operator != b'^' && operator != b'~' {
+                return Err(self.error(i - 1, format!("unexpected '{}'", self.text[i - 1..].chars().next().unwrap_or_default())));
+            }
+            let digits = bytes[i..end].iter().take_while(|c| c.is_ascii_digit()).count();
+            let count = match digits {
+                0 => 1,
+                _ => self.text[i..i + digits].parse::<usize>().map_err(|_| self.error(i, "number too large"))?,
+            };
+            i += digits;
+            revision = match operator {
+                b'^' => Revision::Parent(Box::new(revision), count),
+                _ => Revision::Ancestor(Box::new(revision), count),
+            };
+        }
+        Ok(revision)
+    }
+
+    /// A name, `@`, or a name with an `@{...}` suffix
+    fn name(&self, start: usize, end: usize) -> Result<Revision> {
+        let name = &self.text[start..end];
+        if name.is_empty() {
+            return Err(match self.text[end..].chars().next() {
+                Some(operator) => self.error(start, format!("expected a revision before '{}'", operator)),
+                None => self.error(start, "empty revision"),
+            });
+        }
+        let Some(at) = name.find("@{") else {
+            return Ok(Revision::Name(String::from(if name == "@" { "HEAD" } else { name })));
+        };
+        let open = start + at + 2;
+        let close = self.text[open..end].find('}')
+            .map(|offset| open + offset)
+            .ok_or_else(|| self.error(end, "expected '}'"))?;
+        if close + 1 != end {
+            return Err(self.error(close + 1, "unexpected text after '@{...}'"));
+        }
+        let prefix = &name[..at];
+        let selector = &self.text[open..close];
+        if selector.eq_ignore_ascii_case("u") || selector.eq_ignore_ascii_case("upstream") {
+            return Ok(Revision::Upstream(prefix.to_string()));
+        }
+        if let Some(count) = selector.strip_prefix('-') {
+            if !prefix.is_empty() {
+                return Err(self.error(start, "'@{-<n>}' can't follow a ref name"));
+            }
+            return match count.parse::<usize>() {
+                Ok(count) if count > 0 => Ok(Revision::PreviousCheckout(count)),
+                _ => Err(self.error(open + 1, "expected a positive number")),
+            };
+        }
+        if !selector.is_empty() && selector.bytes().all(|c| c.is_ascii_digit()) {
+            let count = selector.parse::<usize>().map_err(|_| self.error(open, "number too large"))?;
+            return Ok(Revision::Reflog(prefix.to_string(), ReflogSelector::Nth(count)));
+        }
+        match approxidate(selector, self.now) {
+            Some(time) => Ok(Revision::Reflog(prefix.to_string(), ReflogSelector::Date(time))),
+            None => Err(self.error(open, format!("unknown reflog selector '{}'", selector))),
+        }
+    }
+}
+
+/// The time `text` describes, in seconds since the Unix epoch: `now`, `yesterday`,
+/// relative times such as `2.weeks.ago` or `3 hours 10 minutes ago`, or a UTC date and
+/// time as `2024-05-01`, `2024-05-01 12:30` or `2024-05-01T12:30:00`
+pub fn approxidate(text: &str, now: i64) -> Option<i64> {
+    let text = text.trim();
+    match text {
+        "now" => return Some(now),
+        "yesterday" => return now.checked_sub(86400),
+        _ => {},
+    }
+    if let Some(time) = absolute_date(text) {
+        return Some(time);
+    }
+    let words = text.split(['.', ' ']).filter(|word| !word.is_empty()).collect::<Vec<&str>>();
+    let words = words.strip_suffix(&["ago"]).unwrap_or(&words);
+    if words.is_empty() || words.len() % 2 != 0 {
+        return None;
+    }
+    let mut seconds: i64 = 0;
+    for pair in words.chunks(2) {
+        let count = pair[0].parse::<i64>().ok()?;
+        let unit = match pair[1].strip_suffix('s').unwrap_or(pair[1]) {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 3600,
+            "day" => 86400,
+            "week" => 7 * 86400,
+            "month" => 30 * 86400,
+            "year" => 365 * 86400,
+            _ => return None,
+        };
+        seconds = seconds.checked_add(count.checked_mul(unit)?)?;
+    }
+    now.checked_sub(seconds)
+}
+
+/// `YYYY-MM-DD`, optionally followed by `HH:MM` or `HH:MM:SS`, in UTC
+fn absolute_date(text: &str) -> Option<i64> {
+    let (date, time) = match text.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let mut parts = date.split('-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let fields = time.split(':').map(|field| field.parse::<i64>().ok()).collect::<Option<Vec<i64>>>()?;
+        let (hours, minutes, secs) = match fields.as_slice() {
+            [hours, minutes] => (*hours, *minutes, 0),
+            [hours, minutes, secs] => (*hours, *minutes, *secs),
+            _ => return None,
+        };
+        if !(0..=23).contains(&hours) || !(0..=59).contains(&minutes) || !(0..=59).contains(&secs) {
+            return None;
+        }
+        seconds = hours * 3600 + minutes * 60 + secs;
+    }
+    // Days since the epoch of a proleptic Gregorian date, counting years from March. The
+    // year is unbounded, so only the arithmetic that depends on it is checked.
+    let year = if month <= 2 { year.checked_sub(1)? } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146097)?
+        .checked_add(day_of_era - 719468)?
+        .checked_mul(86400)?
+        .checked_add(seconds)
+}
@@ -0,0 +1,219 @@
+use clap::ValueEnum;
+use std::fmt::Write;
+use crate::hash::{HashAlgorithm, ObjectId};
+
+/// Which untracked files `Repository::status` lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum UntrackedFiles {
+    /// None at all
+    No,
+    /// Directories with nothing tracked in them as one `dir/` entry
+    #[default]
+    Normal,
+    /// Every file
+    All,
+}
+
+/// How a path differs from one of HEAD, the index and the worktree to the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+    /// A file became a symlink or a submodule, or the other way around
+    TypeChanged,
+}
+
+impl Change {
+    /// The letter git's short formats use
+    pub fn code(&self) -> char {
+        match self {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+            Change::TypeChanged => 'T',
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Change::Added => "new file",
+            Change::Modified => "modified",
+            Change::Deleted => "deleted",
+            Change::TypeChanged => "typechange",
+        }
+    }
+}
+
+/// A mode and object id, as recorded for a path in a tree or the index
+pub type Version = (u32, ObjectId);
+
+/// A path that differs somewhere between HEAD, the index and the worktree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusEntry {
+    pub path: String,
+    /// From HEAD to the index
+    pub staged: Option<Change>,
+    /// From the index to the worktree
+    pub unstaged: Option<Change>,
+    pub head: Option<Version>,
+    /// The stage 0 entry; conflicted paths have none
+    pub index: Option<Version>,
+    /// The mode of the file in the worktree, if there is one
+    pub worktree: Option<u32>,
+    /// The base, ours and theirs stages of a path with an unresolved conflict
+    pub conflict: Option<[Option<Version>; 3]>,
+}
+
+impl StatusEntry {
+    /// The two letters of the short formats, with `unchanged` for a side without changes.
+    /// Conflicts are spelled out by which stages are present, as `UU` for both modified.
+    pub fn code(&self, unchanged: char) -> String {
+        if let Some(stages) = &self.conflict {
+            return String::from(match stages.each_ref().map(Option::is_some) {
+                [true, false, false] => "DD",
+                [false, true, false] => "AU",
+                [true, false, true] => "UD",
+                [false, false, true] => "UA",
+                [true, true, false] => "DU",
+                [false, true, true] => "AA",
+                _ => "UU",
+            });
+        }
+        let letter = |change: Option<Change>| change.map_or(unchanged, |change| change.code());
+        format!("{}{}", letter(self.staged), letter(self.unstaged))
+    }
+
+    fn conflict_label(&self) -> &str {
+        match self.code(' ').as_str() {
+            "DD" => "both deleted",
+            "AU" => "added by us",
+            "UD" => "deleted by them",
+            "UA" => "added by them",
+            "DU" => "deleted by us",
+            "AA" => "both added",
+            _ => "both modified",
+        }
+    }
+}
+
+/// The state of the worktree as `Repository::status` sees it
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Status {
+    /// The branch HEAD points at without `refs/heads/`, or `None` when detached
+    pub branch: Option<String>,
+    /// The commit HEAD resolves to, `None` before the first commit
+    pub head: Option<ObjectId>,
+    /// Changed paths in index order
+    pub entries: Vec<StatusEntry>,
+    /// Untracked paths that aren't ignored, directories ending in `/`
+    pub untracked: Vec<String>,
+}
+
+impl Status {
+    /// Whether nothing is staged, changed or untracked
+    pub fn is_clean(&self) -> bool {
+        self.entries.is_empty() && self.untracked.is_empty()
+    }
+
+    /// The format meant for people, as printed by `status` without options
+    pub fn long_format(&self) -> String {
+        let mut out = String::new();
+        match (&self.branch, &self.head) {
+            (Some(branch), _) => _ = writeln!(out, "On branch {}", branch),
+            (None, Some(head)) => _ = writeln!(out, "HEAD detached at {}", &head.to_hex()[0..7]),
+            (None, None) => _ = writeln!(out, "Not currently on any branch."),
+        }
+        if self.head.is_none() {
+            out.push_str("\nNo commits yet\n");
+        }
+
+        let section = |out: &mut String, title: &str, lines: Vec<String>| {
+            if !lines.is_empty() {
+                _ = writeln!(out, "\n{}:", title);
+                lines.iter().for_each(|line| _ = writeln!(out, "\t{}", line));
+            }
+        };
+        let staged = self.entries.iter()
+            .filter_map(|entry| entry.staged.map(|change| format!("{:<12}{}", format!("{}:", change.label()), entry.path)))
+            .collect::<Vec<String>>();
+        let conflicted = self.entries.iter()
+            .filter(|entry| entry.conflict.is_some())
+            .map(|entry| format!("{:<17}{}", format!("{}:", entry.conflict_label()), entry.path))
+            .collect::<Vec<String>>();
+        let unstaged = self.entries.iter()
+            .filter(|entry| entry.conflict.is_none())
+            .filter_map(|entry| entry.unstaged.map(|change| format!("{:<12}{}", format!("{}:", change.label()), entry.path)))
+            .collect::<Vec<String>>();
+        let summary = match (staged.is_empty(), unstaged.is_empty() && conflicted.is_empty(), self.untracked.is_empty()) {
+            (false, _, _) => None,
+            (true, false, _) => Some("no changes added to commit"),
+            (true, true, false) => Some("nothing added to commit but untracked files present"),
+            (true, true, true) if self.head.is_none() => Some("nothing to commit (create/copy files and use \"minit add\" to track)"),
+            (true, true, true) => Some("nothing to commit, working tree clean"),
+        };
+        section(&mut out, "Changes to be committed", staged);
+        section(&mut out, "Unmerged paths", conflicted);
+        section(&mut out, "Changes not staged for commit", unstaged);
+        section(&mut out, "Untracked files", self.untracked.clone());
+        if let Some(summary) = summary {
+            _ = writeln!(out, "\n{}", summary);
+        }
+        out
+    }
+
+    /// `XY path` per entry and `?? path` per untracked path, as `--short` and
+    /// `--porcelain=v1` print, with a `## branch` header if asked for
+    pub fn short_format(&self, branch: bool) -> String {
+        let mut out = String::new();
+        if branch {
+            match (&self.branch, &self.head) {
+                (Some(name), Some(_)) => _ = writeln!(out, "## {}", name),
+                (Some(name), None) => _ = writeln!(out, "## No commits yet on {}", name),
+                (None, _) => _ = writeln!(out, "## HEAD (no branch)"),
+            }
+        }
+        for entry in &self.entries {
+            _ = writeln!(out, "{} {}", entry.code(' '), entry.path);
+        }
+        for path in &self.untracked {
+            _ = writeln!(out, "?? {}", path);
+        }
+        out
+    }
+
+    /// The `--porcelain=v2` format, which adds modes and object ids for every side:
+    /// `1 XY N... <mH> <mI> <mW> <hH> <hI> <path>` for changed paths,
+    /// `u XY N... <m1> <m2> <m3> <mW> <h1> <h2> <h3> <path>` for conflicts and
+    /// `? <path>` for untracked ones, after `# branch.*` headers if asked for
+    pub fn porcelain_v2(&self, branch: bool, algorithm: HashAlgorithm) -> String {
+        let null = ObjectId::null(algorithm);
+        let mut out = String::new();
+        if branch {
+            let oid = self.head.map_or(String::from("(initial)"), |head| head.to_string());
+            _ = writeln!(out, "# branch.oid {}", oid);
+            _ = writeln!(out, "# branch.head {}", self.branch.as_deref().unwrap_or("(detached)"));
+        }
+        let mode = |version: &Option<Version>| format!("{:06o}", version.map_or(0, |(mode, _)| mode));
+        let sha = |version: &Option<Version>| version.map_or(null, |(_, sha)| sha).to_string();
+        for entry in &self.entries {
+            let worktree = format!("{:06o}", entry.worktree.unwrap_or(0));
+            match &entry.conflict {
+                Some(stages) => _ = writeln!(
+                    out, "u {} N... {} {} {} {} {} {} {} {}",
+                    entry.code('.'), mode(&stages[0]), mode(&stages[1]), mode(&stages[2]), worktree,
+                    sha(&stages[0]), sha(&stages[1]), sha(&stages[2]), entry.path,
+                ),
+                None => _ = writeln!(
+                    out, "1 {} N... {} {} {} {} {} {}",
+                    entry.code('.'), mode(&entry.head), mode(&entry.index), worktree,
+                    sha(&entry.head), sha(&entry.index), entry.path,
+                ),
+            }
+        }
+        for path in &self.untracked {
+            _ = writeln!(out, "? {}", path);
+        }
+        out
+    }
+}
@@ -0,0 +1,159 @@
+use minit::repository::{AddOptions, Repository};
+use minit::hash::{HashAlgorithm, ObjectId};
+use minit::index::{IndexChange, MODE_EXECUTABLE, MODE_FILE, MODE_SYMLINK};
+use minit::pathspec::{Pathspec, wildmatch};
+use minit::object::Object;
+use minit::error::Error;
+use std::fs;
+
+mod common;
+use common::empty_repository;
+
+fn pathspec(repo: &Repository, cwd: &str, specs: &[&str]) -> Pathspec {
+    let specs = specs.iter().map(|spec| spec.to_string()).collect::<Vec<String>>();
+    Pathspec::new(repo.worktree(), &repo.worktree().join(cwd), &specs).unwrap()
+}
+
+fn blob(repo: &Repository, sha: &ObjectId) -> Vec<u8> {
+    match repo.read_object(sha).unwrap() {
+        Object::Blob(data) => data,
+        _ => panic!("{} is not a blob", sha),
+    }
+}
+
+fn staged(repo: &Repository) -> Vec<String> {
+    repo.read_index().unwrap().entries.into_iter().map(|entry| entry.path).collect()
+}
+
+#[test]
+fn test_add_paths() {
+    let repo = empty_repository("add_paths");
+    let worktree = repo.worktree().to_path_buf();
+    fs::create_dir_all(worktree.join("dir/sub")).unwrap();
+    fs::write(worktree.join("a.txt"), b"a\n").unwrap();
+    fs::write(worktree.join("dir/b.txt"), b"b\n").unwrap();
+    fs::write(worktree.join("dir/sub/c.txt"), b"c\n").unwrap();
+
+    // Nothing is staged without a pathspec or -A
+    assert!(repo.add(&Pathspec::default(), &AddOptions::default()).unwrap().is_empty());
+
+    let changes = repo.add(&pathspec(&repo, "dir", &["."]), &AddOptions::default()).unwrap();
+    assert_eq!(changes, vec![IndexChange::Add(String::from("dir/b.txt")), IndexChange::Add(String::from("dir/sub/c.txt"))]);
+    assert_eq!(staged(&repo), vec!["dir/b.txt", "dir/sub/c.txt"]);
+    let entry = repo.read_index().unwrap().get("dir/b.txt").unwrap().clone();
+    assert_eq!(entry.mode, MODE_FILE);
+    assert_eq!(entry.size, 2);
+    assert_eq!(entry.sha.to_string(), "61780798228d17af2d34fce4cfbdf35556832472");
+    assert_eq!(blob(&repo, &entry.sha), b"b\n");
+
+    // Unchanged files are left alone, wildcards cross directories
+    let changes = repo.add(&pathspec(&repo, "", &["*.txt"]), &AddOptions::default()).unwrap();
+    assert_eq!(changes, vec![IndexChange::Add(String::from("a.txt"))]);
+    assert_eq!(changes[0].to_string(), "add 'a.txt'");
+
+    match repo.add(&pathspec(&repo, "", &["missing"]), &AddOptions::default()) {
+        Err(Error::PathspecNoMatch(pattern)) => assert_eq!(pattern, "missing"),
+        other => panic!("expected a pathspec error, got {:?}", other),
+    }
+    assert!(Pathspec::new(repo.worktree(), repo.worktree(), &[String::from("../outside")]).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_add_modes() {
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    let repo = empty_repository("add_modes");
+    let worktree = repo.worktree().to_path_buf();
+    fs::write(worktree.join("run.sh"), b"#!/bin/sh\n").unwrap();
+    fs::set_permissions(worktree.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    symlink("run.sh", worktree.join("link")).unwrap();
+
+    let all = AddOptions { all: true, ..AddOptions::default() };
+    repo.add(&Pathspec::default(), &all).unwrap();
+    let index = repo.read_index().unwrap();
+    assert_eq!(index.get("run.sh").unwrap().mode, MODE_EXECUTABLE);
+    let link = index.get("link").unwrap();
+    assert_eq!(link.mode, MODE_SYMLINK);
+    assert_eq!(blob(&repo, &link.sha), b"run.sh");
+
+    // Losing the executable bit is a change even though the content is the same
+    fs::set_permissions(worktree.join("run.sh"), fs::Permissions::from_mode(0o644)).unwrap();
+    let changes = repo.add(&Pathspec::default(), &all).unwrap();
+    assert_eq!(changes, vec![IndexChange::Add(String::from("run.sh"))]);
+    assert_eq!(repo.read_index().unwrap().get("run.sh").unwrap().mode, MODE_FILE);
+}
+
+#[test]
+fn test_add_update_and_removals() {
+    let repo = empty_repository("add_update");
+    let worktree = repo.worktree().to_path_buf();
+    fs::write(worktree.join("kept"), b"one").unwrap();
+    fs::write(worktree.join("gone"), b"two").unwrap();
+    let all = AddOptions { all: true, ..AddOptions::default() };
+    repo.add(&Pathspec::default(), &all).unwrap();
+    assert_eq!(staged(&repo), vec!["gone", "kept"]);
+    assert!(!staged(&repo).iter().any(|path| path.starts_with(".minit")));
+
+    fs::remove_file(worktree.join("gone")).unwrap();
+    fs::write(worktree.join("kept"), b"changed").unwrap();
+    fs::write(worktree.join("untracked"), b"three").unwrap();
+
+    // A dry run reports without writing anything
+    let dry_run = AddOptions { update: true, dry_run: true, ..AddOptions::default() };
+    let changes = repo.add(&Pathspec::default(), &dry_run).unwrap();
+    assert_eq!(changes, vec![IndexChange::Remove(String::from("gone")), IndexChange::Add(String::from("kept"))]);
+    assert_eq!(changes[0].to_string(), "remove 'gone'");
+    assert_eq!(staged(&repo), vec!["gone", "kept"]);
+    let changed = HashAlgorithm::Sha1.digest(b"blob 7\x00changed");
+    assert!(repo.read_object(&changed).is_err());
+
+    // -u leaves untracked files out
+    let update = AddOptions { update: true, ..AddOptions::default() };
+    repo.add(&Pathspec::default(), &update).unwrap();
+    assert_eq!(staged(&repo), vec!["kept"]);
+    assert_eq!(repo.read_index().unwrap().get("kept").unwrap().sha, changed);
+
+    // A file may replace a staged directory
+    repo.add(&Pathspec::default(), &all).unwrap();
+    fs::create_dir(worktree.join("dir")).unwrap();
+    fs::write(worktree.join("dir/file"), b"").unwrap();
+    repo.add(&Pathspec::default(), &all).unwrap();
+    fs::remove_dir_all(worktree.join("dir")).unwrap();
+    fs::write(worktree.join("dir"), b"").unwrap();
+    repo.add(&pathspec(&repo, "", &["dir"]), &AddOptions::default()).unwrap();
+    assert_eq!(staged(&repo), vec!["dir", "kept", "untracked"]);
+}
+
+#[test]
+fn test_add_intent_to_add() {
+    let repo = empty_repository("add_intent");
+    fs::write(repo.worktree().join("later"), b"content").unwrap();
+    let options = AddOptions { intent_to_add: true, ..AddOptions::default() };
+    let changes = repo.add(&pathspec(&repo, "", &["later"]), &options).unwrap();
+    assert_eq!(changes, vec![IndexChange::Add(String::from("later"))]);
+    let entry = repo.read_index().unwrap().get("later").unwrap().clone();
+    assert!(entry.intent_to_add);
+    assert_eq!(entry.sha, HashAlgorithm::Sha1.digest(b"blob 0\x00"));
+    assert_eq!(repo.read_index().unwrap().version, 3);
+
+    // A plain add then stages the content for real
+    repo.add(&pathspec(&repo, "", &["later"]), &AddOptions::default()).unwrap();
+    let entry = repo.read_index().unwrap().get("later").unwrap().clone();
+    assert!(!entry.intent_to_add);
+    assert_eq!(blob(&repo, &entry.sha), b"content");
+    assert!(repo.fsck().unwrap().issues.is_empty());
+}
+
+#[test]
+fn test_wildmatch() {
+    assert!(wildmatch(b"*.txt", b"dir/a.txt", false));
+    assert!(!wildmatch(b"*.txt", b"dir/a.txt", true));
+    assert!(wildmatch(b"**/a.txt", b"a.txt", true));
+    assert!(wildmatch(b"dir/**/c", b"dir/x/y/c", true));
+    assert!(wildmatch(b"dir/**", b"dir/x/y", true));
+    assert!(wildmatch(b"[a-c]?[!x]", b"bzy", true));
+    assert!(!wildmatch(b"[a-c]?[!x]", b"bzx", true));
+    assert!(wildmatch(b"\\*", b"*", true));
+    assert!(!wildmatch(b"\\*", b"a", true));
+}
@@ -0,0 +1,122 @@
+use minit::repository::{AddOptions, CheckoutOptions, CommitOptions, Repository};
+use minit::pathspec::Pathspec;
+use minit::error::Error;
+use std::{fs, process::Command};
+
+mod common;
+use common::fresh_repository;
+
+fn commit_file(repo: &Repository, file: &str, message: &str) {
+    fs::write(repo.worktree().join(file), message).unwrap();
+    repo.add(&Pathspec::from_patterns(vec![file.to_string()]), &AddOptions::default()).unwrap();
+    repo.commit(Some(format!("{}\n", message).into_bytes()), &CommitOptions::default()).unwrap();
+}
+
+fn names(repo: &Repository) -> Vec<String> {
+    repo.branches().unwrap().into_iter().map(|(name, _)| name).collect()
+}
+
+fn refused<T: std::fmt::Debug>(result: minit::error::Result<T>) -> String {
+    match result {
+        Err(Error::Refused(reason)) => reason,
+        other => panic!("expected a refusal, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_branch_create_delete_rename() {
+    let repo = fresh_repository("branch");
+    commit_file(&repo, "a.txt", "First");
+    let first = repo.resolve_ref("HEAD").unwrap();
+
+    assert_eq!(repo.create_branch("topic", "HEAD", false).unwrap(), first);
+    assert!(refused(repo.create_branch("topic", "HEAD", false)).contains("already exists"));
+    assert!(refused(repo.create_branch("master", "HEAD", true)).contains("current branch"));
+    for bad in ["-x", "a..b", "a/.b", "x.lock", "HEAD", "sp ace", "a:b"] {
+        assert!(refused(repo.create_branch(bad, "HEAD", false)).contains("not a valid branch name"), "{}", bad);
+    }
+    assert_eq!(names(&repo), vec!["master", "topic"]);
+
+    // A branch with work that HEAD lacks isn't merged until HEAD has it
+    repo.checkout("topic", &CheckoutOptions::default()).unwrap();
+    commit_file(&repo, "b.txt", "Second");
+    let second = repo.resolve_ref("HEAD").unwrap();
+    assert!(refused(repo.delete_branch("topic", false)).contains("checked out"));
+    repo.checkout("master", &CheckoutOptions::default()).unwrap();
+    assert!(refused(repo.delete_branch("topic", false)).contains("not fully merged"));
+    assert!(repo.is_ancestor(&first, &second).unwrap());
+    assert!(!repo.is_ancestor(&second, &first).unwrap());
+
+    // Renaming moves the config and the reflog, and HEAD with the current branch
+    repo.set_upstream("topic", "master").unwrap();
+    fs::create_dir_all(repo.repo_path(vec!["logs", "refs", "heads"])).unwrap();
+    fs::write(repo.repo_path(vec!["logs", "refs", "heads", "topic"]), b"log\n").unwrap();
+    repo.rename_branch("topic", "feature/x", false).unwrap();
+    assert_eq!(names(&repo), vec!["feature/x", "master"]);
+    assert_eq!(repo.resolve_ref("refs/heads/feature/x").unwrap(), second);
+    let log = fs::read_to_string(repo.repo_path(vec!["logs", "refs", "heads", "feature", "x"])).unwrap();
+    assert!(log.starts_with("log\n"), "{}", log);
+    assert!(log.ends_with("\tBranch: renamed refs/heads/topic to refs/heads/feature/x\n"), "{}", log);
+    assert!(!repo.repo_path(vec!["logs", "refs", "heads", "topic"]).exists());
+    assert_eq!(repo.upstream("feature/x").as_deref(), Some("refs/heads/master"));
+    assert_eq!(repo.upstream("topic"), None);
+    assert!(refused(repo.rename_branch("feature/x", "master", false)).contains("already exists"));
+    repo.rename_branch("master", "main", false).unwrap();
+    assert_eq!(repo.head_ref().unwrap().as_deref(), Some("refs/heads/main"));
+
+    // A rename that can't take its lock leaves the branch and its log where they were
+    let lock = repo.repo_path(vec!["refs", "heads", "locked.lock"]);
+    fs::write(&lock, b"").unwrap();
+    assert!(refused(repo.rename_branch("feature/x", "locked", false)).contains("cannot lock"));
+    fs::remove_file(&lock).unwrap();
+    assert_eq!(repo.resolve_ref("refs/heads/feature/x").unwrap(), second);
+    assert!(repo.repo_path(vec!["logs", "refs", "heads", "feature", "x"]).is_file());
+    assert!(!repo.repo_path(vec!["logs", "refs", "heads", "locked"]).exists());
+
+    // A branch can move under its own name and back
+    repo.rename_branch("feature/x", "feature", false).unwrap();
+    repo.rename_branch("feature", "feature/x", false).unwrap();
+    assert_eq!(names(&repo), vec!["feature/x", "main"]);
+    let log = fs::read_to_string(repo.repo_path(vec!["logs", "refs", "heads", "feature", "x"])).unwrap();
+    assert!(log.starts_with("log\n") && log.lines().count() == 4, "{}", log);
+
+    // Merged into its upstream is enough, otherwise it must be forced
+    repo.create_branch("done", "main", false).unwrap();
+    assert_eq!(repo.delete_branch("done", false).unwrap(), first);
+    assert_eq!(repo.delete_branch("feature/x", true).unwrap(), second);
+    assert_eq!(names(&repo), vec!["main"]);
+    assert!(!repo.repo_path(vec!["refs", "heads", "feature"]).exists());
+    assert!(repo.repo_path(vec!["refs", "heads"]).is_dir());
+    assert_eq!(repo.config("branch \"feature/x\"", "merge"), None);
+    assert!(refused(repo.delete_branch("gone", false)).contains("not found"));
+}
+
+#[test]
+fn test_upstream_and_default_branch() {
+    let repo = fresh_repository("branch_upstream");
+    commit_file(&repo, "a.txt", "First");
+    let head = repo.resolve_ref("HEAD").unwrap();
+    repo.update_ref("refs/remotes/origin/main", &head, None, "fetch").unwrap();
+    repo.set_upstream("master", "origin/main").unwrap();
+    assert_eq!(repo.config("branch \"master\"", "remote").as_deref(), Some("origin"));
+    assert_eq!(repo.config("branch \"master\"", "merge").as_deref(), Some("refs/heads/main"));
+    assert_eq!(repo.upstream("master").as_deref(), Some("refs/remotes/origin/main"));
+    // The setting survives reopening the repository
+    let reopened = Repository::find(repo.worktree(), true).unwrap().unwrap();
+    assert_eq!(reopened.upstream("master").as_deref(), Some("refs/remotes/origin/main"));
+    assert!(refused(repo.set_upstream("master", "nowhere")).contains("does not exist"));
+
+    // The first branch comes from init.defaultBranch in the user's config
+    let temp = common::temp_dir("branch_default");
+    let dir = temp.path();
+    let global = dir.join("global-config");
+    fs::write(&global, b"[init]\n\tdefaultBranch = trunk\n").unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_minit"))
+        .env("MINIT_CONFIG_GLOBAL", &global)
+        .arg("init")
+        .arg(dir.join("repo"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(dir.join("repo/.minit/HEAD")).unwrap(), "ref: refs/heads/trunk\n");
+}
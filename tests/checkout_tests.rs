@@ -0,0 +1,156 @@
+use minit::repository::{AddOptions, CheckoutOptions, CommitOptions, Repository};
+use minit::object::{Leaf, Object};
+use minit::pathspec::Pathspec;
+use minit::status::UntrackedFiles;
+use minit::error::Error;
+use std::{fs, path::Path};
+
+mod common;
+use common::fresh_repository;
+
+fn commit_all(repo: &Repository, message: &str) {
+    repo.add(&Pathspec::default(), &AddOptions { all: true, ..AddOptions::default() }).unwrap();
+    repo.commit(Some(format!("{}\n", message).into_bytes()), &CommitOptions::default()).unwrap();
+}
+
+fn short(repo: &Repository) -> String {
+    repo.status(UntrackedFiles::Normal).unwrap().short_format(false)
+}
+
+fn refused(result: minit::error::Result<Option<String>>) -> String {
+    match result {
+        Err(Error::Refused(reason)) => reason,
+        other => panic!("expected the checkout to be refused, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_checkout_switches_in_place() {
+    let repo = fresh_repository("checkout");
+    let worktree = repo.worktree().to_path_buf();
+    fs::create_dir_all(worktree.join("dir")).unwrap();
+    fs::write(worktree.join("a.txt"), b"a\n").unwrap();
+    fs::write(worktree.join("dir/b.txt"), b"b\n").unwrap();
+    fs::write(worktree.join("kept.txt"), b"kept\n").unwrap();
+    commit_all(&repo, "First");
+
+    let new_branch = CheckoutOptions { new_branch: Some(String::from("feature")), ..CheckoutOptions::default() };
+    assert_eq!(repo.checkout("HEAD", &new_branch).unwrap().as_deref(), Some("feature"));
+    assert_eq!(repo.head_ref().unwrap().as_deref(), Some("refs/heads/feature"));
+    assert!(refused(repo.checkout("master", &new_branch)).contains("already exists"));
+
+    // A directory turns into a file and a file is added
+    fs::write(worktree.join("a.txt"), b"a on feature\n").unwrap();
+    fs::remove_dir_all(worktree.join("dir")).unwrap();
+    fs::write(worktree.join("dir"), b"now a file\n").unwrap();
+    fs::write(worktree.join("new.txt"), b"new\n").unwrap();
+    commit_all(&repo, "Second");
+    let feature = repo.resolve_ref("HEAD").unwrap();
+
+    // Changes to paths that are the same on both sides carry over
+    fs::write(worktree.join("kept.txt"), b"edited\n").unwrap();
+    assert_eq!(repo.checkout("master", &CheckoutOptions::default()).unwrap().as_deref(), Some("master"));
+    assert_eq!(fs::read(worktree.join("a.txt")).unwrap(), b"a\n");
+    assert_eq!(fs::read(worktree.join("dir/b.txt")).unwrap(), b"b\n");
+    assert!(!worktree.join("new.txt").exists());
+    assert_eq!(short(&repo), " M kept.txt\n");
+
+    // Detached at a commit, and back to the branch by name
+    assert_eq!(repo.checkout(&feature.to_hex(), &CheckoutOptions::default()).unwrap(), None);
+    assert_eq!(fs::read_to_string(repo.repo_path(vec!["HEAD"])).unwrap(), format!("{}\n", feature));
+    assert_eq!(fs::read(worktree.join("dir")).unwrap(), b"now a file\n");
+    let detach = CheckoutOptions { detach: true, ..CheckoutOptions::default() };
+    assert_eq!(repo.checkout("feature", &detach).unwrap(), None);
+    assert_eq!(repo.checkout("feature", &CheckoutOptions::default()).unwrap().as_deref(), Some("feature"));
+    assert_eq!(short(&repo), " M kept.txt\n");
+}
+
+#[test]
+fn test_checkout_refuses_to_lose_changes() {
+    let repo = fresh_repository("checkout_refuse");
+    let worktree = repo.worktree().to_path_buf();
+    fs::write(worktree.join("a.txt"), b"a\n").unwrap();
+    fs::write(worktree.join(".gitignore"), b"*.log\n").unwrap();
+    commit_all(&repo, "First");
+    let new_branch = CheckoutOptions { new_branch: Some(String::from("other")), ..CheckoutOptions::default() };
+    repo.checkout("HEAD", &new_branch).unwrap();
+    fs::write(worktree.join("a.txt"), b"a on other\n").unwrap();
+    fs::write(worktree.join("b.txt"), b"b\n").unwrap();
+    fs::write(worktree.join("c.log"), b"c\n").unwrap();
+    repo.add(&Pathspec::default(), &AddOptions { all: true, force: true, ..AddOptions::default() }).unwrap();
+    repo.commit(Some(b"Second\n".to_vec()), &CommitOptions::default()).unwrap();
+    repo.checkout("master", &CheckoutOptions::default()).unwrap();
+    assert!(!worktree.join("b.txt").exists());
+
+    // Unstaged and staged changes, and untracked files in the way
+    fs::write(worktree.join("a.txt"), b"local\n").unwrap();
+    fs::write(worktree.join("b.txt"), b"untracked\n").unwrap();
+    fs::write(worktree.join("c.log"), b"ignored\n").unwrap();
+    let reason = refused(repo.checkout("other", &CheckoutOptions::default()));
+    assert!(reason.contains("local changes to the following files would be overwritten by checkout:\n\ta.txt\n"), "{}", reason);
+    assert!(reason.contains("untracked working tree files would be overwritten by checkout:\n\tb.txt\n"), "{}", reason);
+    assert!(!reason.contains("c.log"), "{}", reason);
+    repo.add(&Pathspec::from_patterns(vec![String::from("a.txt")]), &AddOptions::default()).unwrap();
+    assert!(refused(repo.checkout("other", &CheckoutOptions::default())).contains("\ta.txt\n"));
+    // Nothing was touched
+    assert_eq!(repo.head_ref().unwrap().as_deref(), Some("refs/heads/master"));
+    assert_eq!(fs::read(worktree.join("b.txt")).unwrap(), b"untracked\n");
+
+    // Staging exactly what the target has is not a conflict
+    fs::write(worktree.join("a.txt"), b"a on other\n").unwrap();
+    fs::remove_file(worktree.join("b.txt")).unwrap();
+    repo.add(&Pathspec::from_patterns(vec![String::from("a.txt")]), &AddOptions::default()).unwrap();
+    repo.checkout("other", &CheckoutOptions::default()).unwrap();
+    assert_eq!(short(&repo), "");
+    assert_eq!(fs::read(worktree.join("c.log")).unwrap(), b"c\n");
+
+    // Forcing resets every tracked path
+    fs::write(worktree.join("a.txt"), b"thrown away\n").unwrap();
+    repo.rm(&Pathspec::from_patterns(vec![String::from("b.txt")]), &Default::default()).unwrap();
+    let force = CheckoutOptions { force: true, ..CheckoutOptions::default() };
+    repo.checkout("master", &force).unwrap();
+    assert_eq!(fs::read(worktree.join("a.txt")).unwrap(), b"a\n");
+    assert!(!worktree.join("b.txt").exists());
+    assert_eq!(short(&repo), "");
+}
+
+#[test]
+fn test_checkout_tree_into_directory() {
+    let repo = fresh_repository("checkout_tree");
+    let blob = repo.write_object(Object::Blob(b"#!/bin/sh\n".to_vec())).unwrap();
+    let target = repo.write_object(Object::Blob(b"run.sh".to_vec())).unwrap();
+    let sub = repo.write_object(Object::Tree(vec![Leaf::new(String::from("100644"), String::from("file"), blob)])).unwrap();
+    let tree = repo.write_object(Object::Tree(vec![
+        Leaf::new(String::from("120000"), String::from("link"), target),
+        Leaf::new(String::from("160000"), String::from("module"), blob),
+        Leaf::new(String::from("100755"), String::from("run.sh"), blob),
+        Leaf::new(String::from("40000"), String::from("sub"), sub),
+    ])).unwrap();
+
+    let temp = common::temp_dir("checkout_tree_out");
+    let out = temp.path().join("out");
+    repo.checkout_tree(&tree, &out).unwrap();
+    assert_eq!(fs::read(out.join("sub/file")).unwrap(), b"#!/bin/sh\n");
+    assert_eq!(fs::read_link(out.join("link")).unwrap(), Path::new("run.sh"));
+    assert!(out.join("module").is_dir() && out.join("module").read_dir().unwrap().next().is_none());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_ne!(fs::metadata(out.join("run.sh")).unwrap().permissions().mode() & 0o111, 0);
+        assert_eq!(fs::metadata(out.join("sub/file")).unwrap().permissions().mode() & 0o111, 0);
+    }
+    // Only into an empty directory
+    assert!(matches!(repo.checkout_tree(&tree, &out), Err(Error::Refused(_))));
+
+    // Crafted trees are refused before anything is written
+    for name in ["..", ".minit", ".GIT", "."] {
+        let evil = repo.write_object(Object::Tree(vec![
+            Leaf::new(String::from("100644"), String::from("a"), blob),
+            Leaf::new(String::from("40000"), String::from(name), sub),
+        ])).unwrap();
+        let temp = common::temp_dir("checkout_tree_evil");
+        let dest = temp.path().join("out");
+        assert!(matches!(repo.checkout_tree(&evil, &dest), Err(Error::MalformedObject(_))), "{}", name);
+        assert!(!dest.join("a").exists());
+    }
+}
@@ -0,0 +1,108 @@
+use minit::repository::{AddOptions, CommitOptions, Repository};
+use minit::hash::ObjectId;
+use minit::object::{Object, commit::{Commit, cleanup_message}};
+use minit::pathspec::Pathspec;
+use minit::error::Error;
+use std::fs;
+
+mod common;
+use common::fresh_repository;
+
+fn add_all(repo: &Repository) {
+    repo.add(&Pathspec::default(), &AddOptions { all: true, ..AddOptions::default() }).unwrap();
+}
+
+fn read_commit(repo: &Repository, sha: &ObjectId) -> Commit {
+    match repo.read_object(sha).unwrap() {
+        Object::Commit(commit) => commit,
+        _ => panic!("{} is not a commit", sha),
+    }
+}
+
+#[test]
+fn test_commit() {
+    let repo = fresh_repository("commit");
+    let worktree = repo.worktree().to_path_buf();
+    fs::create_dir_all(worktree.join("dir/sub")).unwrap();
+    fs::write(worktree.join("a.txt"), b"a\n").unwrap();
+    fs::write(worktree.join("a-b"), b"").unwrap();
+    fs::write(worktree.join("dir/b.txt"), b"b\n").unwrap();
+    fs::write(worktree.join("dir/sub/c.txt"), b"c\n").unwrap();
+    add_all(&repo);
+
+    let first = repo.commit(Some(b"First\n".to_vec()), &CommitOptions::default()).unwrap();
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), first);
+    let commit = read_commit(&repo, &first);
+    assert!(commit.parents.is_empty());
+    assert_eq!(commit.author.name, b"Minit");
+    assert_eq!(commit.committer.email, b"minit@example.com");
+    assert!(commit.author.time > 1700000000);
+    assert_eq!(commit.message, Some(b"First\n".to_vec()));
+    // The same tree git writes for this worktree
+    assert_eq!(commit.tree.to_string(), "834cce19ebadac32b36f8c5350e36464fb2792c4");
+    let files = repo.flatten_tree(&commit.tree).unwrap().into_keys().collect::<Vec<String>>();
+    assert_eq!(files, vec!["a-b", "a.txt", "dir/b.txt", "dir/sub/c.txt"]);
+
+    // Nothing staged means nothing to commit, unless asked
+    match repo.commit(Some(b"Again\n".to_vec()), &CommitOptions::default()) {
+        Err(Error::Refused(reason)) => assert_eq!(reason, "nothing to commit"),
+        other => panic!("expected a refusal, got {:?}", other),
+    }
+    let empty = repo.commit(Some(b"Empty\n".to_vec()), &CommitOptions { allow_empty: true, ..CommitOptions::default() }).unwrap();
+    assert_eq!(read_commit(&repo, &empty).parents, vec![first]);
+    assert_eq!(read_commit(&repo, &empty).tree, commit.tree);
+    assert!(repo.commit(Some(Vec::new()), &CommitOptions { allow_empty: true, ..CommitOptions::default() }).is_err());
+
+    // -a picks up edits and deletions but not new files
+    fs::write(worktree.join("a.txt"), b"edited\n").unwrap();
+    fs::remove_file(worktree.join("dir/b.txt")).unwrap();
+    fs::write(worktree.join("new.txt"), b"").unwrap();
+    let second = repo.commit(Some(b"Second\n".to_vec()), &CommitOptions { all: true, ..CommitOptions::default() }).unwrap();
+    let tree = read_commit(&repo, &second).tree;
+    let files = repo.flatten_tree(&tree).unwrap().into_keys().collect::<Vec<String>>();
+    assert_eq!(files, vec!["a-b", "a.txt", "dir/sub/c.txt"]);
+    assert_eq!(repo.head_tree().unwrap(), Some(tree));
+
+    // Amending keeps the parents, the author and, without a new one, the message
+    let amended = repo.commit(None, &CommitOptions { amend: true, allow_empty: true, ..CommitOptions::default() }).unwrap();
+    let commit = read_commit(&repo, &amended);
+    assert_eq!(commit.parents, vec![empty]);
+    assert_eq!(commit.author, read_commit(&repo, &second).author);
+    assert_eq!(commit.message, Some(b"Second\n".to_vec()));
+    assert_eq!(repo.resolve_ref("HEAD").unwrap(), amended);
+
+    // A branch that moved underneath is not overwritten
+    fs::write(repo.repo_path(vec!["refs", "heads", "master"]), format!("{}\n", first)).unwrap();
+    assert!(repo.update_ref("refs/heads/master", &amended, Some(&second), "commit").is_err());
+    assert_eq!(repo.resolve_ref("HEAD").unwrap(), first);
+    assert!(!repo.repo_path(vec!["refs", "heads", "master.lock"]).exists());
+}
+
+#[test]
+fn test_commit_detached_and_conflicted() {
+    let repo = fresh_repository("commit_detached");
+    fs::write(repo.worktree().join("a.txt"), b"a\n").unwrap();
+    add_all(&repo);
+    let first = repo.commit(Some(b"First\n".to_vec()), &CommitOptions::default()).unwrap();
+
+    fs::write(repo.repo_path(vec!["HEAD"]), format!("{}\n", first)).unwrap();
+    assert_eq!(repo.head_ref().unwrap(), None);
+    let detached = repo.commit(Some(b"Detached\n".to_vec()), &CommitOptions { allow_empty: true, ..CommitOptions::default() }).unwrap();
+    assert_eq!(repo.resolve_ref("HEAD").unwrap(), detached);
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), first);
+
+    let mut index = repo.read_index().unwrap();
+    let mut conflict = index.get("a.txt").unwrap().clone();
+    conflict.stage = 2;
+    index.add(conflict);
+    assert!(repo.write_tree(&index).is_err());
+}
+
+#[test]
+fn test_cleanup_message() {
+    assert_eq!(cleanup_message(b"\n\n  Subject  \n\n\n\nBody\t\n\n", false), b"  Subject\n\nBody\n");
+    assert_eq!(cleanup_message(b"Subject\n# comment\n#\n", true), b"Subject\n");
+    assert_eq!(cleanup_message(b"# only a comment\n", true), b"");
+    assert_eq!(cleanup_message(b"# kept\n", false), b"# kept\n");
+    assert_eq!(cleanup_message(b"  \n\t\n", false), b"");
+}
@@ -0,0 +1,49 @@
+//! Helpers shared by the integration tests. Each test file only uses some of them.
+#![allow(dead_code)]
+
+use minit::hash::HashAlgorithm;
+use minit::repository::Repository;
+use std::{fs, io::Write, ops::Deref, path::Path};
+use tempfile::TempDir;
+
+/// A repository in a temporary directory, deleted along with everything in it when dropped
+pub struct TestRepository {
+    repo: Repository,
+    dir: TempDir,
+}
+
+impl TestRepository {
+    /// The directory the repository was created in
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Deref for TestRepository {
+    type Target = Repository;
+
+    fn deref(&self) -> &Repository {
+        &self.repo
+    }
+}
+
+/// A temporary directory named after the test that uses it
+pub fn temp_dir(name: &str) -> TempDir {
+    tempfile::Builder::new().prefix(&format!("minit-{}-", name)).tempdir().unwrap()
+}
+
+/// An empty SHA-1 repository
+pub fn empty_repository(name: &str) -> TestRepository {
+    let dir = temp_dir(name);
+    let repo = Repository::create(dir.path(), Some(HashAlgorithm::Sha1)).unwrap();
+    TestRepository { repo, dir }
+}
+
+/// An empty SHA-1 repository with an identity in its config, reopened so the config is read
+pub fn fresh_repository(name: &str) -> TestRepository {
+    let TestRepository { repo, dir } = empty_repository(name);
+    let mut config = fs::OpenOptions::new().append(true).open(repo.repo_path(vec!["config"])).unwrap();
+    config.write_all(b"[user]\nname = Minit\nemail = minit@example.com\n").unwrap();
+    let repo = Repository::find(dir.path(), true).unwrap().unwrap();
+    TestRepository { repo, dir }
+}
@@ -0,0 +1,100 @@
+use minit::object::delta::{self, DeltaObject, DeltaOptions};
+use minit::cli::Format;
+
+fn sample(lines: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..lines).map(|i| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        format!("{:05} {:016x}\n", i, state)
+    }).collect::<String>().into_bytes()
+}
+
+#[test]
+fn test_apply_delta() {
+    let base = b"the quick brown fox";
+    // copy "the quick ", insert "red", copy " fox", insert " jumps"
+    let delta = [
+        19, 23,
+        0x90, 10,
+        3, b'r', b'e', b'd',
+        0x91, 15, 4,
+        6, b' ', b'j', b'u', b'm', b'p', b's',
+    ];
+    assert_eq!(delta::apply(base, &delta).unwrap(), b"the quick red fox jumps");
+    assert!(delta::apply(base, &delta[..5]).is_err());
+    assert!(delta::apply(b"short", &delta).is_err());
+
+    // A size varint that never ends, and a huge target size with no instructions behind it
+    assert!(delta::apply(base, &[0xff; 16]).is_err());
+    let huge = [19, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+    assert!(delta::apply(base, &huge).is_err());
+}
+
+#[test]
+fn test_encode_round_trip() {
+    let base = sample(5000, 1);
+    let mut prefixed = b"a brand new first line\n".to_vec();
+    prefixed.extend(&base);
+    let mut edited = base.clone();
+    edited.splice(40000..40010, b"EDITED".iter().cloned());
+    let cases: Vec<(&[u8], Vec<u8>)> = vec![
+        (&base, base.clone()),
+        (&base, prefixed),
+        (&base, edited),
+        (&base, base[1000..60000].to_vec()),
+        (&base, sample(100, 2)),
+        (&base, Vec::new()),
+        (b"", base.clone()),
+    ];
+    for (source, target) in cases {
+        let encoded = delta::encode(source, &target);
+        assert_eq!(delta::apply(source, &encoded).unwrap(), target);
+    }
+
+    // Near-identical inputs collapse to a handful of copies
+    let mut edited = base.clone();
+    edited[3000] = b'#';
+    assert!(delta::encode(&base, &edited).len() < 64);
+}
+
+#[test]
+fn test_plan_respects_depth() {
+    let versions = (0..4)
+        .map(|i| {
+            let mut data = sample(400, 7);
+            data.truncate(data.len() - i * 300);
+            data
+        })
+        .collect::<Vec<Vec<u8>>>();
+    let objects = versions.iter()
+        .map(|data| DeltaObject { format: Format::Blob, data, path: Some("src/lib.rs") })
+        .chain(std::iter::once(DeltaObject { format: Format::Tree, data: &versions[0], path: None }))
+        .collect::<Vec<DeltaObject>>();
+
+    let plan = delta::plan(&objects, &DeltaOptions { window: 1, max_depth: 2 });
+    // Bases come before the objects that use them
+    for (pos, &i) in plan.order.iter().enumerate() {
+        if let Some((base, _)) = &plan.deltas[i] {
+            assert!(plan.order[..pos].contains(base));
+            assert_eq!(objects[*base].format, objects[i].format);
+        }
+    }
+    let depth = |mut i: usize| {
+        let mut depth = 0;
+        while let Some((base, _)) = &plan.deltas[i] {
+            i = *base;
+            depth += 1;
+        }
+        depth
+    };
+    assert_eq!((0..objects.len()).map(depth).max(), Some(2));
+    assert!(plan.deltas[4].is_none());
+    for (i, object) in objects.iter().enumerate() {
+        if let Some((base, encoded)) = &plan.deltas[i] {
+            assert_eq!(delta::apply(objects[*base].data, encoded).unwrap(), object.data);
+        }
+    }
+
+    let plan = delta::plan(&objects, &DeltaOptions { window: 0, max_depth: 50 });
+    assert!(plan.deltas.iter().all(|d| d.is_none()));
+}
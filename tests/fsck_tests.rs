@@ -0,0 +1,118 @@
+use minit::repository::Repository;
+use minit::hash::{HashAlgorithm, ObjectId};
+use minit::object::{Leaf, Object, commit::{Commit, Identity}, delta::DeltaOptions};
+use minit::fsck::FsckIssue;
+use minit::cli::Format;
+use flate2::{Compression, write::ZlibEncoder};
+use std::{fs, io::Write};
+
+mod common;
+use common::empty_repository;
+
+fn commit(repo: &Repository, tree: ObjectId, parents: Vec<ObjectId>) -> ObjectId {
+    let identity = Identity::new("Minit", "minit@example.com", 1700000000, 0);
+    repo.write_object(Object::Commit(Commit {
+        tree,
+        parents,
+        author: identity.clone(),
+        committer: identity,
+        encoding: None,
+        extra_headers: Vec::new(),
+        gpgsig: None,
+        message: Some(b"A commit\n".to_vec()),
+    })).unwrap()
+}
+
+fn set_master(repo: &Repository, sha: &ObjectId) {
+    fs::write(repo.repo_path(vec!["refs", "heads", "master"]), format!("{}\n", sha)).unwrap();
+}
+
+#[test]
+fn test_fsck_clean_and_dangling() {
+    let repo = empty_repository("fsck_clean");
+    let blob = repo.write_object(Object::Blob(b"hello".to_vec())).unwrap();
+    let tree = repo.write_object(Object::Tree(vec![Leaf::new(String::from("100644"), String::from("a.txt"), blob)])).unwrap();
+    let head = commit(&repo, tree, Vec::new());
+    set_master(&repo, &head);
+
+    let report = repo.fsck().unwrap();
+    assert_eq!(report.checked, 3);
+    assert!(report.issues.is_empty(), "{}", report);
+
+    // A commit nobody points at is dangling; the tree only it points at is unreachable
+    let stray_blob = repo.write_object(Object::Blob(b"stray".to_vec())).unwrap();
+    let stray_tree = repo.write_object(Object::Tree(vec![Leaf::new(String::from("100644"), String::from("b.txt"), blob)])).unwrap();
+    let stray_commit = commit(&repo, stray_tree, vec![head]);
+    let report = repo.fsck().unwrap();
+    assert!(report.is_ok());
+    let mut expected = vec![
+        FsckIssue::Dangling(Format::Blob, stray_blob),
+        FsckIssue::Dangling(Format::Commit, stray_commit),
+        FsckIssue::Unreachable(Format::Tree, stray_tree),
+    ];
+    expected.sort_by_key(|issue| issue.to_string().split(' ').nth(2).unwrap().to_string());
+    assert_eq!(report.issues, expected);
+    assert!(report.to_string().contains(&format!("dangling blob {}\n", stray_blob)));
+
+    // Packed copies are checked too
+    repo.repack(&DeltaOptions::default()).unwrap().unwrap();
+    let report = repo.fsck().unwrap();
+    assert_eq!(report.checked, 6);
+    assert!(report.is_ok(), "{}", report);
+}
+
+#[test]
+fn test_fsck_reports_damage() {
+    let repo = empty_repository("fsck_damage");
+    let blob = repo.write_object(Object::Blob(b"hello".to_vec())).unwrap();
+    let missing_blob = ObjectId::from_hex("1111111111111111111111111111111111111111", HashAlgorithm::Sha1).unwrap();
+    let missing_parent = ObjectId::from_hex("2222222222222222222222222222222222222222", HashAlgorithm::Sha1).unwrap();
+
+    // Written by hand because `Object::serialize` would sort the entries
+    let mut raw = Vec::new();
+    for (mode, path, sha) in [("100600", "b.txt", blob), ("100644", "a.txt", missing_blob), ("40000", "c", blob)] {
+        raw.extend(format!("{} {}\x00", mode, path).as_bytes());
+        raw.extend(sha.as_bytes());
+    }
+    let tree = repo.write_stream(Format::Tree, raw.len() as u64, &raw[..]).unwrap();
+    let head = commit(&repo, tree, vec![missing_parent]);
+    set_master(&repo, &head);
+
+    let garbage = b"tree nothing\n\n";
+    let broken_commit = repo.write_stream(Format::Commit, garbage.len() as u64, &garbage[..]).unwrap();
+    fs::write(repo.repo_path(vec!["refs", "heads", "broken"]), format!("{}\n", broken_commit)).unwrap();
+
+    // Swap the content of the blob for something else
+    let path = repo.repo_path(vec!["objects", &blob.to_hex()[0..2], &blob.to_hex()[2..]]);
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"blob 5\x00jello").unwrap();
+    fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+    let report = repo.fsck().unwrap();
+    assert!(!report.is_ok());
+    let lines = report.to_string();
+    let kinds = report.issues.iter()
+        .map(|issue| issue.to_string().split(' ').take(2).collect::<Vec<&str>>().join(" "))
+        .collect::<Vec<String>>();
+    for expected in [
+        "hash-mismatch blob",
+        "malformed commit",
+        "malformed tree",
+        "missing blob",
+        "missing commit",
+        "wrong-type blob",
+    ] {
+        assert!(kinds.iter().any(|kind| kind == expected), "no {} in\n{}", expected, lines);
+    }
+    assert!(lines.contains("has invalid mode 100600"), "{}", lines);
+    assert!(lines.contains("\"a.txt\" is out of order"), "{}", lines);
+    assert!(report.issues.contains(&FsckIssue::Missing(Some(Format::Blob), missing_blob)));
+    assert!(report.issues.contains(&FsckIssue::Missing(Some(Format::Commit), missing_parent)));
+    assert!(report.issues.contains(&FsckIssue::WrongType(blob, Format::Tree, Format::Blob)));
+
+    // A loose object that doesn't even inflate is corrupt rather than a panic
+    fs::write(&path, b"not zlib").unwrap();
+    let report = repo.fsck().unwrap();
+    assert!(report.issues.iter().any(|issue| matches!(issue, FsckIssue::Corrupt(sha, _) if *sha == blob)));
+    assert!(repo.read_object(&blob).is_err());
+}
@@ -0,0 +1,109 @@
+use minit::repository::{AddOptions, Repository};
+use minit::ignore::IgnoreRule;
+use minit::pathspec::Pathspec;
+use minit::status::UntrackedFiles;
+use minit::error::Error;
+use std::{fs, io::Write, path::Path};
+
+mod common;
+use common::empty_repository;
+
+fn rule(line: &str) -> IgnoreRule {
+    IgnoreRule::parse(line, "", Path::new(".gitignore"), 1).unwrap()
+}
+
+#[test]
+fn test_ignore_rule_syntax() {
+    assert!(IgnoreRule::parse("# comment", "", Path::new(".gitignore"), 1).is_none());
+    assert!(IgnoreRule::parse("   ", "", Path::new(".gitignore"), 1).is_none());
+    assert!(IgnoreRule::parse("/", "", Path::new(".gitignore"), 1).is_none());
+
+    // A name matches at any depth, a path only from the ignore file's directory
+    assert!(rule("*.o").matches("a/b/c.o", false));
+    assert!(rule("doc/*.txt").matches("doc/a.txt", false));
+    assert!(!rule("doc/*.txt").matches("x/doc/a.txt", false));
+    assert!(!rule("doc/*.txt").matches("doc/sub/a.txt", false));
+    assert!(rule("/top").matches("top", false));
+    assert!(!rule("/top").matches("sub/top", false));
+
+    // `**` crosses directories, a trailing `/` only matches directories
+    assert!(rule("**/logs").matches("a/b/logs", true));
+    assert!(rule("a/**/z").matches("a/z", false));
+    assert!(rule("a/**/z").matches("a/x/y/z", false));
+    assert!(rule("out/**").matches("out/deep/file", false));
+    assert!(rule("build/").matches("src/build", true));
+    assert!(!rule("build/").matches("src/build", false));
+
+    // Escapes and trailing spaces
+    assert!(rule("\\#file").matches("#file", false));
+    assert!(!rule("\\!bang").negated);
+    assert!(rule("\\!bang").matches("!bang", false));
+    assert!(rule("!bang").negated);
+    assert!(rule("trailing   ").matches("trailing", false));
+    assert!(rule("space\\ ").matches("space ", false));
+    assert!(rule("[ab]?.c").matches("bz.c", false));
+
+    let nested = IgnoreRule::parse("*.tmp", "sub", Path::new("sub/.gitignore"), 3).unwrap();
+    assert!(nested.matches("sub/deeper/x.tmp", false));
+    assert!(!nested.matches("x.tmp", false));
+    assert_eq!(nested.line, 3);
+}
+
+#[test]
+fn test_ignore_precedence() {
+    let repo = empty_repository("ignore");
+    let worktree = repo.worktree().to_path_buf();
+    fs::create_dir_all(worktree.join("sub/build")).unwrap();
+    fs::create_dir_all(repo.repo_path(vec!["info"])).unwrap();
+    fs::write(worktree.join(".gitignore"), b"*.log\nbuild/\n").unwrap();
+    fs::write(worktree.join(".minitignore"), b"!keep.log\n").unwrap();
+    fs::write(worktree.join("sub/.gitignore"), b"!*.log\n").unwrap();
+    fs::write(repo.repo_path(vec!["info", "exclude"]), b"secret\n*.bak\n").unwrap();
+    let excludes = repo.repo_path(vec!["global-excludes"]);
+    fs::write(&excludes, b"*.swp\n!*.bak\n").unwrap();
+    let mut config = fs::OpenOptions::new().append(true).open(repo.repo_path(vec!["config"])).unwrap();
+    config.write_all(b"[core]\nexcludesFile = .minit/global-excludes\n").unwrap();
+    let repo = Repository::find(&worktree, true).unwrap().unwrap();
+    let worktree = repo.worktree().to_path_buf();
+
+    let mut ignore = repo.ignore().unwrap();
+    assert!(ignore.is_ignored("a.log", false).unwrap());
+    assert!(!ignore.is_ignored("keep.log", false).unwrap());
+    assert!(!ignore.is_ignored("sub/a.log", false).unwrap());
+    assert!(ignore.is_ignored("secret", false).unwrap());
+    assert!(ignore.is_ignored("x.swp", false).unwrap());
+    // info/exclude outranks the user's excludes file
+    assert!(ignore.is_ignored("x.bak", false).unwrap());
+    // Nothing inside an ignored directory can be re-included
+    assert!(ignore.is_ignored("sub/build/a.log", false).unwrap());
+
+    let rule = ignore.matching("sub/build/a.log", false).unwrap().unwrap();
+    assert_eq!((rule.source.clone(), rule.line, rule.text.as_str()), (worktree.join(".gitignore"), 2, "build/"));
+    let rule = ignore.matching("keep.log", false).unwrap().unwrap();
+    assert!(rule.negated);
+    assert_eq!(rule.source, worktree.join(".minitignore"));
+    assert!(ignore.matching("plain.txt", false).unwrap().is_none());
+
+    // Status and add use the same rules
+    for file in ["a.log", "keep.log", "sub/a.log", "sub/build/o", "x.swp", "plain.txt"] {
+        fs::write(worktree.join(file), b"").unwrap();
+    }
+    let status = repo.status(UntrackedFiles::All).unwrap();
+    assert_eq!(status.untracked, vec![".gitignore", ".minitignore", "keep.log", "plain.txt", "sub/.gitignore", "sub/a.log"]);
+
+    let all = AddOptions { all: true, ..AddOptions::default() };
+    repo.add(&Pathspec::default(), &all).unwrap();
+    let staged = repo.read_index().unwrap().entries.into_iter().map(|entry| entry.path).collect::<Vec<String>>();
+    assert_eq!(staged, status.untracked);
+
+    let named = Pathspec::from_patterns(vec![String::from("a.log")]);
+    match repo.add(&named, &AddOptions::default()) {
+        Err(Error::Refused(reason)) => assert!(reason.contains("ignored") && reason.contains("a.log"), "{}", reason),
+        other => panic!("expected ignored files to be refused, got {:?}", other),
+    }
+    repo.add(&named, &AddOptions { force: true, ..AddOptions::default() }).unwrap();
+    assert!(repo.read_index().unwrap().get("a.log").is_some());
+    // Once tracked, an ignored file is no longer untracked or ignored for add
+    fs::write(worktree.join("a.log"), b"changed").unwrap();
+    assert_eq!(repo.add(&named, &AddOptions::default()).unwrap().len(), 1);
+}
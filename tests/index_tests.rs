@@ -0,0 +1,99 @@
+use minit::repository::Repository;
+use minit::hash::HashAlgorithm;
+use minit::index::{Index, IndexEntry};
+use std::{fs, path::Path};
+
+mod common;
+use common::temp_dir;
+
+// Written by git from the same worktree: a.txt, dir/b.txt, dir/sub/c.txt, a symlink and an
+// executable, plus an intent-to-add new.txt from version 3 on.
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new("tests/fixtures/index").join(name)).unwrap()
+}
+
+fn paths(index: &Index) -> Vec<&str> {
+    index.entries.iter().map(|entry| entry.path.as_str()).collect()
+}
+
+#[test]
+fn test_read_git_indexes() {
+    let data = fixture("v2");
+    let index = Index::parse(&data, HashAlgorithm::Sha1).unwrap();
+    assert_eq!(index.version, 2);
+    assert_eq!(paths(&index), vec!["a.txt", "dir/b.txt", "dir/sub/c.txt", "link", "run.sh"]);
+    let a = index.get("a.txt").unwrap();
+    assert_eq!(a.sha.to_string(), "78981922613b2afb6025042ff6bd878ac1994e85");
+    assert_eq!(a.mode, 0o100644);
+    assert_eq!(a.size, 2);
+    assert_eq!(index.get("link").unwrap().mode, 0o120000);
+    assert_eq!(index.get("run.sh").unwrap().mode, 0o100755);
+    assert_eq!(index.serialize(HashAlgorithm::Sha1), data);
+
+    let data = fixture("v3");
+    let v3 = Index::parse(&data, HashAlgorithm::Sha1).unwrap();
+    assert_eq!(v3.version, 3);
+    assert!(v3.get("new.txt").unwrap().intent_to_add);
+    assert!(!v3.get("a.txt").unwrap().intent_to_add);
+    assert_eq!(v3.serialize(HashAlgorithm::Sha1), data);
+
+    let data = fixture("v4");
+    let v4 = Index::parse(&data, HashAlgorithm::Sha1).unwrap();
+    assert_eq!(v4.version, 4);
+    assert_eq!(v4.entries, v3.entries);
+    assert_eq!(v4.serialize(HashAlgorithm::Sha1), data);
+
+    // The cached tree git writes on commit is skipped, and dropped on write
+    let data = fixture("tree-extension");
+    let cached = Index::parse(&data, HashAlgorithm::Sha1).unwrap();
+    assert_eq!(paths(&cached), paths(&index));
+    let rewritten = cached.serialize(HashAlgorithm::Sha1);
+    assert!(rewritten.len() < data.len());
+    assert_eq!(Index::parse(&rewritten, HashAlgorithm::Sha1).unwrap(), cached);
+}
+
+#[test]
+fn test_reject_damaged_index() {
+    let mut data = fixture("v2");
+    assert!(Index::parse(&data, HashAlgorithm::Sha256).is_err());
+    data[70] ^= 1;
+    assert!(Index::parse(&data, HashAlgorithm::Sha1).is_err());
+    assert!(Index::parse(&data[0..30], HashAlgorithm::Sha1).is_err());
+    assert!(Index::parse(b"", HashAlgorithm::Sha1).is_err());
+}
+
+#[test]
+fn test_repository_index() {
+    let temp = temp_dir("index");
+    let repo = Repository::create(temp.path(), None).unwrap();
+    assert_eq!(repo.read_index().unwrap(), Index::default());
+
+    let sha = HashAlgorithm::Sha256.digest(b"blob 0\x00");
+    let mut index = Index::default();
+    for name in ["b", "a/z", "a-b", "a"] {
+        index.add(IndexEntry::new(String::from(name), 0o100644, sha));
+    }
+    let mut conflict = IndexEntry::new(String::from("a"), 0o100644, sha);
+    conflict.stage = 2;
+    index.add(conflict);
+    index.add(IndexEntry::new(String::from("b"), 0o100755, sha));
+    assert_eq!(paths(&index), vec!["a", "a", "a-b", "a/z", "b"]);
+    assert_eq!(index.get("b").unwrap().mode, 0o100755);
+
+    repo.write_index(&index).unwrap();
+    assert_eq!(repo.read_index().unwrap(), index);
+
+    assert!(index.remove("a"));
+    assert!(!index.remove("a"));
+    assert!(index.get("a").is_none());
+    index.version = 4;
+    repo.write_index(&index).unwrap();
+    let read = repo.read_index().unwrap();
+    assert_eq!(read, index);
+    assert_eq!(read.get("a/z").unwrap().sha, sha);
+
+    // Someone else holding the lock wins
+    fs::write(repo.repo_path(vec!["index.lock"]), b"").unwrap();
+    assert!(repo.write_index(&Index::default()).is_err());
+    assert_eq!(repo.read_index().unwrap(), index);
+}
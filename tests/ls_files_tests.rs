@@ -0,0 +1,48 @@
+use minit::repository::{AddOptions, LsFilesOptions};
+use minit::pathspec::Pathspec;
+use std::fs;
+
+mod common;
+use common::empty_repository;
+
+#[test]
+fn test_ls_files() {
+    let repo = empty_repository("ls_files");
+    let worktree = repo.worktree().to_path_buf();
+    fs::create_dir_all(worktree.join("dir/sub")).unwrap();
+    for (file, content) in [("a.txt", "a\n"), ("c.txt", "c\n"), ("dir/sub/b.txt", "b\n"), (".gitignore", "*.log\n")] {
+        fs::write(worktree.join(file), content).unwrap();
+    }
+    repo.add(&Pathspec::default(), &AddOptions { all: true, ..AddOptions::default() }).unwrap();
+    fs::write(worktree.join("a.txt"), b"edited\n").unwrap();
+    fs::remove_file(worktree.join("c.txt")).unwrap();
+    fs::write(worktree.join("untracked"), b"").unwrap();
+    fs::write(worktree.join("debug.log"), b"").unwrap();
+
+    let list = |options: LsFilesOptions| repo.ls_files(&Pathspec::default(), &options).unwrap();
+    assert_eq!(list(LsFilesOptions::default()), vec![".gitignore", "a.txt", "c.txt", "dir/sub/b.txt"]);
+    assert_eq!(list(LsFilesOptions { deleted: true, ..LsFilesOptions::default() }), vec!["c.txt"]);
+    assert_eq!(list(LsFilesOptions { modified: true, ..LsFilesOptions::default() }), vec!["a.txt", "c.txt"]);
+    assert_eq!(list(LsFilesOptions { others: true, ..LsFilesOptions::default() }), vec!["debug.log", "untracked"]);
+    assert_eq!(list(LsFilesOptions { others: true, exclude_standard: true, ..LsFilesOptions::default() }), vec!["untracked"]);
+    // Each entry is followed by its deleted and modified lines, as git prints them
+    let everything = LsFilesOptions { cached: true, deleted: true, modified: true, others: true, exclude_standard: true, ..LsFilesOptions::default() };
+    assert_eq!(list(everything), vec!["untracked", ".gitignore", "a.txt", "a.txt", "c.txt", "c.txt", "c.txt", "dir/sub/b.txt"]);
+
+    // Stage lines as written by git for the same files
+    let dir = Pathspec::from_patterns(vec![String::from("dir")]);
+    assert_eq!(
+        repo.ls_files(&dir, &LsFilesOptions { stage: true, ..LsFilesOptions::default() }).unwrap(),
+        vec!["100644 61780798228d17af2d34fce4cfbdf35556832472 0\tdir/sub/b.txt"],
+    );
+
+    let unmerged = LsFilesOptions { unmerged: true, ..LsFilesOptions::default() };
+    assert!(list(unmerged.clone()).is_empty());
+    let mut index = repo.read_index().unwrap();
+    let mut theirs = index.get("a.txt").unwrap().clone();
+    index.remove("a.txt");
+    theirs.stage = 3;
+    index.add(theirs.clone());
+    repo.write_index(&index).unwrap();
+    assert_eq!(list(unmerged), vec![format!("100644 {} 3\ta.txt", theirs.sha)]);
+}
@@ -0,0 +1,336 @@
+use minit::object::{Leaf, Object, commit::Identity};
+use minit::cli::{self, Format};
+use minit::hash::{HashAlgorithm, ObjectId, ObjectIdPrefix};
+use minit::repository::Repository;
+use std::{fs, io::Read};
+
+mod common;
+use common::{empty_repository, temp_dir};
+
+const BLOB_SHA: &str = "0de8a387362327d616c7db64c04f46d956317c1ba2e91237c904c3322b7a381a";
+const TREE_SHA: &str = "d6d3e24a3403099049d7ac701834ac8d14ffcdec02114e30d3f7ead31ed5f249";
+
+#[test]
+fn test_tree_binary_round_trip() {
+    let blob_sha = BLOB_SHA.parse::<ObjectId>().unwrap();
+    let tree_sha = TREE_SHA.parse::<ObjectId>().unwrap();
+    let tree = Object::Tree(vec![
+        Leaf::new(String::from("40000"), String::from("foo"), tree_sha),
+        Leaf::new(String::from("100644"), String::from("foo.txt"), blob_sha),
+        Leaf::new(String::from("100644"), String::from("foo-bar"), blob_sha),
+        Leaf::new(String::from("100755"), String::from("multi\nline"), blob_sha),
+    ]);
+    let data = tree.serialize().unwrap();
+
+    // "foo" is a directory so it sorts as "foo/", after both "foo-bar" and "foo.txt".
+    let mut expected: Vec<u8> = Vec::new();
+    for (mode, path, sha) in [
+        ("100644", "foo-bar", BLOB_SHA),
+        ("100644", "foo.txt", BLOB_SHA),
+        ("40000", "foo", TREE_SHA),
+        ("100755", "multi\nline", BLOB_SHA),
+    ] {
+        expected.extend(format!("{} {}\x00", mode, path).as_bytes());
+        expected.extend((0..sha.len()).step_by(2).map(|i| u8::from_str_radix(&sha[i..i+2], 16).unwrap()));
+    }
+    assert_eq!(data, expected);
+
+    let parsed = match Object::new(Format::Tree, data.clone(), HashAlgorithm::Sha256).unwrap() {
+        Object::Tree(leaves) => leaves,
+        _ => panic!("expected a tree"),
+    };
+    let paths = parsed.iter().map(|leaf| leaf.path_str().unwrap()).collect::<Vec<&str>>();
+    assert_eq!(paths, vec!["foo-bar", "foo.txt", "foo", "multi\nline"]);
+    assert_eq!(parsed[2].get_type().unwrap(), "tree");
+    assert_eq!(parsed[2].sha, tree_sha);
+    assert_eq!(Object::Tree(parsed).serialize().unwrap(), data);
+}
+
+#[test]
+fn test_tree_truncated_digest() {
+    let mut data = b"100644 foo.txt\x00".to_vec();
+    data.extend([0xab; 12]);
+    assert!(Object::new(Format::Tree, data, HashAlgorithm::Sha256).is_err());
+}
+
+#[test]
+fn test_tree_non_utf8_names() {
+    let repo = empty_repository("tree_bytes");
+    let blob = repo.write_object(Object::Blob(b"latin-1\n".to_vec())).unwrap();
+    let tree = Object::Tree(vec![Leaf::new(String::from("100644"), b"caf\xe9.txt".to_vec(), blob)]);
+    let data = tree.serialize().unwrap();
+    let parsed = match Object::new(Format::Tree, data.clone(), HashAlgorithm::Sha1).unwrap() {
+        Object::Tree(leaves) => leaves,
+        _ => panic!("expected a tree"),
+    };
+    assert_eq!(parsed[0].path, b"caf\xe9.txt");
+    assert!(parsed[0].path_str().is_err());
+    assert_eq!(Object::Tree(parsed).serialize().unwrap(), data);
+
+    // Such trees stay readable for connectivity checks and packing
+    let tree = repo.write_object(tree).unwrap();
+    fs::write(repo.repo_path(vec!["refs", "tags", "tree"]), format!("{}\n", tree)).unwrap();
+    assert!(repo.reachable_objects().unwrap().contains(&blob));
+    repo.repack(&minit::object::delta::DeltaOptions::default()).unwrap().unwrap();
+    let report = repo.fsck().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+
+    // An unknown mode is an error rather than a panic
+    let odd = Leaf::new(String::from("777777"), "odd", blob);
+    assert!(odd.get_type().is_err());
+    let odd_tree = repo.write_object(Object::Tree(vec![odd])).unwrap();
+    let result = cli::ls_tree(false, &odd_tree.to_string(), Some(repo.path().to_string_lossy().into_owned()), Default::default());
+    assert!(matches!(result, Err(minit::error::Error::MalformedObject(_))));
+}
+
+#[test]
+fn test_error_source() {
+    use std::error::Error as _;
+    let io = minit::error::Error::from(std::io::Error::other("disk on fire"));
+    assert_eq!(io.source().unwrap().to_string(), "disk on fire");
+    let utf8 = minit::error::Error::from(String::from_utf8(vec![0xff]).unwrap_err());
+    assert!(utf8.source().is_some());
+    assert!(minit::error::Error::MalformedObject(String::from("bad")).source().is_none());
+}
+
+#[test]
+fn test_object_id_parsing() {
+    let sha = BLOB_SHA.parse::<ObjectId>().unwrap();
+    assert_eq!(sha.algorithm(), HashAlgorithm::Sha256);
+    assert_eq!(sha.as_bytes().len(), 32);
+    assert_eq!(sha.to_string(), BLOB_SHA);
+    assert_eq!(ObjectId::from_hex(&BLOB_SHA.to_uppercase(), HashAlgorithm::Sha256).unwrap(), sha);
+    assert!(ObjectId::from_hex(BLOB_SHA, HashAlgorithm::Sha1).is_err());
+    assert!(ObjectId::from_hex(&BLOB_SHA[0..40], HashAlgorithm::Sha256).is_err());
+    assert!("zz".repeat(20).parse::<ObjectId>().is_err());
+    assert!(ObjectId::null(HashAlgorithm::Sha1).is_null());
+
+    let prefix = ObjectIdPrefix::parse("0DE8A3", HashAlgorithm::Sha256).unwrap();
+    assert_eq!(prefix.as_str(), "0de8a3");
+    assert_eq!(prefix.first_byte(), 0x0d);
+    assert!(prefix.matches(&sha));
+    assert!(ObjectIdPrefix::parse("0de", HashAlgorithm::Sha256).is_err());
+    assert!(ObjectIdPrefix::parse("0de8g", HashAlgorithm::Sha256).is_err());
+    assert!(ObjectIdPrefix::parse(BLOB_SHA, HashAlgorithm::Sha1).is_err());
+}
+
+#[test]
+fn test_sha1_repository() {
+    let temp = temp_dir("sha1");
+    _ = Repository::create(temp.path(), Some(HashAlgorithm::Sha1)).unwrap();
+    let repo = Repository::new(temp.path(), false).unwrap();
+    assert_eq!(repo.algorithm(), HashAlgorithm::Sha1);
+
+    let file_path = temp.path().join("helloworld.txt");
+    fs::write(&file_path, b"helloworld").unwrap();
+    let sha = cli::hash_object(Format::Blob, true, file_path.to_str().unwrap()).unwrap();
+    assert_eq!(sha.to_string(), "620ffd0fd9579a46e46ef4505b198ee0a01a57f2");
+
+    // Tree digests are 20 raw bytes in a SHA-1 repository
+    let tree = Object::Tree(vec![Leaf::new(String::from("100644"), String::from("helloworld.txt"), sha)]);
+    let tree_sha = repo.write_object(tree).unwrap();
+    assert_eq!(tree_sha.algorithm(), HashAlgorithm::Sha1);
+    assert_eq!(tree_sha.to_hex().len(), 40);
+    match repo.read_object(&tree_sha).unwrap() {
+        Object::Tree(leaves) => assert_eq!(leaves[0].sha, sha),
+        _ => panic!("expected a tree"),
+    }
+    assert_eq!(repo.resolve_object(&sha.to_hex()[0..8]).unwrap(), vec![sha]);
+    // Too short to be a prefix, so it can only name a ref
+    assert!(repo.resolve_object("62").unwrap().is_empty());
+}
+
+#[test]
+fn test_hash_object_types() {
+    let temp = temp_dir("hash_types");
+    let repo = Repository::create(temp.path(), None).unwrap();
+    let blob_sha = repo.write_object(Object::Blob(b"helloworld".to_vec())).unwrap();
+    let tree = Object::Tree(vec![Leaf::new(String::from("100644"), "helloworld.txt", blob_sha)]);
+    let tree_path = temp.path().join("tree.bin");
+    fs::write(&tree_path, tree.serialize().unwrap()).unwrap();
+    let tree_path = tree_path.to_str().unwrap();
+
+    let (expected, _) = tree.write(repo.algorithm()).unwrap();
+    assert_eq!(cli::hash_object(Format::Tree, false, tree_path).unwrap(), expected);
+    assert!(repo.read_object(&expected).is_err());
+    assert_eq!(cli::hash_object(Format::Tree, true, tree_path).unwrap(), expected);
+    assert!(matches!(repo.read_object(&expected).unwrap(), Object::Tree(_)));
+
+    // A commit that doesn't parse is refused instead of stored
+    let commit_path = temp.path().join("commit.txt");
+    fs::write(&commit_path, b"not a commit").unwrap();
+    let commit_path = commit_path.to_str().unwrap();
+    assert!(cli::hash_object(Format::Commit, true, commit_path).is_err());
+    assert!(cli::hash_object(Format::Tag, false, commit_path).is_err());
+}
+
+#[test]
+fn test_streaming_binary_blob() {
+    let temp = temp_dir("stream");
+    let repo = Repository::create(temp.path(), None).unwrap();
+
+    // Several buffers worth of data that is not valid UTF-8
+    let data = (0..3 * 1024 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect::<Vec<u8>>();
+    assert!(String::from_utf8(data.clone()).is_err());
+    let file_path = temp.path().join("asset.bin");
+    fs::write(&file_path, &data).unwrap();
+    let file_path = file_path.to_str().unwrap();
+
+    let (expected, _) = Object::Blob(data.clone()).write(HashAlgorithm::Sha256).unwrap();
+    assert_eq!(cli::hash_object(Format::Blob, false, file_path).unwrap(), expected);
+    assert_eq!(cli::hash_object(Format::Blob, true, file_path).unwrap(), expected);
+    assert_eq!(repo.loose_objects().unwrap(), vec![expected]);
+
+    let mut streamed: Vec<u8> = Vec::new();
+    repo.read_blob_stream(&expected).unwrap().read_to_end(&mut streamed).unwrap();
+    assert!(streamed == data);
+
+    // A short reader must not leave a half written object behind
+    assert!(repo.write_stream(Format::Blob, 10, &b"short"[..]).is_err());
+    assert_eq!(repo.loose_objects().unwrap(), vec![expected]);
+    assert_eq!(repo.repo_path(vec!["objects"]).read_dir().unwrap().count(), 1);
+}
+
+const SIGNED_MERGE: &str = "tree d6d3e24a3403099049d7ac701834ac8d14ffcdec02114e30d3f7ead31ed5f249
+parent 0de8a387362327d616c7db64c04f46d956317c1ba2e91237c904c3322b7a381a
+parent d6d3e24a3403099049d7ac701834ac8d14ffcdec02114e30d3f7ead31ed5f249
+author A U Thor <author@example.com> 1700000000 +0530
+committer C O Mitter <committer@example.com> 1700000100 -0000
+encoding ISO-8859-1
+mergetag object 0de8a387362327d616c7db64c04f46d956317c1ba2e91237c904c3322b7a381a
+ type commit
+ tag v1.0
+ tagger A U Thor <author@example.com> 1699999999 -0800
+ 
+ release
+x-custom some value
+gpgsig -----BEGIN PGP SIGNATURE-----
+ 
+ iQEzBAABCAAdFiEE
+ -----END PGP SIGNATURE-----
+
+Merge tag 'v1.0'
+
+Signed-off-by: A U Thor <author@example.com>
+";
+
+#[test]
+fn test_commit_round_trip() {
+    let commit = match Object::new(Format::Commit, SIGNED_MERGE.as_bytes().to_vec(), HashAlgorithm::Sha256).unwrap() {
+        Object::Commit(commit) => commit,
+        _ => panic!("expected a commit"),
+    };
+    assert_eq!(commit.tree, TREE_SHA.parse::<ObjectId>().unwrap());
+    assert_eq!(commit.parents.len(), 2);
+    assert_eq!(commit.author.name, b"A U Thor");
+    assert_eq!(commit.author.email, b"author@example.com");
+    assert_eq!(commit.author.time, 1700000000);
+    assert_eq!(commit.author.offset, 330);
+    assert_eq!(commit.committer.offset, 0);
+    assert_eq!(commit.encoding.as_deref(), Some("ISO-8859-1"));
+    let keys = commit.extra_headers.iter().map(|(k, _)| k.as_str()).collect::<Vec<&str>>();
+    assert_eq!(keys, vec!["mergetag", "x-custom"]);
+    assert!(commit.extra_headers[0].1.ends_with(b"tagger A U Thor <author@example.com> 1699999999 -0800\n\nrelease"));
+    assert!(commit.gpgsig.as_ref().unwrap().ends_with(b"-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----"));
+    assert_eq!(commit.message.as_deref(), Some(&b"Merge tag 'v1.0'\n\nSigned-off-by: A U Thor <author@example.com>\n"[..]));
+
+    assert_eq!(Object::Commit(commit).serialize().unwrap(), SIGNED_MERGE.as_bytes());
+}
+
+#[test]
+fn test_tag_round_trip() {
+    let raw = "object d5974d504fee54fc5ae45de93bdd2f09d82b1877\ntype commit\ntag v1\ntagger A U Thor <a@x.org> 1792311473 +0000\n\nrelease\n";
+    let tag = match Object::new(Format::Tag, raw.as_bytes().to_vec(), HashAlgorithm::Sha1).unwrap() {
+        Object::Tag(tag) => tag,
+        _ => panic!("expected a tag"),
+    };
+    assert_eq!(tag.object.to_string(), "d5974d504fee54fc5ae45de93bdd2f09d82b1877");
+    assert_eq!(tag.kind, Format::Commit);
+    assert_eq!(tag.name, "v1");
+    assert_eq!(tag.tagger.as_ref().unwrap().time, 1792311473);
+    assert_eq!(tag.message.as_deref(), Some(&b"release\n"[..]));
+    assert_eq!(tag.serialize(), raw.as_bytes());
+
+    assert!(Object::new(Format::Tag, b"type commit\ntag v1\n\n".to_vec(), HashAlgorithm::Sha1).is_err());
+}
+
+#[test]
+fn test_identity_parsing() {
+    let identity = Identity::parse(b"Jane Doe <jane@example.com> 1700000000 -0130").unwrap();
+    assert_eq!(identity, Identity::new("Jane Doe", "jane@example.com", 1700000000, -90));
+    assert_eq!(identity.to_string(), "Jane Doe <jane@example.com> 1700000000 -0130");
+
+    for malformed in [
+        "Jane Doe jane@example.com 1700000000 +0000",
+        "Jane Doe<jane@example.com> 1700000000 +0000",
+        "Jane Doe <jane@example.com>",
+        "Jane Doe <jane@example.com> 01700000000 +0000",
+        "Jane Doe <jane@example.com> 1700000000 0000",
+        "Jane Doe <jane@example.com> 1700000000 +00",
+    ] {
+        assert!(Identity::parse(malformed.as_bytes()).is_err(), "{}", malformed);
+    }
+
+    // Objects git already wrote keep whatever identity they hold
+    let padded = Identity::lenient(b"Jane Doe <jane@example.com> 01700000000 +0100");
+    assert_eq!((padded.time, padded.offset), (1700000000, 60));
+    assert_eq!(padded.to_bytes(), b"Jane Doe <jane@example.com> 01700000000 +0100");
+    let mut renamed = padded.clone();
+    renamed.name = b"John Doe".to_vec();
+    assert_eq!(renamed.to_bytes(), b"John Doe <jane@example.com> 1700000000 +0100");
+}
+
+#[test]
+fn test_malformed_identity_round_trip() {
+    let tree = format!("tree {}\n", TREE_SHA);
+    for identity in [
+        "Jane Doe <jane@example.com> 1700000000",
+        "Jane Doe <jane@example.com> 01700000000 +0000",
+        "Jane Doe<jane@example.com> 1700000000 +0000",
+        "Jane Doe <jane@example.com>",
+        "Jane Doe",
+    ] {
+        let raw = format!("{}author {}\ncommitter {}\n\nmessage\n", tree, identity, identity).into_bytes();
+        let object = Object::new(Format::Commit, raw.clone(), HashAlgorithm::Sha256).unwrap();
+        assert_eq!(object.serialize().unwrap(), raw, "{}", identity);
+
+        let raw = format!("object {}\ntype tree\ntag v1\ntagger {}\n\nrelease\n", TREE_SHA, identity).into_bytes();
+        let object = Object::new(Format::Tag, raw.clone(), HashAlgorithm::Sha256).unwrap();
+        assert_eq!(object.serialize().unwrap(), raw, "{}", identity);
+    }
+}
+
+#[test]
+fn test_commit_header_edge_cases() {
+    let tree = format!("tree {}\n", TREE_SHA);
+    let committer = "committer A U Thor <a@example.com> 1700000000 +0000\n";
+    let author = format!("author A U Thor <a@example.com> 1700000000 +0000\n{}", committer);
+    let cases: Vec<Vec<u8>> = vec![
+        // Latin-1 name declared through the encoding header
+        [tree.as_bytes(), b"author J\xf6rg <j@example.com> 1700000000 +0100\n", committer.as_bytes(), b"encoding ISO-8859-1\n\nGr\xfc\xdfe\n"].concat(),
+        // An extra header that happens to be called message, and no blank line at all
+        [tree.as_bytes(), author.as_bytes(), b"message not the message\n"].concat(),
+        // An empty message and a header with an empty value
+        [tree.as_bytes(), author.as_bytes(), b"x-empty \n\n"].concat(),
+    ];
+    for raw in cases {
+        let object = Object::new(Format::Commit, raw.clone(), HashAlgorithm::Sha256).unwrap();
+        assert_eq!(object.serialize().unwrap(), raw, "{}", String::from_utf8_lossy(&raw));
+    }
+
+    let commit = match Object::new(Format::Commit, [tree.as_bytes(), author.as_bytes(), b"message not the message\n"].concat(), HashAlgorithm::Sha256).unwrap() {
+        Object::Commit(commit) => commit,
+        _ => panic!("expected a commit"),
+    };
+    assert_eq!(commit.extra_headers, vec![(String::from("message"), b"not the message".to_vec())]);
+    assert_eq!(commit.message, None);
+
+    for malformed in [
+        format!("{}{}", tree, author.trim_end()),
+        format!(" {}{}", tree, author),
+        format!("{}{}novalue\n\n", tree, author),
+        format!("{}\n", author),
+    ] {
+        assert!(Object::new(Format::Commit, malformed.clone().into_bytes(), HashAlgorithm::Sha256).is_err(), "{}", malformed);
+    }
+}
@@ -0,0 +1,212 @@
+use minit::repository::Repository;
+use minit::hash::{HashAlgorithm, ObjectId};
+use minit::object::{Leaf, Object, commit::{Commit, Identity}, delta::DeltaOptions};
+use minit::cli::Format;
+use std::{fs, io::Read, path::Path};
+use minit::error::Error;
+use minit::pack::PackIndex;
+use minit::fsck::FsckIssue;
+
+mod common;
+use common::{empty_repository, temp_dir, TestRepository};
+
+// Both fixtures were written by git from the same three commit history, one with
+// OFS_DELTA entries and one with REF_DELTA entries.
+const HEAD: &str = "63c91b1a24100bb6dbe8721a1ef84f9343ac5168";
+const BIG_TXT: [&str; 3] = [
+    "bcf1310ea2f417812479a6f6da96e383bff0cb4c",
+    "6a78b9623a9c00f8c14852f63cab5e2fb9c5a5b0",
+    "5ec4ef5aed008ba0b9fea7c07c57b8a7478822cf",
+];
+
+fn repository_with_pack(name: &str, fixture: &str) -> TestRepository {
+    let repo = empty_repository(name);
+    let pack_dir = repo.repo_dir(vec!["objects", "pack"], true).unwrap().unwrap();
+    for entry in Path::new("tests/fixtures/pack").join(fixture).read_dir().unwrap() {
+        let entry = entry.unwrap();
+        fs::copy(entry.path(), pack_dir.join(entry.file_name())).unwrap();
+    }
+    repo
+}
+
+fn id(hex: &str) -> ObjectId {
+    ObjectId::from_hex(hex, HashAlgorithm::Sha1).unwrap()
+}
+
+fn check_pack(repo: &Repository) {
+    let head = id(HEAD);
+    let packs = repo.packs().unwrap();
+    assert_eq!(packs.len(), 1);
+    assert_eq!(packs[0].len(), 10);
+
+    // Every object, delta or not, must hash back to its name
+    for sha in packs[0].ids() {
+        let (format, data) = repo.read_raw(&sha).unwrap();
+        let mut raw = format!("{} {}\x00", format, data.len()).into_bytes();
+        raw.extend(data);
+        assert_eq!(HashAlgorithm::Sha1.digest(&raw), sha);
+    }
+
+    let blobs = BIG_TXT.iter()
+        .map(|sha| match repo.read_object(&id(sha)).unwrap() {
+            Object::Blob(data) => String::from_utf8(data).unwrap(),
+            _ => panic!("expected a blob"),
+        })
+        .collect::<Vec<String>>();
+    assert!(blobs[0].contains("LINE 50 ") && blobs[0].contains("LINE 10 "));
+    assert!(!blobs[1].contains("LINE 50 ") && blobs[1].contains("LINE 100 "));
+    assert!(!blobs[2].contains("LINE "));
+
+    assert_eq!(repo.read_raw(&head).unwrap().0, Format::Commit);
+    match repo.read_object(&head).unwrap() {
+        Object::Commit(commit) => {
+            assert_eq!(commit.parents.len(), 1);
+            assert_eq!(commit.serialize(), repo.read_raw(&head).unwrap().1);
+        },
+        _ => panic!("expected a commit"),
+    }
+
+    for sha in BIG_TXT {
+        let mut streamed: Vec<u8> = Vec::new();
+        repo.read_blob_stream(&id(sha)).unwrap().read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, repo.read_raw(&id(sha)).unwrap().1);
+    }
+    assert!(matches!(repo.read_blob_stream(&head), Err(Error::WrongObjectType(..))));
+    assert_eq!(repo.resolve_object(&HEAD[0..7]).unwrap(), vec![head]);
+
+    // Nothing points into the fixture, so its history is garbage but intact
+    let report = repo.fsck().unwrap();
+    assert_eq!(report.checked, 10);
+    assert!(report.is_ok(), "{}", report);
+}
+
+#[test]
+fn test_read_ofs_delta_pack() {
+    check_pack(&repository_with_pack("pack_ofs", "ofs"));
+}
+
+#[test]
+fn test_read_ref_delta_pack() {
+    check_pack(&repository_with_pack("pack_ref", "ref"));
+}
+
+#[test]
+fn test_repack_and_prune() {
+    let repo = empty_repository("repack");
+
+    let base = (0..200).map(|i| format!("line {}\n", i)).collect::<String>();
+    let edited = base.replace("line 120\n", "line one hundred and twenty\n");
+    let base_sha = repo.write_object(Object::Blob(base.into_bytes())).unwrap();
+    let edited_sha = repo.write_object(Object::Blob(edited.clone().into_bytes())).unwrap();
+    let dangling_sha = repo.write_object(Object::Blob(b"nobody points at me".to_vec())).unwrap();
+    let tree_sha = repo.write_object(Object::Tree(vec![
+        Leaf::new(String::from("100644"), String::from("a.txt"), base_sha),
+        Leaf::new(String::from("100644"), String::from("b.txt"), edited_sha),
+    ])).unwrap();
+    let identity = Identity::new("Minit", "minit@example.com", 1700000000, 0);
+    let commit_sha = repo.write_object(Object::Commit(Commit {
+        tree: tree_sha,
+        parents: Vec::new(),
+        author: identity.clone(),
+        committer: identity,
+        encoding: None,
+        extra_headers: Vec::new(),
+        gpgsig: None,
+        message: Some(b"Initial commit\n".to_vec()),
+    })).unwrap();
+    fs::write(repo.repo_path(vec!["refs", "heads", "master"]), format!("{}\n", commit_sha)).unwrap();
+
+    let mut reachable = repo.reachable_objects().unwrap();
+    reachable.sort();
+    let mut expected = vec![commit_sha, tree_sha, base_sha, edited_sha];
+    expected.sort();
+    assert_eq!(reachable, expected);
+
+    repo.repack(&DeltaOptions::default()).unwrap().unwrap();
+    assert_eq!(repo.loose_objects().unwrap(), vec![dangling_sha]);
+    assert_eq!(repo.packs().unwrap()[0].len(), 4);
+    match repo.read_object(&edited_sha).unwrap() {
+        Object::Blob(data) => assert_eq!(data, edited.as_bytes()),
+        _ => panic!("expected a blob"),
+    }
+
+    // Packing again replaces the old pack rather than adding to it
+    repo.repack(&DeltaOptions { window: 0, max_depth: 50 }).unwrap().unwrap();
+    assert_eq!(repo.packs().unwrap().len(), 1);
+    let pack_dir = repo.repo_path(vec!["objects", "pack"]);
+    for entry in pack_dir.read_dir().unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        assert!(name.starts_with("pack-"), "{} was left behind", name);
+    }
+    assert_eq!(repo.read_raw(&commit_sha).unwrap().0, Format::Commit);
+
+    assert_eq!(repo.prune_loose().unwrap(), vec![dangling_sha]);
+    assert!(repo.read_object(&dangling_sha).is_err());
+}
+
+// A version 2 index holding a single object whose 4 byte offset is `offset`,
+// followed by `large` entries of the 8 byte offset table
+fn single_entry_index(digest: [u8; 20], offset: u32, large: usize) -> Vec<u8> {
+    let mut data = b"\xfftOc".to_vec();
+    data.extend(2u32.to_be_bytes());
+    for byte in 0..256 {
+        data.extend(u32::from(byte >= digest[0] as usize).to_be_bytes());
+    }
+    data.extend(digest);
+    data.extend(0u32.to_be_bytes());
+    data.extend(offset.to_be_bytes());
+    data.extend(vec![0; large * 8]);
+    data.extend([0; 40]);
+    data
+}
+
+#[test]
+fn test_corrupt_pack_is_rejected() {
+    let temp = temp_dir("pack_corrupt");
+    let path = temp.path();
+    let idx = path.join("pack-corrupt.idx");
+    let digest = [0x42; 20];
+
+    let mut backwards = single_entry_index(digest, 12, 0);
+    backwards[8 + 200 * 4..8 + 201 * 4].copy_from_slice(&5u32.to_be_bytes());
+    fs::write(&idx, backwards).unwrap();
+    assert!(matches!(PackIndex::open(&idx, HashAlgorithm::Sha1), Err(Error::MalformedPack(_))));
+
+    let mut truncated = single_entry_index(digest, 12, 0);
+    truncated.truncate(truncated.len() - 1);
+    fs::write(&idx, truncated).unwrap();
+    assert!(matches!(PackIndex::open(&idx, HashAlgorithm::Sha1), Err(Error::MalformedPack(_))));
+
+    fs::write(&idx, single_entry_index(digest, 0x8000_0001, 1)).unwrap();
+    assert!(matches!(PackIndex::open(&idx, HashAlgorithm::Sha1), Err(Error::MalformedPack(_))));
+    fs::write(&idx, single_entry_index(digest, 0x8000_0000, 1)).unwrap();
+    assert!(PackIndex::open(&idx, HashAlgorithm::Sha1).is_ok());
+
+    // An OFS_DELTA whose distance of 0 names itself as its own base
+    fs::write(&idx, single_entry_index(digest, 12, 0)).unwrap();
+    let mut pack = b"PACK".to_vec();
+    pack.extend(2u32.to_be_bytes());
+    pack.extend(1u32.to_be_bytes());
+    pack.extend([0x65, 0x00]);
+    pack.extend([0; 20]);
+    fs::write(path.join("pack-corrupt.pack"), pack).unwrap();
+    let index = PackIndex::open(&idx, HashAlgorithm::Sha1).unwrap();
+    let offset = index.find(&ObjectId::from_bytes(&digest, HashAlgorithm::Sha1).unwrap()).unwrap();
+    assert_eq!(offset, 12);
+    let result = index.read_at(offset, &|sha| Err(Error::ObjectNotDefined(sha.to_string())));
+    assert!(matches!(result, Err(Error::MalformedPack(_))));
+}
+
+#[test]
+fn test_damaged_index_is_skipped() {
+    let repo = repository_with_pack("pack_bad_idx", "ofs");
+    let pack_dir = repo.repo_path(vec!["objects", "pack"]);
+    fs::write(pack_dir.join("pack-0000.idx"), b"not an index").unwrap();
+
+    // The good pack still serves reads, and fsck names the bad one
+    assert_eq!(repo.packs().unwrap().len(), 1);
+    assert_eq!(repo.read_raw(&id(HEAD)).unwrap().0, Format::Commit);
+    let report = repo.fsck().unwrap();
+    let errors = report.issues.iter().filter(|issue| issue.is_error()).collect::<Vec<&FsckIssue>>();
+    assert!(matches!(&errors[..], [FsckIssue::BadPack(path, _)] if path.ends_with("pack-0000.idx")), "{}", report);
+}
@@ -0,0 +1,146 @@
+use minit::repository::{AddOptions, CommitOptions, Repository};
+use minit::refs::{Expected, PackedRef, PackedRefs, RefValue};
+use minit::hash::{HashAlgorithm, ObjectId};
+use minit::pathspec::Pathspec;
+use minit::cli;
+use std::fs;
+
+mod common;
+use common::fresh_repository;
+
+fn commit_file(repo: &Repository, file: &str, message: &str) -> ObjectId {
+    fs::write(repo.worktree().join(file), message).unwrap();
+    repo.add(&Pathspec::from_patterns(vec![file.to_string()]), &AddOptions::default()).unwrap();
+    repo.commit(Some(format!("{}\n", message).into_bytes()), &CommitOptions::default()).unwrap();
+    repo.resolve_ref("HEAD").unwrap()
+}
+
+fn names(repo: &Repository) -> Vec<String> {
+    repo.ls_ref(None).unwrap().into_keys().collect()
+}
+
+#[test]
+fn test_parse_packed_refs() {
+    let a = "1111111111111111111111111111111111111111";
+    let b = "2222222222222222222222222222222222222222";
+    let c = "3333333333333333333333333333333333333333";
+    let data = format!("# pack-refs with: peeled fully-peeled sorted \n{a} refs/heads/main\n{b} refs/tags/v1\n^{c}\n");
+    let packed = PackedRefs::parse(data.as_bytes(), HashAlgorithm::Sha1).unwrap();
+    let id = |hex: &str| ObjectId::from_hex(hex, HashAlgorithm::Sha1).unwrap();
+    assert_eq!(packed.refs, vec![
+        PackedRef { name: String::from("refs/heads/main"), sha: id(a), peeled: None },
+        PackedRef { name: String::from("refs/tags/v1"), sha: id(b), peeled: Some(id(c)) },
+    ]);
+    assert_eq!(packed.serialize(), data.as_bytes());
+    assert_eq!(packed.get("refs/tags/v1").unwrap().sha, id(b));
+    assert!(packed.get("refs/tags/v2").is_none());
+
+    // Files without the sorted trait are sorted on read
+    let unsorted = format!("{b} refs/tags/v1\n{a} refs/heads/main\n");
+    assert_eq!(PackedRefs::parse(unsorted.as_bytes(), HashAlgorithm::Sha1).unwrap().refs[0].name, "refs/heads/main");
+    assert!(PackedRefs::parse(format!("^{c}\n").as_bytes(), HashAlgorithm::Sha1).is_err());
+    assert!(PackedRefs::parse(format!("{a} refs/heads/a..b\n").as_bytes(), HashAlgorithm::Sha1).is_err());
+}
+
+#[test]
+fn test_pack_refs() {
+    let repo = fresh_repository("packed_refs");
+    let first = commit_file(&repo, "a.txt", "First");
+    repo.create_branch("topic", "HEAD", false).unwrap();
+    repo.create_branch("feature/x", "HEAD", false).unwrap();
+    repo.create_tag("light", "HEAD", false).unwrap();
+    repo.create_tag("annotated", "HEAD", true).unwrap();
+    let annotated = repo.resolve_ref("refs/tags/annotated").unwrap();
+    let second = commit_file(&repo, "a.txt", "Second");
+    let before = repo.ls_ref(None).unwrap();
+
+    // Without --all only tags are packed
+    repo.pack_refs(false, true).unwrap();
+    let packed = repo.packed_refs().unwrap();
+    assert_eq!(packed.refs.iter().map(|packed| packed.name.as_str()).collect::<Vec<&str>>(), vec!["refs/tags/annotated", "refs/tags/light"]);
+    assert_eq!(packed.get("refs/tags/annotated").unwrap().peeled, Some(first));
+    assert_eq!(packed.get("refs/tags/light").unwrap().peeled, None);
+    assert!(!repo.repo_path(vec!["refs", "tags", "light"]).exists());
+    assert!(repo.repo_path(vec!["refs", "tags"]).is_dir());
+
+    repo.pack_refs(true, true).unwrap();
+    assert_eq!(repo.packed_refs().unwrap().refs.len(), 5);
+    assert!(!repo.repo_path(vec!["refs", "heads", "master"]).exists());
+    assert!(!repo.repo_path(vec!["refs", "heads", "feature"]).exists());
+    assert_eq!(fs::read_to_string(repo.repo_path(vec!["HEAD"])).unwrap(), "ref: refs/heads/master\n");
+    assert!(repo.repo_path(vec!["logs", "refs", "heads", "master"]).is_file());
+
+    // Everything still reads the same
+    assert_eq!(repo.ls_ref(None).unwrap(), before);
+    assert_eq!(repo.resolve_ref("HEAD").unwrap(), second);
+    assert_eq!(repo.resolve_ref("refs/tags/annotated").unwrap(), annotated);
+    assert_eq!(repo.find_object("light", None, false).unwrap(), first);
+    assert_eq!(repo.find_object("topic", None, false).unwrap(), first);
+    assert_eq!(repo.branches().unwrap(), vec![
+        (String::from("feature/x"), first),
+        (String::from("master"), second),
+        (String::from("topic"), first),
+    ]);
+
+    // Committing writes a loose ref again, which shadows the packed one
+    let third = commit_file(&repo, "a.txt", "Third");
+    assert_eq!(repo.packed_refs().unwrap().get("refs/heads/master").unwrap().sha, second);
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), third);
+    assert_eq!(repo.ls_ref(None).unwrap()["refs/heads/master"], third);
+    assert_eq!(names(&repo).len(), 5);
+
+    // Without pruning the loose files stay
+    repo.pack_refs(true, false).unwrap();
+    assert_eq!(repo.packed_refs().unwrap().get("refs/heads/master").unwrap().sha, third);
+    assert!(repo.repo_path(vec!["refs", "heads", "master"]).is_file());
+}
+
+#[test]
+fn test_packed_refs_in_transactions() {
+    let repo = fresh_repository("packed_refs_transaction");
+    let first = commit_file(&repo, "a.txt", "First");
+    repo.create_branch("feature/x", "HEAD", false).unwrap();
+    repo.create_branch("loose", "HEAD", false).unwrap();
+    repo.create_tag("v1", "HEAD", false).unwrap();
+    repo.pack_refs(true, true).unwrap();
+    repo.create_branch("loose", "HEAD", true).unwrap();
+    assert!(repo.repo_path(vec!["refs", "heads", "loose"]).is_file());
+
+    // Packed refs exist for expectations and name checks
+    assert!(repo.create_tag("v1", "HEAD", false).is_err());
+    assert!(repo.create_branch("feature", "HEAD", false).is_err());
+    let mut transaction = repo.transaction();
+    transaction.update("refs/heads/feature/x", RefValue::Object(first), Expected::Missing, "again");
+    assert!(transaction.commit().is_err());
+
+    // Deleting takes a ref out of both places
+    repo.delete_branch("loose", true).unwrap();
+    repo.delete_branch("feature/x", true).unwrap();
+    let mut transaction = repo.transaction();
+    transaction.delete("refs/tags/v1", Expected::Value(first));
+    transaction.commit().unwrap();
+    assert!(repo.resolve_ref("refs/heads/loose").is_err());
+    assert!(!repo.repo_path(vec!["refs", "heads", "loose"]).exists());
+    assert!(!repo.repo_path(vec!["refs", "heads", "feature"]).exists());
+    assert!(!repo.repo_path(vec!["packed-refs.lock"]).exists());
+    assert_eq!(repo.packed_refs().unwrap().refs.iter().map(|packed| packed.name.as_str()).collect::<Vec<&str>>(), vec!["refs/heads/master"]);
+    assert_eq!(names(&repo), vec!["refs/heads/master"]);
+
+    // A branch can now be created where the directory was
+    repo.create_branch("feature", "HEAD", false).unwrap();
+    assert_eq!(repo.resolve_ref("refs/heads/feature").unwrap(), first);
+}
+
+#[test]
+fn test_switch_to_packed_branch() {
+    let repo = fresh_repository("packed_refs_switch");
+    commit_file(&repo, "a.txt", "First");
+    repo.create_branch("topic", "HEAD", false).unwrap();
+    repo.pack_refs(true, true).unwrap();
+    assert!(!repo.repo_path(vec!["refs", "heads", "topic"]).exists());
+
+    let path = repo.worktree().to_string_lossy().into_owned();
+    cli::switch(Some("topic"), None, false, false, Some(&path)).unwrap();
+    assert_eq!(repo.head_ref().unwrap().as_deref(), Some("refs/heads/topic"));
+    assert!(cli::switch(Some("HEAD~0"), None, false, false, Some(&path)).is_err());
+}
@@ -0,0 +1,120 @@
+use minit::repository::{AddOptions, CheckoutOptions, CommitOptions, Repository};
+use minit::hash::ObjectId;
+use minit::pathspec::Pathspec;
+use minit::cli;
+use std::fs;
+
+mod common;
+use common::fresh_repository;
+
+fn commit_file(repo: &Repository, file: &str, message: &str) -> ObjectId {
+    fs::write(repo.worktree().join(file), message).unwrap();
+    repo.add(&Pathspec::from_patterns(vec![file.to_string()]), &AddOptions::default()).unwrap();
+    repo.commit(Some(format!("{}\n", message).into_bytes()), &CommitOptions::default()).unwrap();
+    repo.resolve_ref("HEAD").unwrap()
+}
+
+fn messages(repo: &Repository, name: &str) -> Vec<String> {
+    repo.reflog(name).unwrap().into_iter().map(|entry| entry.message).collect()
+}
+
+#[test]
+fn test_ref_updates_are_logged() {
+    let repo = fresh_repository("reflog");
+    let first = commit_file(&repo, "a.txt", "First");
+    let second = commit_file(&repo, "a.txt", "Second\n\nwith a body");
+    repo.checkout("HEAD~", &CheckoutOptions { new_branch: Some(String::from("topic")), ..CheckoutOptions::default() }).unwrap();
+    repo.checkout("master", &CheckoutOptions::default()).unwrap();
+    repo.commit(None, &CommitOptions { amend: true, ..CommitOptions::default() }).unwrap();
+    let amended = repo.resolve_ref("HEAD").unwrap();
+    repo.create_branch("topic", "HEAD", true).unwrap();
+
+    assert_eq!(messages(&repo, "refs/heads/master"), vec![
+        "commit (initial): First",
+        "commit: Second",
+        "commit (amend): Second",
+    ]);
+    assert_eq!(messages(&repo, "HEAD"), vec![
+        "commit (initial): First",
+        "commit: Second",
+        "checkout: moving from master to topic",
+        "checkout: moving from topic to master",
+        "commit (amend): Second",
+    ]);
+    assert_eq!(messages(&repo, "refs/heads/topic"), vec!["branch: Created from HEAD~", "branch: Reset to HEAD"]);
+
+    let log = repo.reflog("refs/heads/master").unwrap();
+    assert!(log[0].old.is_null());
+    assert_eq!((log[0].new, log[1].old, log[1].new, log[2].old, log[2].new), (first, first, second, second, amended));
+    assert_eq!(log[0].identity.name, b"Minit");
+    let head = repo.reflog("HEAD").unwrap();
+    assert_eq!((head[2].old, head[2].new), (second, first));
+
+    // The revision syntax reads the logs
+    assert_eq!(repo.find_object("master@{1}", None, false).unwrap(), second);
+    assert_eq!(repo.find_object("@{-1}", None, false).unwrap(), amended);
+    assert_eq!(repo.find_object("HEAD@{2}", None, false).unwrap(), first);
+
+    // Deleting a branch drops its log
+    repo.delete_branch("topic", true).unwrap();
+    assert!(repo.reflog("refs/heads/topic").unwrap().is_empty());
+    assert!(!repo.repo_path(vec!["logs", "refs", "heads", "topic"]).exists());
+}
+
+#[test]
+fn test_log_all_ref_updates() {
+    let repo = fresh_repository("reflog_config");
+    let first = commit_file(&repo, "a.txt", "First");
+
+    // Only HEAD and branches by default, everything with `always`
+    repo.update_ref("refs/tags/v1", &first, None, "tag").unwrap();
+    assert!(repo.reflog("refs/tags/v1").unwrap().is_empty());
+    repo.set_config("core", "logallrefupdates", Some("always")).unwrap();
+    repo.update_ref("refs/tags/v2", &first, None, "tag").unwrap();
+    assert_eq!(messages(&repo, "refs/tags/v2"), vec!["tag"]);
+
+    // Turned off, only refs that already have a log keep getting entries
+    repo.set_config("core", "logallrefupdates", Some("false")).unwrap();
+    repo.update_ref("refs/heads/other", &first, None, "branch").unwrap();
+    assert!(repo.reflog("refs/heads/other").unwrap().is_empty());
+    let second = commit_file(&repo, "a.txt", "Second");
+    assert_eq!(messages(&repo, "refs/heads/master"), vec!["commit (initial): First", "commit: Second"]);
+    assert_eq!(repo.reflogs().unwrap(), vec!["HEAD", "refs/heads/master", "refs/tags/v2"]);
+
+    // Expiring and deleting entries
+    let entries = repo.reflog("HEAD").unwrap();
+    assert_eq!(repo.expire_reflog("HEAD", entries[1].identity.time).unwrap(), 0);
+    assert_eq!(repo.expire_reflog("HEAD", i64::MAX).unwrap(), 2);
+    assert!(repo.reflog("HEAD").unwrap().is_empty());
+    let deleted = repo.delete_reflog_entry("refs/heads/master", 1).unwrap();
+    assert_eq!(deleted.new, first);
+    let remaining = repo.reflog("refs/heads/master").unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].new, second);
+    assert!(repo.delete_reflog_entry("refs/heads/master", 1).is_err());
+}
+
+#[test]
+fn test_reflog_keeps_objects_alive() {
+    let repo = fresh_repository("reflog_gc");
+    commit_file(&repo, "a.txt", "First");
+    let original = commit_file(&repo, "a.txt", "Second");
+    repo.commit(Some(b"Amended\n".to_vec()), &CommitOptions { amend: true, ..CommitOptions::default() }).unwrap();
+
+    // Only the reflogs still name the commit that was amended away
+    assert!(repo.prune_loose().unwrap().is_empty());
+    let report = repo.fsck().unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert!(!report.to_string().contains("dangling"), "{}", report);
+
+    let path = repo.worktree().to_string_lossy().into_owned();
+    cli::gc(true, Some(&path)).unwrap();
+    assert_eq!(repo.find_object("HEAD@{1}", None, false).unwrap(), original);
+    assert!(repo.read_object(&original).is_ok());
+
+    // Once the log forgets it, so does gc
+    for name in repo.reflogs().unwrap() {
+        repo.expire_reflog(&name, i64::MAX).unwrap();
+    }
+    assert!(!repo.reachable_objects().unwrap().contains(&original));
+}
@@ -0,0 +1,134 @@
+use minit::repository::{AddOptions, CommitOptions, Repository};
+use minit::refs::{Expected, RefValue};
+use minit::hash::ObjectId;
+use minit::pathspec::Pathspec;
+use minit::error::Error;
+use std::fs;
+
+mod common;
+use common::fresh_repository;
+
+fn commit_file(repo: &Repository, file: &str, message: &str) -> ObjectId {
+    fs::write(repo.worktree().join(file), message).unwrap();
+    repo.add(&Pathspec::from_patterns(vec![file.to_string()]), &AddOptions::default()).unwrap();
+    repo.commit(Some(format!("{}\n", message).into_bytes()), &CommitOptions::default()).unwrap();
+    repo.resolve_ref("HEAD").unwrap()
+}
+
+fn refused<T: std::fmt::Debug>(result: minit::error::Result<T>) -> String {
+    match result {
+        Err(Error::Refused(reason)) => reason,
+        other => panic!("expected a refusal, got {:?}", other),
+    }
+}
+
+fn locks(repo: &Repository) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut dirs = vec![repo.minit_dir.clone()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir).unwrap().flatten() {
+            if entry.path().is_dir() {
+                dirs.push(entry.path());
+            } else if entry.path().extension().is_some_and(|extension| extension == "lock") {
+                found.push(entry.path().display().to_string());
+            }
+        }
+    }
+    found
+}
+
+#[test]
+fn test_transactions_are_all_or_nothing() {
+    let repo = fresh_repository("refs_transaction");
+    let first = commit_file(&repo, "a.txt", "First");
+    let second = commit_file(&repo, "a.txt", "Second");
+
+    // One stale expectation stops every update
+    let mut transaction = repo.transaction();
+    transaction
+        .update("refs/heads/new", RefValue::Object(first), Expected::Missing, "create")
+        .update("refs/heads/master", RefValue::Object(first), Expected::Value(first), "rewind");
+    let reason = refused(transaction.commit());
+    assert!(reason.contains("cannot lock ref 'refs/heads/master'"), "{}", reason);
+    assert!(repo.resolve_ref("refs/heads/new").is_err());
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), second);
+    assert!(locks(&repo).is_empty(), "{:?}", locks(&repo));
+
+    // Together they go through, and each is logged
+    let mut transaction = repo.transaction();
+    transaction
+        .update("refs/heads/new", RefValue::Object(first), Expected::Missing, "create")
+        .update("refs/heads/master", RefValue::Object(first), Expected::Value(second), "rewind")
+        .update("refs/tags/v1", RefValue::Object(second), Expected::Any, "tag");
+    transaction.commit().unwrap();
+    assert_eq!(repo.resolve_ref("refs/heads/new").unwrap(), first);
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), first);
+    assert_eq!(fs::read_to_string(repo.repo_path(vec!["refs", "tags", "v1"])).unwrap(), format!("{}\n", second));
+    assert_eq!(repo.reflog("HEAD").unwrap().last().unwrap().message, "rewind");
+    assert_eq!(repo.reflog("refs/heads/new").unwrap().last().unwrap().message, "create");
+
+    // A ref someone else has locked is left alone
+    fs::write(repo.repo_path(vec!["refs", "heads", "new.lock"]), b"").unwrap();
+    let mut transaction = repo.transaction();
+    transaction
+        .update("refs/heads/master", RefValue::Object(second), Expected::Value(first), "forward")
+        .delete("refs/heads/new", Expected::Value(first));
+    assert!(refused(transaction.commit()).contains("another process"));
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), first);
+    assert!(repo.repo_path(vec!["refs", "heads", "new.lock"]).is_file());
+    assert!(repo.branches().unwrap().iter().all(|(name, _)| name != "new.lock"));
+    fs::remove_file(repo.repo_path(vec!["refs", "heads", "new.lock"])).unwrap();
+
+    // Deletions and updates mix
+    let mut transaction = repo.transaction();
+    transaction
+        .update("refs/heads/master", RefValue::Object(second), Expected::Value(first), "forward")
+        .delete("refs/heads/new", Expected::Value(first));
+    transaction.commit().unwrap();
+    assert!(!repo.repo_path(vec!["refs", "heads", "new"]).exists());
+    assert!(!repo.repo_path(vec!["logs", "refs", "heads", "new"]).exists());
+    assert_eq!(repo.resolve_ref("HEAD").unwrap(), second);
+}
+
+#[test]
+fn test_transaction_refusals() {
+    let repo = fresh_repository("refs_refusals");
+    let first = commit_file(&repo, "a.txt", "First");
+
+    for name in ["master", "refs/heads/a..b", "refs/heads/x.lock", "refs/heads/", "ORIG_HEAD"] {
+        let mut transaction = repo.transaction();
+        transaction.update(name, RefValue::Object(first), Expected::Any, "bad");
+        assert!(refused(transaction.commit()).contains("not a valid ref name"), "{}", name);
+    }
+    let mut transaction = repo.transaction();
+    transaction
+        .update("refs/heads/twice", RefValue::Object(first), Expected::Any, "one")
+        .update("refs/heads/twice", RefValue::Object(first), Expected::Any, "two");
+    assert!(refused(transaction.commit()).contains("multiple updates"));
+
+    // A ref can't also be a directory of refs
+    let mut transaction = repo.transaction();
+    transaction.update("refs/heads/master/topic", RefValue::Object(first), Expected::Missing, "nested");
+    assert!(refused(transaction.commit()).contains("cannot lock ref"));
+    repo.update_ref("refs/heads/dir/topic", &first, None, "nested").unwrap();
+    let mut transaction = repo.transaction();
+    transaction.update("refs/heads/dir", RefValue::Object(first), Expected::Missing, "over");
+    assert!(refused(transaction.commit()).contains("there are refs under it"));
+    assert!(locks(&repo).is_empty(), "{:?}", locks(&repo));
+
+    // Tags are created, and never silently replaced
+    repo.create_tag("v1", "HEAD", false).unwrap();
+    assert_eq!(repo.resolve_ref("refs/tags/v1").unwrap(), first);
+    assert!(refused(repo.create_tag("v1", "HEAD", false)).contains("already exists"));
+    assert!(refused(repo.create_tag("bad..name", "HEAD", false)).contains("not a valid ref name"));
+
+    // An annotated tag that is refused leaves no tag object behind
+    let loose = || fs::read_dir(repo.repo_path(vec!["objects"])).unwrap().flatten()
+        .filter(|entry| entry.file_name().len() == 2)
+        .map(|entry| fs::read_dir(entry.path()).unwrap().count())
+        .sum::<usize>();
+    let before = loose();
+    assert!(refused(repo.create_tag("v1", "HEAD", true)).contains("already exists"));
+    assert!(refused(repo.create_tag("bad..name", "HEAD", true)).contains("not a valid ref name"));
+    assert_eq!(loose(), before);
+}
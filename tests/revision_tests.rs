@@ -0,0 +1,222 @@
+use minit::repository::{AddOptions, CheckoutOptions, CommitOptions, Repository};
+use minit::revision::{approxidate, PeelTarget, ReflogSelector, Revision};
+use minit::object::{Object, tag::Tag};
+use minit::hash::{HashAlgorithm, ObjectId};
+use minit::pathspec::Pathspec;
+use minit::cli::Format;
+use minit::error::Error;
+use std::fs;
+
+mod common;
+use common::fresh_repository;
+
+fn commit_file(repo: &Repository, file: &str, message: &str) -> ObjectId {
+    let path = repo.worktree().join(file);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, message).unwrap();
+    repo.add(&Pathspec::from_patterns(vec![file.to_string()]), &AddOptions::default()).unwrap();
+    repo.commit(Some(format!("{}\n", message).into_bytes()), &CommitOptions::default()).unwrap();
+    repo.resolve_ref("HEAD").unwrap()
+}
+
+fn resolve(repo: &Repository, revision: &str) -> ObjectId {
+    repo.find_object(revision, None, false).unwrap_or_else(|err| panic!("{}: {}", revision, err))
+}
+
+fn bad_position(revision: &str) -> usize {
+    match Revision::parse(revision) {
+        Err(Error::BadRevision(_, position, _)) => position,
+        other => panic!("expected {:?} not to parse, got {:?}", revision, other),
+    }
+}
+
+#[test]
+fn test_parse_revisions() {
+    let name = |name: &str| Box::new(Revision::Name(name.to_string()));
+    assert_eq!(Revision::parse("@").unwrap(), Revision::Name(String::from("HEAD")));
+    assert_eq!(Revision::parse("main~2^2").unwrap(), Revision::Parent(Box::new(Revision::Ancestor(name("main"), 2)), 2));
+    assert_eq!(Revision::parse("v1^{}").unwrap(), Revision::Peel(name("v1"), PeelTarget::Tags));
+    assert_eq!(Revision::parse("v1^{tree}:src/a.rs").unwrap(),
+        Revision::Path(Box::new(Revision::Peel(name("v1"), PeelTarget::Type(Format::Tree))), String::from("src/a.rs")));
+    assert_eq!(Revision::parse(":2:a.txt").unwrap(), Revision::Index(2, String::from("a.txt")));
+    assert_eq!(Revision::parse(":a:b").unwrap(), Revision::Index(0, String::from("a:b")));
+    assert_eq!(Revision::parse(":/fix: the bug").unwrap(), Revision::MessageSearch(String::from("fix: the bug")));
+    assert_eq!(Revision::parse("@{u}").unwrap(), Revision::Upstream(String::new()));
+    assert_eq!(Revision::parse("@{-2}^").unwrap(), Revision::Parent(Box::new(Revision::PreviousCheckout(2)), 1));
+    assert_eq!(Revision::parse("main@{3}").unwrap(), Revision::Reflog(String::from("main"), ReflogSelector::Nth(3)));
+    assert_eq!(Revision::parse_at("@{2.days.ago}", 1_000_000).unwrap(),
+        Revision::Reflog(String::new(), ReflogSelector::Date(1_000_000 - 2 * 86400)));
+
+    assert_eq!(approxidate("2024-05-01 12:30", 0), Some(1714566600));
+    assert_eq!(approxidate("1 hour 5 minutes ago", 10_000), Some(10_000 - 3900));
+    assert_eq!(approxidate("someday", 0), None);
+    assert_eq!(approxidate("2024-01-01 24:00", 0), None);
+    assert_eq!(approxidate("2024-01-01 12:60", 0), None);
+    assert_eq!(approxidate("2024-01-01 12:30:60", 0), None);
+    assert_eq!(approxidate("1 second ago", i64::MIN), None);
+
+    assert_eq!(bad_position("~1"), 0);
+    assert_eq!(bad_position("HEAD^{tre}"), 6);
+    assert_eq!(bad_position("HEAD@{nope}"), 6);
+    assert_eq!(bad_position("HEAD@{1"), 7);
+    assert_eq!(bad_position("main@{-1}"), 0);
+    assert_eq!(bad_position(":7:a"), 1);
+    assert_eq!(bad_position("HEAD@{99999999999999-01-01}"), 6);
+    assert_eq!(bad_position("HEAD@{2024-01-01 9999999999999999:00}"), 6);
+}
+
+#[test]
+fn test_resolve_revisions() {
+    let repo = fresh_repository("revision");
+    let first = commit_file(&repo, "src/a.txt", "First");
+    let second = commit_file(&repo, "b.txt", "Second fixes a bug");
+    repo.checkout("HEAD~", &CheckoutOptions { new_branch: Some(String::from("side")), ..CheckoutOptions::default() }).unwrap();
+    let side = commit_file(&repo, "c.txt", "Side");
+    repo.checkout("master", &CheckoutOptions::default()).unwrap();
+
+    // A merge, written by hand, with the side branch as its second parent
+    let Object::Commit(mut merge) = repo.read_object(&second).unwrap() else { unreachable!() };
+    merge.parents = vec![second, side];
+    merge.message = Some(b"Merge side\n".to_vec());
+    let merge = repo.write_object(Object::Commit(merge)).unwrap();
+    repo.update_ref("refs/heads/master", &merge, Some(&second), "commit (merge): Merge side").unwrap();
+
+    assert_eq!(resolve(&repo, "@"), merge);
+    assert_eq!(resolve(&repo, "HEAD^"), second);
+    assert_eq!(resolve(&repo, "HEAD^2"), side);
+    assert_eq!(resolve(&repo, "master^0"), merge);
+    assert_eq!(resolve(&repo, "HEAD~2"), first);
+    assert_eq!(resolve(&repo, "HEAD^2~1"), first);
+    assert_eq!(resolve(&repo, "refs/heads/side"), side);
+    assert!(matches!(repo.find_object("HEAD^3", None, false), Err(Error::UnknownRevision(..))));
+    assert!(matches!(repo.find_object("HEAD~3", None, false), Err(Error::UnknownRevision(..))));
+
+    // Trees, paths and the index
+    let Object::Commit(commit) = repo.read_object(&first).unwrap() else { unreachable!() };
+    assert_eq!(resolve(&repo, "HEAD~2^{tree}"), commit.tree);
+    assert_eq!(resolve(&repo, "HEAD~2:"), commit.tree);
+    let blob = resolve(&repo, "HEAD:src/a.txt");
+    assert!(matches!(repo.read_object(&blob).unwrap(), Object::Blob(data) if data == b"First"));
+    assert_eq!(resolve(&repo, ":src/a.txt"), blob);
+    assert_eq!(resolve(&repo, ":0:src/a.txt"), blob);
+    assert!(matches!(repo.find_object("HEAD:nope", None, false), Err(Error::PathNotFound(..))));
+    assert!(matches!(repo.find_object(":1:src/a.txt", None, false), Err(Error::UnknownRevision(..))));
+    assert!(matches!(repo.find_object(&format!("{}^{{commit}}", blob), None, false), Err(Error::WrongObjectType(..))));
+
+    // Tags peel through to what they point at
+    let tag = |object: ObjectId, kind: Format| repo.write_object(Object::Tag(Tag {
+        object,
+        kind,
+        name: String::from("v1"),
+        tagger: None,
+        extra_headers: Vec::new(),
+        message: Some(b"Release\n".to_vec()),
+    })).unwrap();
+    let inner = tag(second, Format::Commit);
+    let outer = tag(inner, Format::Tag);
+    repo.update_ref("refs/tags/v1", &outer, None, "tag").unwrap();
+    assert_eq!(resolve(&repo, "v1"), outer);
+    assert_eq!(resolve(&repo, "v1^{}"), second);
+    assert_eq!(resolve(&repo, "v1^{commit}"), second);
+    assert_eq!(resolve(&repo, "v1^{tag}"), outer);
+    assert_eq!(resolve(&repo, "v1~1"), first);
+    assert_eq!(resolve(&repo, "v1:b.txt"), resolve(&repo, "HEAD:b.txt"));
+
+    // Commit messages, youngest first
+    assert_eq!(resolve(&repo, ":/Merge"), merge);
+    assert_eq!(resolve(&repo, ":/fixes? a"), second);
+    assert!(matches!(repo.find_object(":/^nothing", None, false), Err(Error::UnknownRevision(..))));
+    assert!(matches!(repo.find_object(":/(", None, false), Err(Error::BadRevision(..))));
+}
+
+#[test]
+fn test_resolve_reflog_and_upstream() {
+    let repo = fresh_repository("revision_reflog");
+    let first = commit_file(&repo, "a.txt", "First");
+    let second = commit_file(&repo, "a.txt", "Second");
+    let null = ObjectId::null(HashAlgorithm::Sha1);
+    let log = |name: &str, lines: Vec<(ObjectId, ObjectId, i64, &str)>| {
+        let path = repo.repo_path([vec!["logs"], name.split('/').collect()].concat());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let text = lines.into_iter()
+            .map(|(old, new, time, message)| format!("{} {} Minit <minit@example.com> {} +0000\t{}\n", old, new, time, message))
+            .collect::<String>();
+        fs::write(path, text).unwrap();
+    };
+    log("refs/heads/master", vec![(null, first, 1000, "commit (initial): First"), (first, second, 2000, "commit: Second")]);
+    log("HEAD", vec![
+        (null, first, 1000, "commit (initial): First"),
+        (first, first, 1500, "checkout: moving from master to topic"),
+        (first, second, 1600, "checkout: moving from topic to master"),
+    ]);
+    repo.create_branch("topic", &first.to_hex(), false).unwrap();
+
+    assert_eq!(resolve(&repo, "master@{0}"), second);
+    assert_eq!(resolve(&repo, "master@{1}"), first);
+    assert_eq!(resolve(&repo, "@{1}"), first);
+    assert_eq!(resolve(&repo, "HEAD@{2}"), first);
+    assert_eq!(resolve(&repo, "master@{1}^{tree}"), resolve(&repo, "HEAD~^{tree}"));
+    assert!(matches!(repo.find_object("master@{2}", None, false), Err(Error::UnknownRevision(..))));
+    assert_eq!(resolve(&repo, "master@{1970-01-01 00:25}"), first);
+    assert_eq!(resolve(&repo, "master@{yesterday}"), second);
+    assert!(matches!(repo.find_object("master@{1970-01-01}", None, false), Err(Error::UnknownRevision(..))));
+
+    // The previous checkouts, newest first
+    assert_eq!(resolve(&repo, "@{-1}"), first);
+    assert_eq!(resolve(&repo, "@{-2}"), second);
+    assert!(matches!(repo.find_object("@{-3}", None, false), Err(Error::UnknownRevision(..))));
+
+    assert!(matches!(repo.find_object("@{upstream}", None, false), Err(Error::UnknownRevision(..))));
+    repo.set_upstream("master", "topic").unwrap();
+    assert_eq!(resolve(&repo, "@{u}"), first);
+    assert_eq!(resolve(&repo, "master@{UPSTREAM}~0"), first);
+}
+
+#[test]
+fn test_peel() {
+    let repo = fresh_repository("revision_peel");
+    let commit = commit_file(&repo, "dir/a.txt", "First");
+    let Object::Commit(parsed) = repo.read_object(&commit).unwrap() else { unreachable!() };
+    let tag = |object: ObjectId, kind: Format| repo.write_object(Object::Tag(Tag {
+        object,
+        kind,
+        name: String::from("v1"),
+        tagger: None,
+        extra_headers: Vec::new(),
+        message: None,
+    })).unwrap();
+    let mut chain = commit;
+    for depth in 0..4 {
+        chain = tag(chain, if depth == 0 { Format::Commit } else { Format::Tag });
+    }
+    repo.update_ref("refs/tags/v1", &chain, None, "tag").unwrap();
+
+    assert_eq!(repo.peel(&chain, None).unwrap(), commit);
+    assert_eq!(repo.peel(&chain, Some(Format::Tag)).unwrap(), chain);
+    assert_eq!(repo.peel(&chain, Some(Format::Tree)).unwrap(), parsed.tree);
+    assert_eq!(repo.find_object("v1", Some(Format::Tree), true).unwrap(), parsed.tree);
+    let blob = repo.peel_path(&chain, "dir/a.txt").unwrap();
+    assert_eq!(repo.find_object("v1:dir/a.txt", Some(Format::Blob), false).unwrap(), blob);
+    assert_eq!(repo.peel_path(&chain, "").unwrap(), parsed.tree);
+
+    // The type that was found is named, not just that the search failed
+    match repo.peel(&chain, Some(Format::Blob)) {
+        Err(Error::WrongObjectType(sha, expected, found)) => {
+            assert_eq!((sha, expected, found), (commit, Format::Blob, Format::Commit));
+        },
+        other => panic!("expected a wrong type, got {:?}", other),
+    }
+    assert!(matches!(repo.find_object("v1", Some(Format::Commit), false), Err(Error::WrongObjectType(_, Format::Commit, Format::Tag))));
+    assert!(matches!(repo.peel_path(&chain, "dir/a.txt/b"), Err(Error::WrongObjectType(sha, Format::Tree, Format::Blob)) if sha == blob));
+    assert!(matches!(repo.peel_path(&chain, "dir/b.txt"), Err(Error::PathNotFound(..))));
+    assert!(matches!(repo.find_object("nothing", None, false), Err(Error::ObjectNotDefined(_))));
+
+    // A damaged store can make a chain of tags loop
+    let missing = "1".repeat(40).parse::<ObjectId>().unwrap();
+    let first = tag(missing, Format::Tag);
+    let second = tag(first, Format::Tag);
+    let path = |sha: &ObjectId| repo.repo_path(vec!["objects", &sha.to_hex()[..2], &sha.to_hex()[2..]]);
+    fs::create_dir_all(path(&missing).parent().unwrap()).unwrap();
+    fs::copy(path(&second), path(&missing)).unwrap();
+    assert!(matches!(repo.peel(&first, Some(Format::Commit)), Err(Error::MalformedObject(_))));
+}
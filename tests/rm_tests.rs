@@ -0,0 +1,134 @@
+use minit::repository::{AddOptions, Repository, RmOptions};
+use minit::object::{Leaf, Object, commit::{Commit, Identity}};
+use minit::pathspec::Pathspec;
+use minit::error::Error;
+use std::fs;
+
+mod common;
+use common::{empty_repository, TestRepository};
+
+/// A repository with a.txt, dir/b.txt and dir/sub/c.txt staged
+fn fresh_repository(name: &str) -> TestRepository {
+    let repo = empty_repository(name);
+    let path = repo.worktree().to_path_buf();
+    fs::create_dir_all(path.join("dir/sub")).unwrap();
+    fs::write(path.join("a.txt"), b"a\n").unwrap();
+    fs::write(path.join("dir/b.txt"), b"b\n").unwrap();
+    fs::write(path.join("dir/sub/c.txt"), b"c\n").unwrap();
+    repo.add(&Pathspec::default(), &AddOptions { all: true, ..AddOptions::default() }).unwrap();
+    repo
+}
+
+fn pathspec(specs: &[&str]) -> Pathspec {
+    Pathspec::from_patterns(specs.iter().map(|spec| spec.to_string()).collect())
+}
+
+fn staged(repo: &Repository) -> Vec<String> {
+    repo.read_index().unwrap().entries.into_iter().map(|entry| entry.path).collect()
+}
+
+/// Commit `a.txt` alone so that HEAD has something to compare against
+fn commit_a(repo: &Repository) {
+    let sha = repo.read_index().unwrap().get("a.txt").unwrap().sha;
+    let tree = repo.write_object(Object::Tree(vec![Leaf::new(String::from("100644"), String::from("a.txt"), sha)])).unwrap();
+    let identity = Identity::new("Minit", "minit@example.com", 1700000000, 0);
+    let commit = repo.write_object(Object::Commit(Commit {
+        tree,
+        parents: Vec::new(),
+        author: identity.clone(),
+        committer: identity,
+        encoding: None,
+        extra_headers: Vec::new(),
+        gpgsig: None,
+        message: Some(b"Add a\n".to_vec()),
+    })).unwrap();
+    fs::write(repo.repo_path(vec!["refs", "heads", "master"]), format!("{}\n", commit)).unwrap();
+}
+
+fn refused(result: Result<Vec<String>, Error>) -> String {
+    match result {
+        Err(Error::Refused(reason)) => reason,
+        other => panic!("expected a refusal, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_rm() {
+    let repo = fresh_repository("rm");
+    let worktree = repo.worktree().to_path_buf();
+    commit_a(&repo);
+
+    // Directories need -r, and staged but uncommitted files need -f
+    let reason = refused(repo.rm(&pathspec(&["dir"]), &RmOptions::default()));
+    assert_eq!(reason, "not removing 'dir' recursively without -r");
+    let recursive = RmOptions { recursive: true, ..RmOptions::default() };
+    let reason = refused(repo.rm(&pathspec(&["dir"]), &recursive));
+    assert_eq!(reason, "'dir/b.txt' has changes staged in the index");
+    assert!(matches!(repo.rm(&pathspec(&["nothing"]), &recursive), Err(Error::PathspecNoMatch(_))));
+
+    // --cached keeps the files
+    let cached = RmOptions { cached: true, recursive: true, ..RmOptions::default() };
+    assert_eq!(repo.rm(&pathspec(&["dir"]), &cached).unwrap(), vec!["dir/b.txt", "dir/sub/c.txt"]);
+    assert_eq!(staged(&repo), vec!["a.txt"]);
+    assert!(worktree.join("dir/sub/c.txt").is_file());
+
+    // Local edits are protected unless forced
+    fs::write(worktree.join("a.txt"), b"edited\n").unwrap();
+    let reason = refused(repo.rm(&pathspec(&["a.txt"]), &RmOptions::default()));
+    assert_eq!(reason, "'a.txt' has local modifications");
+    assert_eq!(repo.rm(&pathspec(&["a.txt"]), &RmOptions { cached: true, ..RmOptions::default() }).unwrap(), vec!["a.txt"]);
+    assert!(worktree.join("a.txt").is_file());
+
+    // Content that is neither in HEAD nor in the worktree is protected even with --cached
+    repo.add(&pathspec(&["a.txt", "dir"]), &AddOptions::default()).unwrap();
+    fs::write(worktree.join("a.txt"), b"edited again\n").unwrap();
+    let reason = refused(repo.rm(&pathspec(&["a.txt"]), &RmOptions { cached: true, ..RmOptions::default() }));
+    assert_eq!(reason, "'a.txt' has staged content different from both the file and the HEAD");
+
+    let force = RmOptions { force: true, recursive: true, ..RmOptions::default() };
+    repo.rm(&pathspec(&["a.txt", "dir"]), &force).unwrap();
+    assert!(staged(&repo).is_empty());
+    assert!(!worktree.join("a.txt").exists());
+    assert!(!worktree.join("dir").exists());
+}
+
+#[test]
+fn test_mv() {
+    let repo = fresh_repository("mv");
+    let worktree = repo.worktree().to_path_buf();
+    let sha = repo.read_index().unwrap().get("a.txt").unwrap().sha;
+
+    assert_eq!(repo.mv("a.txt", "dir/sub").unwrap(), vec![(String::from("a.txt"), String::from("dir/sub/a.txt"))]);
+    assert!(worktree.join("dir/sub/a.txt").is_file());
+    assert!(!worktree.join("a.txt").exists());
+    assert_eq!(repo.read_index().unwrap().get("dir/sub/a.txt").unwrap().sha, sha);
+
+    // Whole directories move, and the emptied parent goes away
+    fs::write(worktree.join("dir/untracked"), b"").unwrap();
+    repo.mv("dir/sub", "moved").unwrap();
+    assert_eq!(staged(&repo), vec!["dir/b.txt", "moved/a.txt", "moved/c.txt"]);
+    assert!(worktree.join("moved/c.txt").is_file());
+    repo.mv("dir", "renamed").unwrap();
+    assert_eq!(staged(&repo), vec!["moved/a.txt", "moved/c.txt", "renamed/b.txt"]);
+    assert!(worktree.join("renamed/untracked").is_file());
+
+    for (source, destination, expected) in [
+        ("missing", "x", "bad source"),
+        ("renamed/untracked", "x", "bad source"),
+        ("moved", "moved/inner", "can not move directory into itself"),
+        ("moved/a.txt", "renamed/b.txt", "destination exists"),
+        ("moved/a.txt", "nowhere/a.txt", "destination directory does not exist"),
+    ] {
+        match repo.mv(source, destination) {
+            Err(Error::Refused(reason)) => assert!(reason.starts_with(expected), "{}", reason),
+            other => panic!("expected {} for {} -> {}, got {:?}", expected, source, destination, other),
+        }
+    }
+    assert_eq!(staged(&repo), vec!["moved/a.txt", "moved/c.txt", "renamed/b.txt"]);
+
+    // Nothing moves if the index can't be written
+    fs::write(repo.repo_path(vec!["index.lock"]), b"").unwrap();
+    assert!(repo.mv("moved", "elsewhere").is_err());
+    assert!(worktree.join("moved/a.txt").is_file());
+    assert!(!worktree.join("elsewhere").exists());
+}
@@ -1,8 +1,5 @@
 use minit::repository::Repository;
 use minit::error::Result;
-use minit::object::{
-    Object
-};
 use minit::cli;
 use minit::cli::Format;
 use flate2::read::ZlibDecoder;
@@ -22,7 +19,7 @@ fn test_init() {
     if path.exists() && path.is_dir() {
         _ = fs::remove_dir_all(path);
     }
-    _ = Repository::create(path).unwrap();
+    _ = Repository::create(path, None).unwrap();
     let mut builder = String::from("");
     let tree = ls_tree(path, &mut builder);
     insta::assert_snapshot!("init directory tree", tree.unwrap());
@@ -35,7 +32,7 @@ fn test_init() {
 
 #[test]
 fn test_full_functionality() {
-    let commit_content = "tree 29ff16c9c14e2652b22f8b78bb08a5a07930c147
+    let _commit_content = "tree 29ff16c9c14e2652b22f8b78bb08a5a07930c147
 author Jonathan Min <test@gmail.com> 1527025023 +0200
 committer Jonathan Min <test@gmail.com> 1527025044 +0200
 gpgsig -----BEGIN PGP SIGNATURE-----
@@ -61,22 +58,22 @@ The first commit ever!";
     if path.exists() && path.is_dir() {
         _ = fs::remove_dir_all(path);
     }
-    _ = Repository::create(path).unwrap();
+    _ = Repository::create(path, None).unwrap();
     let mut builder = String::from("");
     let helloworld_path = "snapshots/working_dir/helloworld.txt";
-    let mut helloworld_file = OpenOptions::new().create(true).write(true).open(helloworld_path).unwrap();
-    helloworld_file.write(b"helloworld").unwrap();
+    let mut helloworld_file = OpenOptions::new().create(true).write(true).truncate(true).open(helloworld_path).unwrap();
+    helloworld_file.write_all(b"helloworld").unwrap();
 
     let foobar_path = "snapshots/working_dir/foobar.txt";
-    let mut foobar_file = OpenOptions::new().create(true).write(true).open(foobar_path).unwrap();
-    foobar_file.write(b"foobar").unwrap();
+    let mut foobar_file = OpenOptions::new().create(true).write(true).truncate(true).open(foobar_path).unwrap();
+    foobar_file.write_all(b"foobar").unwrap();
 
     let helloworld_path = "snapshots/working_dir/helloworld.txt";
-    let mut helloworld_file = OpenOptions::new().create(true).write(true).open(helloworld_path).unwrap();
-    helloworld_file.write(b"helloworld").unwrap();
+    let mut helloworld_file = OpenOptions::new().create(true).write(true).truncate(true).open(helloworld_path).unwrap();
+    helloworld_file.write_all(b"helloworld").unwrap();
 
-    let helloworld_sha = cli::hash_object(Format::Blob, true, helloworld_path);
-    let foobar_sha = cli::hash_object(Format::Blob, true, foobar_path);
+    let helloworld_sha = cli::hash_object(Format::Blob, true, helloworld_path).unwrap().to_string();
+    let foobar_sha = cli::hash_object(Format::Blob, true, foobar_path).unwrap().to_string();
 
     // let tree_content = format!("{}\n", helloworld_sha, foobar_sha);
     println!("{}", foobar_sha);
@@ -116,7 +113,7 @@ fn standardize_config(path: &Path) -> String {
             let mut key_values = subsection.into_iter()
                 .map(|(k, v)| format!("{}: {}", k, v.unwrap_or(String::from(""))))
                 .collect::<Vec<String>>();
-            key_values.sort_by(|a, b| a.cmp(b));
+            key_values.sort();
             format!("[{}]\n{}", k, key_values.join("\n"))
         })
     .collect::<Vec<String>>()
@@ -127,8 +124,11 @@ fn standardize_config(path: &Path) -> String {
 fn ls_tree<'a>(path: &Path, builder: &'a mut String) -> Result<&'a mut String> {
     if path.is_dir() {
         *builder = builder.clone() + "directory: " + path.to_str().unwrap() + "\n\n";
-        for path in path.read_dir()? {
-            let new_path = path?.path();
+        let mut entries = path.read_dir()?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+        for new_path in entries {
             *builder = ls_tree(&new_path, builder)?.clone();
         }
         return Ok(builder);
@@ -142,10 +142,10 @@ fn ls_tree<'a>(path: &Path, builder: &'a mut String) -> Result<&'a mut String> {
                 &content + 
                 "\n<<<<<\n\n";
         } else {
-            let content = match std::fs::read_to_string(&path) {
+            let content = match std::fs::read_to_string(path) {
                 Ok(content) => content,
                 Err(..) => {
-                    let mut file = OpenOptions::new().read(true).open(&path)?;
+                    let mut file = OpenOptions::new().read(true).open(path)?;
                     let mut buf: Vec<u8> = Vec::new();
                     file.read_to_end(&mut buf)?;
 
@@ -164,6 +164,6 @@ fn ls_tree<'a>(path: &Path, builder: &'a mut String) -> Result<&'a mut String> {
         return Ok(builder);
     }
     
-    return Ok(builder)
-} 
+    Ok(builder)
+}
 
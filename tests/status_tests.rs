@@ -0,0 +1,140 @@
+use minit::repository::{AddOptions, CommitOptions, Repository};
+use minit::hash::HashAlgorithm;
+use minit::pathspec::Pathspec;
+use minit::status::{Change, UntrackedFiles};
+use std::fs;
+
+mod common;
+use common::fresh_repository;
+
+fn add(repo: &Repository, paths: &[&str]) {
+    let pathspec = Pathspec::from_patterns(paths.iter().map(|path| path.to_string()).collect());
+    repo.add(&pathspec, &AddOptions::default()).unwrap();
+}
+
+fn short(repo: &Repository) -> String {
+    repo.status(UntrackedFiles::Normal).unwrap().short_format(false)
+}
+
+#[test]
+fn test_status() {
+    let repo = fresh_repository("status");
+    let worktree = repo.worktree().to_path_buf();
+    let status = repo.status(UntrackedFiles::Normal).unwrap();
+    assert!(status.is_clean());
+    assert_eq!(status.short_format(true), "## No commits yet on master\n");
+
+    fs::create_dir_all(worktree.join("dir/sub")).unwrap();
+    fs::create_dir_all(worktree.join("build")).unwrap();
+    fs::create_dir_all(worktree.join("loose/deeper")).unwrap();
+    fs::write(worktree.join("a.txt"), b"a\n").unwrap();
+    fs::write(worktree.join("gone.txt"), b"gone\n").unwrap();
+    fs::write(worktree.join("dir/b.txt"), b"b\n").unwrap();
+    fs::write(worktree.join("dir/sub/c.txt"), b"c\n").unwrap();
+    fs::write(worktree.join("build/out.o"), b"").unwrap();
+    fs::write(worktree.join("loose/deeper/x"), b"").unwrap();
+    fs::write(worktree.join("debug.log"), b"").unwrap();
+    fs::write(worktree.join(".gitignore"), b"*.log\nbuild/\n").unwrap();
+    add(&repo, &["a.txt", "gone.txt", "dir", ".gitignore"]);
+    repo.commit(Some(b"First\n".to_vec()), &CommitOptions::default()).unwrap();
+    assert_eq!(short(&repo), "?? loose/\n");
+
+    // Staged, unstaged and both, against HEAD and the worktree
+    fs::write(worktree.join("a.txt"), b"staged\n").unwrap();
+    add(&repo, &["a.txt"]);
+    fs::write(worktree.join("a.txt"), b"staged and then edited\n").unwrap();
+    fs::write(worktree.join("dir/b.txt"), b"edited\n").unwrap();
+    fs::remove_file(worktree.join("dir/sub/c.txt")).unwrap();
+    fs::write(worktree.join("dir/new.txt"), b"new\n").unwrap();
+    add(&repo, &["dir/new.txt"]);
+    repo.rm(&Pathspec::from_patterns(vec![String::from("gone.txt")]), &Default::default()).unwrap();
+    fs::write(worktree.join("dir/untracked"), b"").unwrap();
+
+    let status = repo.status(UntrackedFiles::Normal).unwrap();
+    assert_eq!(status.branch.as_deref(), Some("master"));
+    assert_eq!(status.short_format(false), "\
+MM a.txt
+ M dir/b.txt
+A  dir/new.txt
+ D dir/sub/c.txt
+D  gone.txt
+?? dir/untracked
+?? loose/
+");
+    let entry = status.entries.iter().find(|entry| entry.path == "dir/new.txt").unwrap();
+    assert_eq!(entry.staged, Some(Change::Added));
+    assert_eq!(entry.head, None);
+    assert_eq!(entry.index.unwrap().0, 0o100644);
+    let all = repo.status(UntrackedFiles::All).unwrap();
+    assert_eq!(all.untracked, vec!["dir/untracked", "loose/deeper/x"]);
+    assert!(repo.status(UntrackedFiles::No).unwrap().untracked.is_empty());
+
+    let long = status.long_format();
+    assert!(long.starts_with("On branch master\n\nChanges to be committed:\n\tmodified:   a.txt\n\tnew file:   dir/new.txt\n\tdeleted:    gone.txt\n"), "{}", long);
+    assert!(long.contains("\nChanges not staged for commit:\n\tmodified:   a.txt\n\tmodified:   dir/b.txt\n\tdeleted:    dir/sub/c.txt\n"), "{}", long);
+    assert!(long.ends_with("\nUntracked files:\n\tdir/untracked\n\tloose/\n"), "{}", long);
+
+    let v2 = status.porcelain_v2(true, HashAlgorithm::Sha1);
+    let mut lines = v2.lines();
+    assert_eq!(lines.next().unwrap(), format!("# branch.oid {}", status.head.unwrap()));
+    assert_eq!(lines.next().unwrap(), "# branch.head master");
+    assert_eq!(lines.next().unwrap(), format!(
+        "1 MM N... 100644 100644 100644 {} {} a.txt",
+        entry_sha(&status, "a.txt", true), entry_sha(&status, "a.txt", false),
+    ));
+    assert!(v2.contains("\n1 D. N... 100644 000000 000000 "), "{}", v2);
+    assert!(v2.ends_with("\n? dir/untracked\n? loose/\n"), "{}", v2);
+}
+
+fn entry_sha(status: &minit::status::Status, path: &str, head: bool) -> String {
+    let entry = status.entries.iter().find(|entry| entry.path == path).unwrap();
+    let version = if head { entry.head } else { entry.index };
+    version.unwrap().1.to_string()
+}
+
+#[test]
+fn test_status_stat_refresh_and_conflicts() {
+    let repo = fresh_repository("status_refresh");
+    let worktree = repo.worktree().to_path_buf();
+    fs::write(worktree.join("a.txt"), b"a\n").unwrap();
+    add(&repo, &["a.txt"]);
+    let head = repo.commit(Some(b"First\n".to_vec()), &CommitOptions::default()).unwrap();
+
+    // Rewriting the same content changes the stat data but not the status, and the
+    // index picks up the new stat data
+    let before = repo.read_index().unwrap().get("a.txt").unwrap().clone();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    fs::remove_file(worktree.join("a.txt")).unwrap();
+    fs::write(worktree.join("a.txt"), b"a\n").unwrap();
+    let metadata = fs::symlink_metadata(worktree.join("a.txt")).unwrap();
+    assert!(!before.stat_matches(&metadata));
+    assert_eq!(short(&repo), "");
+    assert!(repo.read_index().unwrap().get("a.txt").unwrap().stat_matches(&metadata));
+
+    // Intent to add shows as added in the worktree only
+    fs::write(worktree.join("later.txt"), b"").unwrap();
+    repo.add(&Pathspec::from_patterns(vec![String::from("later.txt")]), &AddOptions { intent_to_add: true, ..AddOptions::default() }).unwrap();
+    assert_eq!(short(&repo), " A later.txt\n");
+
+    // Stages are spelled out as git does
+    let mut index = repo.read_index().unwrap();
+    let ours = index.get("a.txt").unwrap().clone();
+    index.remove("a.txt");
+    for stage in [1, 2] {
+        let mut entry = ours.clone();
+        entry.stage = stage;
+        index.add(entry);
+    }
+    repo.write_index(&index).unwrap();
+    let status = repo.status(UntrackedFiles::Normal).unwrap();
+    assert_eq!(status.short_format(false), "DU a.txt\n A later.txt\n");
+    assert!(status.long_format().contains("\nUnmerged paths:\n\tdeleted by us:   a.txt\n"));
+    assert!(status.porcelain_v2(false, HashAlgorithm::Sha1).starts_with(&format!("u DU N... 100644 100644 000000 100644 {} {} ", ours.sha, ours.sha)));
+
+    // Detached HEAD
+    fs::write(repo.repo_path(vec!["HEAD"]), format!("{}\n", head)).unwrap();
+    let status = repo.status(UntrackedFiles::Normal).unwrap();
+    assert_eq!(status.branch, None);
+    assert!(status.long_format().starts_with(&format!("HEAD detached at {}\n", &head.to_hex()[0..7])));
+    assert!(status.short_format(true).starts_with("## HEAD (no branch)\n"));
+}